@@ -0,0 +1,21 @@
+module Main (main) where
+
+import Data.Char (isAlpha, toLower)
+import Data.List (sortBy)
+import Data.Ord (comparing, Down(..))
+import qualified Data.Map.Strict as Map
+import System.Environment (getArgs)
+
+main :: IO ()
+main = do
+  [path] <- getArgs
+  counts <- countWords . tokenize <$> readFile path
+  mapM_ print (take 20 (sortBy (comparing (Down . snd)) (Map.toList counts)))
+
+countWords :: [String] -> Map.Map String Int
+countWords = foldr bump Map.empty
+  where
+    bump w = Map.insertWith (+) w 1
+
+tokenize :: String -> [String]
+tokenize = words . map (\c -> if isAlpha c then toLower c else ' ')
@@ -0,0 +1,131 @@
+use metric::Absolute;
+use parser::{ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree};
+use source::SrcSpan;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Writes `prof` in the callgrind format, for KCachegrind and QCachegrind.
+///
+/// Each cost centre is a function named `Module.cost_centre`, in the file
+/// and at the line of its SRC span, `???` at line 0 without one. Every node
+/// of the cost centre tree adds its individual costs to its function, and
+/// its calls to its children, with their entries and inherited costs. The
+/// events are `time` in microseconds and `alloc` in bytes, followed by
+/// `ticks` and `bytes` when the profile has those columns. Call sites are
+/// unknown: calls are made from the line of the caller.
+pub fn write_callgrind<'a, W: Write>(out: &mut W, prof: &GHCProf<'a>) -> io::Result<()> {
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    let mut writer = Callgrind {
+        costs: Absolute::of(&prof.header),
+        ticks: tree.value.ticks.is_some(),
+        bytes: tree.value.bytes.is_some(),
+        files: HashMap::new(),
+        functions: HashMap::new(),
+    };
+    let mut events = vec!["time", "alloc"];
+    if writer.ticks {
+        events.push("ticks");
+    }
+    if writer.bytes {
+        events.push("bytes");
+    }
+
+    writeln!(out, "# callgrind format")?;
+    writeln!(out, "version: 1")?;
+    writeln!(out, "creator: profiv")?;
+    writeln!(out, "cmd: {}", prof.header.program.trim())?;
+    writeln!(out, "positions: line")?;
+    writeln!(out, "event: time : Time (microseconds)")?;
+    writeln!(out, "event: alloc : Allocation (bytes)")?;
+    if writer.ticks {
+        writeln!(out, "event: ticks : Ticks")?;
+    }
+    if writer.bytes {
+        writeln!(out, "event: bytes : Bytes")?;
+    }
+    writeln!(out, "events: {}", events.join(" "))?;
+    writeln!(out, "summary: {}", costs(&writer.inherited(tree)))?;
+    writer.write_node(out, tree)
+}
+
+struct Callgrind {
+    costs: Absolute,
+    /// Whether the profile has the `ticks` and `bytes` columns, and so
+    /// events.
+    ticks: bool,
+    bytes: bool,
+    /// The ids of the files and functions named so far.
+    files: HashMap<String, usize>,
+    functions: HashMap<String, usize>,
+}
+
+impl Callgrind {
+    fn write_node<'a, W: Write>(&mut self, out: &mut W, tree: &RoseTree<ExtendedSummaryLine<'a>>) -> io::Result<()> {
+        let line = &tree.value;
+        let (file, at) = position(line);
+        writeln!(out)?;
+        writeln!(out, "fl={}", name(&mut self.files, &file))?;
+        writeln!(out, "fn={}", name(&mut self.functions, &function(line)))?;
+        writeln!(out, "{} {}", at, costs(&self.individual(line)))?;
+        for t in &tree.sub_forest {
+            let (callee_file, callee_at) = position(&t.value);
+            writeln!(out, "cfi={}", name(&mut self.files, &callee_file))?;
+            writeln!(out, "cfn={}", name(&mut self.functions, &function(&t.value)))?;
+            writeln!(out, "calls={} {}", t.value.entries, callee_at)?;
+            writeln!(out, "{} {}", at, costs(&self.inherited(t)))?;
+        }
+        for t in &tree.sub_forest {
+            self.write_node(out, t)?;
+        }
+        Ok(())
+    }
+
+    fn individual<'a>(&self, line: &ExtendedSummaryLine<'a>) -> Vec<u64> {
+        let mut costs = vec![self.costs.time(line).round() as u64, self.costs.alloc(line).round() as u64];
+        if self.ticks {
+            costs.push(line.ticks.unwrap_or(0));
+        }
+        if self.bytes {
+            costs.push(line.bytes.unwrap_or(0));
+        }
+        costs
+    }
+
+    /// The individual costs of the nodes of `tree` added up, so that they
+    /// match those of its functions exactly.
+    fn inherited<'a>(&self, tree: &RoseTree<ExtendedSummaryLine<'a>>) -> Vec<u64> {
+        let mut costs = self.individual(&tree.value);
+        for t in &tree.sub_forest {
+            for (sum, cost) in costs.iter_mut().zip(self.inherited(t)) {
+                *sum += cost;
+            }
+        }
+        costs
+    }
+}
+
+fn function<'a>(line: &ExtendedSummaryLine<'a>) -> String {
+    format!("{}.{}", line.module, line.cost_centre)
+}
+
+/// The file and line of a cost centre.
+fn position<'a>(line: &ExtendedSummaryLine<'a>) -> (String, usize) {
+    match line.src.and_then(SrcSpan::parse) {
+        Some(span) => (span.file.to_string(), span.start_line),
+        None => ("???".to_string(), 0),
+    }
+}
+
+/// `(id) name` the first time, then `(id)` alone.
+fn name(ids: &mut HashMap<String, usize>, name: &str) -> String {
+    if let Some(id) = ids.get(name) {
+        return format!("({})", id);
+    }
+    let id = ids.len() + 1;
+    ids.insert(name.to_string(), id);
+    format!("({}) {}", id, name)
+}
+
+fn costs(costs: &[u64]) -> String {
+    costs.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,53 @@
+use parser::{ExtendedSummaryLine, RoseTree};
+
+/// Identifies a node of the cost centre tree by the cost centres, and their
+/// modules, on the way from the root to it. Unlike the `no` column, it is
+/// the same in every profile of a program.
+pub type CallPath<'a> = Vec<(&'a str, &'a str)>;
+
+/// The call path of the node numbered `no`, if any.
+pub fn call_path<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, no: u32) -> Option<CallPath<'a>> {
+    let step = (tree.value.cost_centre, tree.value.module);
+    if tree.value.no == no {
+        return Some(vec![step]);
+    }
+    for t in &tree.sub_forest {
+        if let Some(mut path) = call_path(t, no) {
+            path.insert(0, step);
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// The nodes along `path`, starting from the root, for as long as `tree`
+/// has them. When the whole path exists the last node is the one it
+/// identifies.
+pub fn follow<'t, 'a>(tree: &'t RoseTree<ExtendedSummaryLine<'a>>,
+                      path: &[(&str, &str)])
+                      -> Vec<&'t RoseTree<ExtendedSummaryLine<'a>>> {
+    let mut nodes = Vec::new();
+    let mut steps = path.iter();
+    match steps.next() {
+        Some(&(cc, module)) if tree.value.cost_centre == cc && tree.value.module == module => {
+            nodes.push(tree)
+        }
+        _ => return nodes,
+    }
+    let mut node = tree;
+    for &(cc, module) in steps {
+        match node.sub_forest.iter().find(|t| t.value.cost_centre == cc && t.value.module == module) {
+            Some(child) => {
+                nodes.push(child);
+                node = child;
+            }
+            None => break,
+        }
+    }
+    nodes
+}
+
+/// `path` as `MAIN.MAIN > Main.CAF > Main.main`.
+pub fn display(path: &[(&str, &str)]) -> String {
+    path.iter().map(|&(cc, module)| format!("{}.{}", module, cc)).collect::<Vec<_>>().join(" > ")
+}
@@ -0,0 +1,481 @@
+use callpath::{display, CallPath};
+use metric::{Absolute, Metric};
+use parser::{ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree};
+use std::collections::HashMap;
+use std::f64;
+use std::io::{self, Write};
+
+/// How much worse than the baseline a profile is allowed to get.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    /// Patterns on `Module.cost_centre`, with `*` and `?` wildcards. Only
+    /// the cost centres matching one of them are checked, all of them when
+    /// there are none.
+    pub scope: Vec<String>,
+    /// The largest increase allowed, in percent of the baseline cost.
+    pub max_time_increase: Option<f32>,
+    pub max_alloc_increase: Option<f32>,
+    /// Cost centres under this percentage of the total in both profiles are
+    /// not checked: their costs are mostly noise.
+    pub min_cost: f32,
+}
+
+impl Default for Budget {
+    fn default() -> Budget {
+        Budget { scope: Vec::new(), max_time_increase: None, max_alloc_increase: None, min_cost: 1.0 }
+    }
+}
+
+impl Budget {
+    fn limit(&self, metric: Metric) -> Option<f32> {
+        match metric {
+            Metric::Time => self.max_time_increase,
+            Metric::Alloc => self.max_alloc_increase,
+        }
+    }
+
+    fn in_scope(&self, cost_centre: &str, module: &str) -> bool {
+        let name = format!("{}.{}", module, cost_centre);
+        self.scope.is_empty() || self.scope.iter().any(|p| glob_matches(p, &name))
+    }
+}
+
+/// What a comparison is about: a node of the cost centre tree, with the
+/// inherited costs of its subtree, or a cost centre with its individual
+/// costs wherever it is called from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Granularity {
+    CallPath,
+    CostCentre,
+}
+
+/// The cost of one call path or cost centre, before and after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub granularity: Granularity,
+    /// e.g. `MAIN.MAIN > Main.CAF > Main.main`, or `Main.main`.
+    pub name: String,
+    pub metric: Metric,
+    /// In microseconds for time and in bytes for alloc.
+    pub baseline: f64,
+    pub current: f64,
+    /// The largest increase allowed, in percent.
+    pub limit: f32,
+}
+
+impl Comparison {
+    /// The increase over the baseline, in percent: infinite for a cost
+    /// centre which costs nothing in the baseline.
+    pub fn increase(&self) -> f64 {
+        if self.baseline > 0.0 {
+            (self.current - self.baseline) * 100.0 / self.baseline
+        } else if self.current > 0.0 {
+            f64::INFINITY
+        } else {
+            0.0
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.increase() > self.limit as f64
+    }
+}
+
+/// Compares the call paths, then the cost centres, of `current` in scope
+/// with the same ones in `baseline`, for each metric with a limit. They
+/// come in the order of the cost centre tree of `current`, followed by
+/// those only in `baseline`, which cost nothing now.
+pub fn compare<'a>(baseline: &GHCProf<'a>, current: &GHCProf<'a>, budget: &Budget) -> Vec<Comparison> {
+    let metrics: Vec<(Metric, f32)> = [Metric::Time, Metric::Alloc]
+        .iter()
+        .filter_map(|&m| budget.limit(m).map(|limit| (m, limit)))
+        .collect();
+    let before = Costs::of(baseline);
+    let after = Costs::of(current);
+    let mut comparisons = Vec::new();
+    let mut check = |granularity: Granularity, name: &str, old: (f64, f64), new: (f64, f64)| {
+        for &(metric, limit) in &metrics {
+            let pick = |costs: (f64, f64)| match metric {
+                Metric::Time => costs.0,
+                Metric::Alloc => costs.1,
+            };
+            let (baseline, current) = (pick(old), pick(new));
+            let min_cost = budget.min_cost as f64;
+            if before.perc(metric, baseline) < min_cost && after.perc(metric, current) < min_cost {
+                continue;
+            }
+            comparisons.push(Comparison {
+                granularity,
+                name: name.to_string(),
+                metric,
+                baseline,
+                current,
+                limit,
+            });
+        }
+    };
+
+    let removed_paths = before.paths.iter().filter(|p| !after.inherited.contains_key(*p));
+    for path in after.paths.iter().chain(removed_paths) {
+        let (cc, module) = path[path.len() - 1];
+        if budget.in_scope(cc, module) {
+            let old = before.inherited.get(path).cloned().unwrap_or((0.0, 0.0));
+            let new = after.inherited.get(path).cloned().unwrap_or((0.0, 0.0));
+            check(Granularity::CallPath, &display(path), old, new);
+        }
+    }
+    let removed_cost_centres = before.cost_centres.iter().filter(|k| !after.individual.contains_key(*k));
+    for &(cc, module) in after.cost_centres.iter().chain(removed_cost_centres) {
+        if budget.in_scope(cc, module) {
+            let old = before.individual.get(&(cc, module)).cloned().unwrap_or((0.0, 0.0));
+            let new = after.individual.get(&(cc, module)).cloned().unwrap_or((0.0, 0.0));
+            check(Granularity::CostCentre, &format!("{}.{}", module, cc), old, new);
+        }
+    }
+    comparisons
+}
+
+/// The absolute costs, as (microseconds, bytes), of a profile.
+struct Costs<'a> {
+    absolute: Absolute,
+    /// The call paths in the order of the tree.
+    paths: Vec<CallPath<'a>>,
+    inherited: HashMap<CallPath<'a>, (f64, f64)>,
+    /// The cost centres in the order they first appear in the tree.
+    cost_centres: Vec<(&'a str, &'a str)>,
+    individual: HashMap<(&'a str, &'a str), (f64, f64)>,
+}
+
+impl<'a> Costs<'a> {
+    fn of(prof: &GHCProf<'a>) -> Costs<'a> {
+        let mut costs = Costs {
+            absolute: Absolute::of(&prof.header),
+            paths: Vec::new(),
+            inherited: HashMap::new(),
+            cost_centres: Vec::new(),
+            individual: HashMap::new(),
+        };
+        let ExtendedSummary(ref tree) = prof.extended_summary;
+        costs.add(tree, &mut Vec::new());
+        costs
+    }
+
+    /// Adds the costs of `tree`, found at `path`, returning its inherited
+    /// costs.
+    fn add(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>, path: &mut CallPath<'a>) -> (f64, f64) {
+        let line = &tree.value;
+        let key = (line.cost_centre, line.module);
+        path.push(key);
+        let time = self.absolute.time(line);
+        let alloc = self.absolute.alloc(line);
+        if !self.individual.contains_key(&key) {
+            self.cost_centres.push(key);
+        }
+        {
+            let individual = self.individual.entry(key).or_insert((0.0, 0.0));
+            individual.0 += time;
+            individual.1 += alloc;
+        }
+
+        // The same call path can appear twice when GHC didn't merge it.
+        if !self.inherited.contains_key(path) {
+            self.paths.push(path.clone());
+            self.inherited.insert(path.clone(), (0.0, 0.0));
+        }
+        let mut inherited = (time, alloc);
+        for t in &tree.sub_forest {
+            let (t_time, t_alloc) = self.add(t, path);
+            inherited.0 += t_time;
+            inherited.1 += t_alloc;
+        }
+        {
+            let sum = self.inherited.get_mut(path).unwrap();
+            sum.0 += inherited.0;
+            sum.1 += inherited.1;
+        }
+        path.pop();
+        inherited
+    }
+
+    fn perc(&self, metric: Metric, cost: f64) -> f64 {
+        let total = self.absolute.total(metric);
+        if total > 0.0 { cost * 100.0 / total } else { 0.0 }
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for any single one.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob(&pattern, &text)
+}
+
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(&'*') => (0..text.len() + 1).any(|i| glob(&pattern[1..], &text[i..])),
+        Some(&'?') => !text.is_empty() && glob(&pattern[1..], &text[1..]),
+        Some(&c) => text.first() == Some(&c) && glob(&pattern[1..], &text[1..]),
+    }
+}
+
+/// A limit on the costs of the call paths ending in some cost centres, as
+/// in "the parser must stay under 20% alloc".
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// Shown in reports, e.g. `parser`.
+    pub name: String,
+    /// Patterns with `*` and `?` wildcards.
+    pub cost_centre: String,
+    pub module: String,
+    pub limits: Vec<(Measure, f64)>,
+}
+
+/// A cost of a node of the cost centre tree a rule can limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measure {
+    IndividualTime,
+    IndividualAlloc,
+    InheritedTime,
+    InheritedAlloc,
+    IndividualBytes,
+    InheritedBytes,
+    Entries,
+}
+
+pub const MEASURES: &[Measure] = &[Measure::IndividualTime,
+                                   Measure::IndividualAlloc,
+                                   Measure::InheritedTime,
+                                   Measure::InheritedAlloc,
+                                   Measure::IndividualBytes,
+                                   Measure::InheritedBytes,
+                                   Measure::Entries];
+
+impl Measure {
+    /// The name of the limit in a rules file.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Measure::IndividualTime => "max-individual-time",
+            Measure::IndividualAlloc => "max-individual-alloc",
+            Measure::InheritedTime => "max-inherited-time",
+            Measure::InheritedAlloc => "max-inherited-alloc",
+            Measure::IndividualBytes => "max-individual-bytes",
+            Measure::InheritedBytes => "max-inherited-bytes",
+            Measure::Entries => "max-entries",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match *self {
+            Measure::IndividualTime => "individual %time",
+            Measure::IndividualAlloc => "individual %alloc",
+            Measure::InheritedTime => "inherited %time",
+            Measure::InheritedAlloc => "inherited %alloc",
+            Measure::IndividualBytes => "individual alloc",
+            Measure::InheritedBytes => "inherited alloc",
+            Measure::Entries => "entries",
+        }
+    }
+
+    fn format(&self, value: f64) -> String {
+        match *self {
+            Measure::IndividualBytes | Measure::InheritedBytes => format_cost(Metric::Alloc, value),
+            Measure::Entries => format!("{}", value),
+            _ => format!("{:.1}%", value),
+        }
+    }
+}
+
+/// The value of a limited cost at one call path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub rule: String,
+    /// The call path, as in `Comparison`.
+    pub path: String,
+    pub measure: Measure,
+    pub value: f64,
+    pub limit: f64,
+}
+
+/// Checks each limit of `rules` at every call path of `prof` ending in a
+/// cost centre they match, rule by rule in the order of the tree.
+pub fn evaluate<'a>(prof: &GHCProf<'a>, rules: &[Rule]) -> Vec<Verdict> {
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    let absolute = Absolute::of(&prof.header);
+    let mut verdicts = Vec::new();
+    for rule in rules {
+        evaluate_tree(tree, rule, &absolute, &mut Vec::new(), &mut verdicts);
+    }
+    verdicts
+}
+
+fn evaluate_tree<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                     rule: &Rule,
+                     absolute: &Absolute,
+                     path: &mut CallPath<'a>,
+                     verdicts: &mut Vec<Verdict>) {
+    let line = &tree.value;
+    path.push((line.cost_centre, line.module));
+    if glob_matches(&rule.cost_centre, line.cost_centre) && glob_matches(&rule.module, line.module) {
+        for &(measure, limit) in &rule.limits {
+            let value = match measure {
+                Measure::IndividualTime => line.individual_time_perc as f64,
+                Measure::IndividualAlloc => line.individual_alloc_perc as f64,
+                Measure::InheritedTime => line.inherited_time_perc as f64,
+                Measure::InheritedAlloc => line.inherited_alloc_perc as f64,
+                Measure::IndividualBytes => absolute.alloc(line),
+                Measure::InheritedBytes => inherited_bytes(tree, absolute),
+                Measure::Entries => line.entries as f64,
+            };
+            verdicts.push(Verdict {
+                rule: rule.name.clone(),
+                path: display(path),
+                measure,
+                value,
+                limit,
+            });
+        }
+    }
+    for t in &tree.sub_forest {
+        evaluate_tree(t, rule, absolute, path, verdicts);
+    }
+    path.pop();
+}
+
+fn inherited_bytes(tree: &RoseTree<ExtendedSummaryLine>, absolute: &Absolute) -> f64 {
+    absolute.alloc(&tree.value) + tree.sub_forest.iter().map(|t| inherited_bytes(t, absolute)).sum::<f64>()
+}
+
+/// A check as reported: what was checked and, if it failed, why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    /// Groups the test cases in the JUnit report.
+    pub suite: String,
+    pub name: String,
+    pub failure: Option<String>,
+}
+
+impl Comparison {
+    pub fn test_case(&self) -> TestCase {
+        let suite = match self.granularity {
+            Granularity::CallPath => "call paths",
+            Granularity::CostCentre => "cost centres",
+        };
+        let failure = if self.failed() {
+            Some(format!("{} ({} -> {}), over the {}% budget",
+                         format_increase(self.increase()),
+                         format_cost(self.metric, self.baseline),
+                         format_cost(self.metric, self.current),
+                         self.limit))
+        } else {
+            None
+        };
+        TestCase {
+            suite: suite.to_string(),
+            name: format!("{} ({})", self.name, self.metric.name()),
+            failure,
+        }
+    }
+}
+
+impl Verdict {
+    pub fn failed(&self) -> bool {
+        self.value > self.limit
+    }
+
+    pub fn test_case(&self) -> TestCase {
+        let failure = if self.failed() {
+            Some(format!("{}, over the {} allowed by rule `{}`",
+                         self.measure.format(self.value),
+                         self.measure.format(self.limit),
+                         self.rule))
+        } else {
+            None
+        };
+        TestCase {
+            suite: format!("rule {}", self.rule),
+            name: format!("{} ({})", self.path, self.measure.description()),
+            failure,
+        }
+    }
+}
+
+/// Writes the failed checks, then how many there are.
+pub fn write_text<W: Write>(out: &mut W, cases: &[TestCase]) -> io::Result<()> {
+    let mut failures = 0;
+    for case in cases {
+        if let Some(ref failure) = case.failure {
+            writeln!(out, "FAIL {}: {}", case.name, failure)?;
+            failures += 1;
+        }
+    }
+    if failures == 0 {
+        writeln!(out, "All {} checks within budget.", cases.len())
+    } else {
+        writeln!(out, "\n{} of {} checks over budget.", failures, cases.len())
+    }
+}
+
+/// Writes the checks as a JUnit XML report for CI servers to pick up, with
+/// the test suites in the order they first appear.
+pub fn write_junit<W: Write>(out: &mut W, cases: &[TestCase]) -> io::Result<()> {
+    let failures = |cases: &[&TestCase]| cases.iter().filter(|c| c.failure.is_some()).count();
+    let all: Vec<&TestCase> = cases.iter().collect();
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out,
+             "<testsuites name=\"profiv check\" tests=\"{}\" failures=\"{}\">",
+             all.len(),
+             failures(&all))?;
+    let mut suites: Vec<&str> = Vec::new();
+    for case in cases {
+        if !suites.contains(&case.suite.as_str()) {
+            suites.push(&case.suite);
+        }
+    }
+    for suite in suites {
+        let in_suite: Vec<&TestCase> = cases.iter().filter(|c| c.suite == suite).collect();
+        let suite = xml_escape(suite);
+        writeln!(out,
+                 "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">",
+                 suite,
+                 in_suite.len(),
+                 failures(&in_suite))?;
+        for case in in_suite {
+            let name = xml_escape(&case.name);
+            match case.failure {
+                None => writeln!(out, "    <testcase classname=\"{}\" name=\"{}\"/>", suite, name)?,
+                Some(ref failure) => {
+                    writeln!(out, "    <testcase classname=\"{}\" name=\"{}\">", suite, name)?;
+                    writeln!(out, "      <failure message=\"{}\"/>", xml_escape(failure))?;
+                    writeln!(out, "    </testcase>")?;
+                }
+            }
+        }
+        writeln!(out, "  </testsuite>")?;
+    }
+    writeln!(out, "</testsuites>")
+}
+
+fn format_increase(increase: f64) -> String {
+    if increase.is_infinite() { "new".to_string() } else { format!("{:+.1}%", increase) }
+}
+
+/// `1234` microseconds as `1.23 ms`, `1234567` bytes as `1.23 MB`.
+fn format_cost(metric: Metric, cost: f64) -> String {
+    let units: &[&str] = match metric {
+        Metric::Time => &["us", "ms", "s"],
+        Metric::Alloc => &["B", "kB", "MB", "GB"],
+    };
+    let mut cost = cost;
+    let mut unit = 0;
+    while cost >= 1000.0 && unit + 1 < units.len() {
+        cost /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{:.0} {}", cost, units[0]) } else { format!("{:.2} {}", cost, units[unit]) }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
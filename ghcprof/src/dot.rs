@@ -0,0 +1,187 @@
+use metric::Metric;
+use parser::{ExtendedSummaryLine, RoseTree};
+use rollup::ModuleCall;
+use std::cmp::{max, Ordering};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Writes the calls between modules as a Graphviz graph, for `dot -Tsvg`.
+/// Edges are labelled with their costs and entries, and drawn thicker the
+/// more `metric` they cost.
+pub fn write_module_calls<'a, W: Write>(out: &mut W, calls: &[ModuleCall<'a>], metric: Metric) -> io::Result<()> {
+    writeln!(out, "digraph modules {{")?;
+    writeln!(out, "  node [shape=box, fontname=\"Helvetica\"];")?;
+    writeln!(out, "  edge [fontname=\"Helvetica\", fontsize=10];")?;
+    for call in calls {
+        let perc = match metric {
+            Metric::Time => call.time_perc,
+            Metric::Alloc => call.alloc_perc,
+        };
+        writeln!(out,
+                 "  {} -> {} [label=\"{:.1}% time\\n{:.1}% alloc\\n{} entries\", penwidth={:.2}];",
+                 quote(call.caller),
+                 quote(call.callee),
+                 call.time_perc,
+                 call.alloc_perc,
+                 call.entries,
+                 penwidth(perc))?;
+    }
+    writeln!(out, "}}")
+}
+
+/// What the call graph of `write_call_graph` shows.
+#[derive(Debug, Clone)]
+pub struct GraphOptions {
+    /// Whether the calls to a cost centre are merged into one node, rather
+    /// than one per call path as in the tree.
+    pub merge: bool,
+    /// The cost the nodes are sized and coloured by.
+    pub metric: Metric,
+    /// Leaves out the nodes whose inherited cost is below this percentage.
+    pub threshold: f32,
+    /// Keeps only the most expensive nodes, by inherited cost.
+    pub max_nodes: Option<usize>,
+}
+
+impl Default for GraphOptions {
+    fn default() -> GraphOptions {
+        GraphOptions { merge: true, metric: Metric::Time, threshold: 1.0, max_nodes: None }
+    }
+}
+
+struct GraphNode<'a> {
+    line: ExtendedSummaryLine<'a>,
+    /// The inherited costs, of the outermost calls only when merged.
+    inherited: (f32, f32),
+}
+
+struct GraphEdge {
+    from: usize,
+    to: usize,
+    inherited: (f32, f32),
+    entries: u64,
+}
+
+struct Graph<'a> {
+    merge: bool,
+    nodes: Vec<GraphNode<'a>>,
+    edges: Vec<GraphEdge>,
+    index: HashMap<(&'a str, &'a str, u32), usize>,
+}
+
+impl<'a> Graph<'a> {
+    /// Adds `tree` and the call from `parent` to it. `open` are the nodes
+    /// and edges on the path to `tree`, whose costs already include its own.
+    fn add(&mut self,
+           tree: &RoseTree<ExtendedSummaryLine<'a>>,
+           parent: Option<usize>,
+           open: &mut Vec<(usize, Option<usize>)>) {
+        let line = &tree.value;
+        let key = (line.cost_centre, line.module, if self.merge { 0 } else { line.no });
+        let idx = match self.index.get(&key).cloned() {
+            Some(idx) => {
+                let node = &mut self.nodes[idx].line;
+                node.entries = node.entries.saturating_add(line.entries);
+                node.individual_time_perc += line.individual_time_perc;
+                node.individual_alloc_perc += line.individual_alloc_perc;
+                idx
+            }
+            None => {
+                self.nodes.push(GraphNode { line: line.clone(), inherited: (0.0, 0.0) });
+                self.index.insert(key, self.nodes.len() - 1);
+                self.nodes.len() - 1
+            }
+        };
+        let costs = (line.inherited_time_perc, line.inherited_alloc_perc);
+        if !open.iter().any(|&(n, _)| n == idx) {
+            self.nodes[idx].inherited.0 += costs.0;
+            self.nodes[idx].inherited.1 += costs.1;
+        }
+        let mut edge = None;
+        if let Some(from) = parent {
+            let e = match self.edges.iter().position(|e| e.from == from && e.to == idx) {
+                Some(e) => e,
+                None => {
+                    self.edges.push(GraphEdge { from, to: idx, inherited: (0.0, 0.0), entries: 0 });
+                    self.edges.len() - 1
+                }
+            };
+            self.edges[e].entries += line.entries as u64;
+            if !open.iter().any(|&(_, open_edge)| open_edge == Some(e)) {
+                self.edges[e].inherited.0 += costs.0;
+                self.edges[e].inherited.1 += costs.1;
+            }
+            edge = Some(e);
+        }
+        open.push((idx, edge));
+        for t in &tree.sub_forest {
+            self.add(t, Some(idx), open);
+        }
+        open.pop();
+    }
+}
+
+/// Writes `tree` as a Graphviz call graph, for `dot -Tsvg`. Nodes are sized
+/// and coloured, from blue to red, by their individual cost, and edges are
+/// labelled with the inherited cost of the callee and its entries. The root
+/// is always kept.
+pub fn write_call_graph<'a, W: Write>(out: &mut W,
+                                      tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                                      opts: &GraphOptions)
+                                      -> io::Result<()> {
+    let mut graph = Graph { merge: opts.merge, nodes: Vec::new(), edges: Vec::new(), index: HashMap::new() };
+    graph.add(tree, None, &mut Vec::new());
+
+    let metric = opts.metric;
+    let perc = |costs: (f32, f32)| if metric == Metric::Time { costs.0 } else { costs.1 };
+    let mut kept: Vec<usize> = (0..graph.nodes.len())
+        .filter(|&i| i == 0 || perc(graph.nodes[i].inherited) >= opts.threshold)
+        .collect();
+    if let Some(max_nodes) = opts.max_nodes {
+        // Stable, so that callers stay ahead of their callees on ties.
+        kept.sort_by(|&a, &b| {
+            let key = |i: usize| if i == 0 { f32::INFINITY } else { perc(graph.nodes[i].inherited) };
+            key(b).partial_cmp(&key(a)).unwrap_or(Ordering::Equal)
+        });
+        kept.truncate(max(max_nodes, 1));
+        kept.sort();
+    }
+
+    writeln!(out, "digraph calls {{")?;
+    writeln!(out, "  node [shape=box, style=filled, fontname=\"Helvetica\"];")?;
+    writeln!(out, "  edge [fontname=\"Helvetica\", fontsize=10];")?;
+    for &i in &kept {
+        let node = &graph.nodes[i];
+        let individual = node.line.individual_perc(metric);
+        writeln!(out,
+                 "  n{} [label={}, fontsize={:.1}, fillcolor=\"{:.3} 0.600 1.000\"];",
+                 i,
+                 quote(&format!("{}\n{}\n{:.1}% {} ({:.1}% inherited)",
+                                node.line.cost_centre,
+                                node.line.module,
+                                individual,
+                                metric.name(),
+                                perc(node.inherited))),
+                 10.0 + 20.0 * individual.min(100.0) / 100.0,
+                 0.65 * (1.0 - individual.min(100.0) / 100.0))?;
+    }
+    for edge in graph.edges.iter().filter(|e| kept.contains(&e.from) && kept.contains(&e.to)) {
+        writeln!(out,
+                 "  n{} -> n{} [label={}, penwidth={:.2}];",
+                 edge.from,
+                 edge.to,
+                 quote(&format!("{:.1}%\n{} entries", perc(edge.inherited), edge.entries)),
+                 penwidth(perc(edge.inherited)))?;
+    }
+    writeln!(out, "}}")
+}
+
+/// From 1 for nothing to 8 for the whole program.
+fn penwidth(perc: f32) -> f32 {
+    1.0 + 7.0 * perc.min(100.0) / 100.0
+}
+
+/// `s` as a Graphviz string.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
@@ -0,0 +1,61 @@
+use metric::Metric;
+use parser::{ExtendedSummaryLine, RoseTree};
+
+/// A node of a flame graph: a cost centre of the tree, drawn `depth` rows
+/// from the root, from `start` to `start + width`, as percentages of the
+/// width of the root.
+#[derive(Debug, Clone)]
+pub struct FlameRect<'t, 'a: 't> {
+    pub node: &'t RoseTree<ExtendedSummaryLine<'a>>,
+    pub depth: usize,
+    pub start: f64,
+    pub width: f64,
+}
+
+/// Lays `tree` out as a flame graph, each node as wide as its inherited
+/// cost in `metric`, and its children side by side above it, in order,
+/// from its left edge. Nodes narrower than `min_width` are left out, with
+/// their children. Rounding in the profile may make children add up to
+/// more than their parent: the last ones are cut to fit.
+pub fn layout<'t, 'a>(tree: &'t RoseTree<ExtendedSummaryLine<'a>>,
+                      metric: Metric,
+                      min_width: f64)
+                      -> Vec<FlameRect<'t, 'a>> {
+    let total = tree.value.inherited_perc(metric) as f64;
+    let layout = Layout {
+        scale: if total > 0.0 { 100.0 / total } else { 0.0 },
+        metric,
+        min_width,
+    };
+    let mut rects = Vec::new();
+    layout.add(tree, 0, 0.0, 100.0, &mut rects);
+    rects
+}
+
+struct Layout {
+    /// From the percentages of the profile to those of the root.
+    scale: f64,
+    metric: Metric,
+    min_width: f64,
+}
+
+impl Layout {
+    fn add<'t, 'a>(&self,
+                   tree: &'t RoseTree<ExtendedSummaryLine<'a>>,
+                   depth: usize,
+                   start: f64,
+                   width: f64,
+                   rects: &mut Vec<FlameRect<'t, 'a>>) {
+        if width < self.min_width || width <= 0.0 {
+            return;
+        }
+        rects.push(FlameRect { node: tree, depth, start, width });
+        let end = start + width;
+        let mut x = start;
+        for t in &tree.sub_forest {
+            let w = (t.value.inherited_perc(self.metric) as f64 * self.scale).min(end - x);
+            self.add(t, depth + 1, x, w, rects);
+            x += w;
+        }
+    }
+}
@@ -1,3 +1,18 @@
 #[macro_use] extern crate nom;
 
 pub mod parser;
+pub mod metric;
+pub mod source;
+pub mod callpath;
+pub mod writer;
+pub mod merge;
+pub mod check;
+pub mod query;
+pub mod transform;
+pub mod rollup;
+pub mod dot;
+pub mod flame;
+pub mod markdown;
+pub mod pprof;
+pub mod trace;
+pub mod callgrind;
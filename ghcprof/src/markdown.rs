@@ -0,0 +1,122 @@
+use callpath::{display, CallPath};
+use check::{compare, Budget, Comparison, Granularity};
+use metric::Metric;
+use parser::{ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree, Summary};
+use std::cmp::Ordering;
+use std::f64;
+use std::io::{self, Write};
+
+/// Writes the `top` most expensive cost centres of `prof` in `metric`, and
+/// the `top` call paths spending the most in it themselves, as GitHub
+/// flavoured Markdown tables.
+pub fn write_summary<'a, W: Write>(out: &mut W, prof: &GHCProf<'a>, metric: Metric, top: usize) -> io::Result<()> {
+    let Summary(ref lines) = prof.summary;
+    let mut lines = lines.clone();
+    lines.sort_by(|a, b| b.perc(metric).partial_cmp(&a.perc(metric)).unwrap_or(Ordering::Equal));
+    writeln!(out, "### Top cost centres by {}", metric.name())?;
+    writeln!(out)?;
+    writeln!(out, "| Cost centre | Module | %time | %alloc |")?;
+    writeln!(out, "|---|---|--:|--:|")?;
+    for line in lines.iter().take(top) {
+        writeln!(out, "| {} | {} | {:.1} | {:.1} |",
+                 code(line.cost_centre), code(line.module), line.time_perc, line.alloc_perc)?;
+    }
+
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    let mut paths = Vec::new();
+    add_paths(tree, &mut Vec::new(), &mut paths);
+    paths.sort_by(|a, b| {
+        b.1.individual_perc(metric).partial_cmp(&a.1.individual_perc(metric)).unwrap_or(Ordering::Equal)
+    });
+    writeln!(out)?;
+    writeln!(out, "### Hot call paths by {}", metric.name())?;
+    writeln!(out)?;
+    writeln!(out, "| Call path | entries | individual %{m} | inherited %{m} |", m = metric.name())?;
+    writeln!(out, "|---|--:|--:|--:|")?;
+    for &(ref path, line) in paths.iter().take(top) {
+        writeln!(out, "| {} | {} | {:.1} | {:.1} |",
+                 code(&display(path)), line.entries, line.individual_perc(metric), line.inherited_perc(metric))?;
+    }
+    Ok(())
+}
+
+/// Every node of `tree` with its call path, leaving out the root, which is
+/// the start of every path.
+fn add_paths<'t, 'a>(tree: &'t RoseTree<ExtendedSummaryLine<'a>>,
+                     path: &mut CallPath<'a>,
+                     paths: &mut Vec<(CallPath<'a>, &'t ExtendedSummaryLine<'a>)>) {
+    for t in &tree.sub_forest {
+        path.push((t.value.cost_centre, t.value.module));
+        paths.push((path.clone(), &t.value));
+        add_paths(t, path, paths);
+        path.pop();
+    }
+}
+
+/// Writes the `top` largest changes in the individual `metric` cost of the
+/// cost centres of `current` since `baseline`, as a GitHub flavoured
+/// Markdown table with an arrow up for increases and one down for
+/// decreases, cost centres gone from `current` included.
+///
+/// Costs are absolute, so that a faster program doesn't show up as all its
+/// cost centres taking a larger share of it. Cost centres under 1% in both
+/// profiles are left out, as `check` does.
+pub fn write_diff<'a, W: Write>(out: &mut W,
+                                baseline: &GHCProf<'a>,
+                                current: &GHCProf<'a>,
+                                metric: Metric,
+                                top: usize) -> io::Result<()> {
+    let budget = Budget {
+        max_time_increase: Some(0.0),
+        max_alloc_increase: Some(0.0),
+        ..Budget::default()
+    };
+    let mut changes: Vec<Comparison> = compare(baseline, current, &budget)
+        .into_iter()
+        .filter(|c| c.granularity == Granularity::CostCentre && c.metric == metric && c.current != c.baseline)
+        .collect();
+    changes.sort_by(|a, b| {
+        let change = |c: &Comparison| (c.current - c.baseline).abs();
+        change(b).partial_cmp(&change(a)).unwrap_or(Ordering::Equal)
+    });
+    writeln!(out, "### Changes in {}", metric.name())?;
+    writeln!(out)?;
+    if changes.is_empty() {
+        return writeln!(out, "No changes.");
+    }
+    writeln!(out, "| Cost centre | Before | After | Change |")?;
+    writeln!(out, "|---|--:|--:|--:|")?;
+    for c in changes.iter().take(top) {
+        let increase = c.increase();
+        let change = if increase == f64::INFINITY {
+            "↑ new".to_string()
+        } else if c.current == 0.0 {
+            "↓ removed".to_string()
+        } else if increase > 0.0 {
+            format!("↑ {:.1}%", increase)
+        } else {
+            format!("↓ {:.1}%", -increase)
+        };
+        writeln!(out, "| {} | {} | {} | {} |",
+                 code(&c.name), amount(metric, c.baseline), amount(metric, c.current), change)?;
+    }
+    Ok(())
+}
+
+/// A cost in microseconds or bytes, in a readable unit.
+fn amount(metric: Metric, cost: f64) -> String {
+    match metric {
+        Metric::Time if cost >= 1e6 => format!("{:.2} s", cost / 1e6),
+        Metric::Time if cost >= 1e3 => format!("{:.1} ms", cost / 1e3),
+        Metric::Time => format!("{:.0} µs", cost),
+        Metric::Alloc if cost >= 1e9 => format!("{:.2} GB", cost / 1e9),
+        Metric::Alloc if cost >= 1e6 => format!("{:.1} MB", cost / 1e6),
+        Metric::Alloc if cost >= 1e3 => format!("{:.1} kB", cost / 1e3),
+        Metric::Alloc => format!("{:.0} B", cost),
+    }
+}
+
+/// `s` as inline code in a table cell, where `|` would end the cell.
+fn code(s: &str) -> String {
+    format!("`{}`", s.replace('|', "\\|"))
+}
@@ -0,0 +1,239 @@
+use metric::{Absolute, Metric};
+use parser::{ExtendedSummary, ExtendedSummaryLine, GHCProf, Header, RoseTree, Summary, SummaryLine,
+             TotalAlloc, TotalTime};
+use std::collections::HashMap;
+
+/// How the costs of several profiles are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Combine {
+    Sum,
+    /// The sum divided by the number of profiles, as for repeated runs of
+    /// the same benchmark.
+    Average,
+}
+
+/// Merges the cost centre trees of `profs` by call path, adding up their
+/// absolute ticks, bytes and entries, then recomputes the percentages and
+/// the header totals. Ticks come from the `ticks` column when the profile
+/// has one (`+RTS -P`), and from the percentages otherwise. The result only
+/// has the `ticks` and `bytes` columns when all of `profs` do. Fails when
+/// there is nothing to merge, or when the trees don't share their root.
+pub fn merge<'a>(profs: &[GHCProf<'a>], how: Combine) -> Result<GHCProf<'a>, String> {
+    let first = match profs.first() {
+        Some(first) => first,
+        None => return Err("no profile to merge".to_string()),
+    };
+    let freq = first.header.total_time.freq;
+    // Ticks are counted at the tick interval of the first profile.
+    let tick = if freq > 0 { freq as f64 } else { 1.0 };
+    let mut total = Costs::default();
+    let mut time = 0.0;
+    let mut procs = 0;
+    let mut root: Option<Node<'a>> = None;
+    for prof in profs {
+        let tt = &prof.header.total_time;
+        let absolute = Absolute::of(&prof.header);
+        total.add(&Costs {
+            ticks: absolute.total(Metric::Time) / tick,
+            bytes: absolute.total(Metric::Alloc),
+            entries: 0.0,
+        });
+        time += tt.time as f64;
+        procs = ::std::cmp::max(procs, tt.procs);
+        let ExtendedSummary(ref tree) = prof.extended_summary;
+        match root {
+            None => root = Some(Node::from(tree, &absolute, tick)),
+            Some(ref mut node) => {
+                let (a, b) = (&node.line, &tree.value);
+                if (a.cost_centre, a.module) != (b.cost_centre, b.module) {
+                    return Err(format!("cannot merge a profile rooted at `{}.{}` with one rooted at `{}.{}`",
+                                       a.module, a.cost_centre, b.module, b.cost_centre));
+                }
+                node.absorb(tree, &absolute, tick)
+            }
+        }
+    }
+    let mut root = root.unwrap();
+    if how == Combine::Average {
+        let n = profs.len() as f64;
+        total.scale(1.0 / n);
+        time /= n;
+        root.scale(1.0 / n);
+    }
+
+    let columns = profs.iter().all(|p| {
+        let ExtendedSummary(ref tree) = p.extended_summary;
+        tree.value.ticks.is_some() && tree.value.bytes.is_some()
+    });
+    let mut no = 0;
+    let tree = root.to_rose_tree(0, &total, columns, &mut no).0;
+    let summary = summarise(&tree);
+    Ok(GHCProf {
+        header: Header {
+            title: first.header.title,
+            program: first.header.program,
+            total_time: TotalTime {
+                time: time as f32,
+                ticks: total.ticks.round() as u32,
+                freq,
+                procs,
+            },
+            total_alloc: TotalAlloc { bytes: total.bytes.round() as u64 },
+        },
+        summary,
+        extended_summary: ExtendedSummary(tree),
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+struct Costs {
+    ticks: f64,
+    bytes: f64,
+    entries: f64,
+}
+
+impl Costs {
+    /// The individual costs of `line`, with its time in ticks of `tick`
+    /// microseconds.
+    fn of(line: &ExtendedSummaryLine, absolute: &Absolute, tick: f64) -> Costs {
+        Costs { ticks: absolute.time(line) / tick, bytes: absolute.alloc(line), entries: line.entries as f64 }
+    }
+
+    fn add(&mut self, other: &Costs) {
+        self.ticks += other.ticks;
+        self.bytes += other.bytes;
+        self.entries += other.entries;
+    }
+
+    fn scale(&mut self, factor: f64) {
+        self.ticks *= factor;
+        self.bytes *= factor;
+        self.entries *= factor;
+    }
+}
+
+struct Node<'a> {
+    line: ExtendedSummaryLine<'a>,
+    costs: Costs,
+    children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn from(tree: &RoseTree<ExtendedSummaryLine<'a>>, absolute: &Absolute, tick: f64) -> Node<'a> {
+        Node {
+            line: tree.value.clone(),
+            costs: Costs::of(&tree.value, absolute, tick),
+            children: tree.sub_forest.iter().map(|t| Node::from(t, absolute, tick)).collect(),
+        }
+    }
+
+    /// Adds the costs of `tree`, which has the same call path as `self`.
+    fn absorb(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>, absolute: &Absolute, tick: f64) {
+        self.costs.add(&Costs::of(&tree.value, absolute, tick));
+        if self.line.src.is_none() {
+            self.line.src = tree.value.src;
+        }
+        for t in &tree.sub_forest {
+            let pos = self.children.iter().position(|c| {
+                c.line.cost_centre == t.value.cost_centre && c.line.module == t.value.module
+            });
+            match pos {
+                Some(idx) => self.children[idx].absorb(t, absolute, tick),
+                None => self.children.push(Node::from(t, absolute, tick)),
+            }
+        }
+    }
+
+    fn scale(&mut self, factor: f64) {
+        self.costs.scale(factor);
+        for c in &mut self.children {
+            c.scale(factor);
+        }
+    }
+
+    /// Numbers the nodes in order, returning the tree and its inherited
+    /// costs. `columns` tells whether to fill in `ticks` and `bytes`.
+    fn to_rose_tree(&self,
+                    depth: usize,
+                    total: &Costs,
+                    columns: bool,
+                    no: &mut u32)
+                    -> (RoseTree<ExtendedSummaryLine<'a>>, Costs) {
+        *no += 1;
+        let mut line = self.line.clone();
+        line.no = *no;
+        let mut inherited = self.costs.clone();
+        let mut sub_forest = Vec::new();
+        for c in &self.children {
+            let (tree, costs) = c.to_rose_tree(depth + 1, total, columns, no);
+            inherited.add(&costs);
+            sub_forest.push(tree);
+        }
+        line.entries = clamp_u32(self.costs.entries);
+        line.individual_time_perc = perc(self.costs.ticks, total.ticks);
+        line.individual_alloc_perc = perc(self.costs.bytes, total.bytes);
+        line.inherited_time_perc = perc(inherited.ticks, total.ticks);
+        line.inherited_alloc_perc = perc(inherited.bytes, total.bytes);
+        if columns {
+            line.ticks = Some(self.costs.ticks.round() as u64);
+            line.bytes = Some(self.costs.bytes.round() as u64);
+        } else {
+            line.ticks = None;
+            line.bytes = None;
+        }
+        let tree = RoseTree { depth, value: line, sub_forest };
+        (tree, inherited)
+    }
+}
+
+fn perc(part: f64, total: f64) -> f32 {
+    if total > 0.0 { (part * 100.0 / total) as f32 } else { 0.0 }
+}
+
+fn clamp_u32(n: f64) -> u32 {
+    if n >= u32::MAX as f64 { u32::MAX } else { n.round() as u32 }
+}
+
+/// The flat summary of `tree`: the individual costs of each cost centre,
+/// wherever it is called from, most expensive first. Cost centres under 1%
+/// of both time and alloc are left out, to keep it short. Ticks and bytes
+/// are added up too, when every node has them.
+pub fn summarise<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> Summary<'a> {
+    let mut order = Vec::new();
+    let mut totals: HashMap<(&'a str, &'a str), SummaryLine<'a>> = HashMap::new();
+    add_to_summary(tree, &mut order, &mut totals);
+    let mut lines: Vec<SummaryLine<'a>> = order.into_iter()
+        .map(|key| totals.remove(&key).unwrap())
+        .filter(|l| l.time_perc >= 1.0 || l.alloc_perc >= 1.0)
+        .collect();
+    lines.sort_by(|a, b| {
+        (b.time_perc, b.alloc_perc).partial_cmp(&(a.time_perc, a.alloc_perc)).unwrap_or(::std::cmp::Ordering::Equal)
+    });
+    Summary(lines)
+}
+
+fn add_to_summary<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                      order: &mut Vec<(&'a str, &'a str)>,
+                      totals: &mut HashMap<(&'a str, &'a str), SummaryLine<'a>>) {
+    let line = &tree.value;
+    let key = (line.cost_centre, line.module);
+    if !totals.contains_key(&key) {
+        order.push(key);
+    }
+    let entry = totals.entry(key).or_insert(SummaryLine {
+        cost_centre: line.cost_centre,
+        module: line.module,
+        src: line.src,
+        time_perc: 0.0,
+        alloc_perc: 0.0,
+        ticks: line.ticks.map(|_| 0),
+        bytes: line.bytes.map(|_| 0),
+    });
+    entry.time_perc += line.individual_time_perc;
+    entry.alloc_perc += line.individual_alloc_perc;
+    entry.ticks = entry.ticks.and_then(|t| line.ticks.map(|lt| t + lt));
+    entry.bytes = entry.bytes.and_then(|b| line.bytes.map(|lb| b + lb));
+    for t in &tree.sub_forest {
+        add_to_summary(t, order, totals);
+    }
+}
@@ -0,0 +1,101 @@
+use std::str::FromStr;
+use parser::{Header, SummaryLine, ExtendedSummaryLine};
+
+/// The cost dimension a view or a report is driven by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Time,
+    Alloc,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Metric::Time => "time",
+            Metric::Alloc => "alloc",
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Metric, String> {
+        match s {
+            "time" => Ok(Metric::Time),
+            "alloc" => Ok(Metric::Alloc),
+            _ => Err(format!("Unknown metric {:?}, expected one of: time, alloc.", s)),
+        }
+    }
+}
+
+impl<'a> SummaryLine<'a> {
+    pub fn perc(&self, metric: Metric) -> f32 {
+        match metric {
+            Metric::Time => self.time_perc,
+            Metric::Alloc => self.alloc_perc,
+        }
+    }
+}
+
+impl<'a> ExtendedSummaryLine<'a> {
+    pub fn individual_perc(&self, metric: Metric) -> f32 {
+        match metric {
+            Metric::Time => self.individual_time_perc,
+            Metric::Alloc => self.individual_alloc_perc,
+        }
+    }
+
+    pub fn inherited_perc(&self, metric: Metric) -> f32 {
+        match metric {
+            Metric::Time => self.inherited_time_perc,
+            Metric::Alloc => self.inherited_alloc_perc,
+        }
+    }
+}
+
+/// Turns the individual costs of the nodes of a profile into microseconds
+/// and bytes, from the `ticks` and `bytes` columns when it has them, from
+/// the percentages and the totals of the header otherwise.
+pub(crate) struct Absolute {
+    tick_micros: f64,
+    total_micros: f64,
+    total_bytes: f64,
+}
+
+impl Absolute {
+    pub(crate) fn of<'a>(header: &Header<'a>) -> Absolute {
+        let tt = &header.total_time;
+        Absolute {
+            tick_micros: tt.freq as f64,
+            total_micros: tt.ticks as f64 * tt.freq as f64,
+            total_bytes: header.total_alloc.bytes as f64,
+        }
+    }
+
+    pub(crate) fn tick_micros(&self) -> f64 {
+        self.tick_micros
+    }
+
+    /// The microseconds or bytes of the whole program.
+    pub(crate) fn total(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Time => self.total_micros,
+            Metric::Alloc => self.total_bytes,
+        }
+    }
+
+    pub(crate) fn time<'a>(&self, line: &ExtendedSummaryLine<'a>) -> f64 {
+        match line.ticks {
+            Some(ticks) => ticks as f64 * self.tick_micros,
+            None => line.individual_time_perc as f64 * self.total_micros / 100.0,
+        }
+    }
+
+    pub(crate) fn alloc<'a>(&self, line: &ExtendedSummaryLine<'a>) -> f64 {
+        match line.bytes {
+            Some(bytes) => bytes as f64,
+            None => line.individual_alloc_perc as f64 * self.total_bytes / 100.0,
+        }
+    }
+}
@@ -6,14 +6,14 @@ use nom::{IResult, Needed, is_space, space, is_digit, line_ending, not_line_endi
 
 // Rose Tree
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RoseTree<T> {
     pub depth: usize,
     pub value: T,
     pub sub_forest: Vec<RoseTree<T>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TotalTime {
     pub time: f32,
     pub ticks: u32,
@@ -21,12 +21,12 @@ pub struct TotalTime {
     pub procs: u8,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TotalAlloc {
     pub bytes: u64,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Header<'a> {
     pub title: &'a str,
     pub program: &'a str,
@@ -41,26 +41,36 @@ pub struct Summary<'a>(pub Vec<SummaryLine<'a>>);
 pub struct SummaryLine<'a> {
     pub cost_centre: &'a str,
     pub module: &'a str,
+    /// The SRC column, only present in profiles made by GHC 8 or later.
+    pub src: Option<&'a str>,
     pub time_perc: f32,
     pub alloc_perc: f32,
+    /// The `ticks` and `bytes` columns, only present with `+RTS -P`.
+    pub ticks: Option<u64>,
+    pub bytes: Option<u64>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ExtendedSummary<'a>(pub RoseTree<ExtendedSummaryLine<'a>>);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ExtendedSummaryLine<'a> {
     pub cost_centre: &'a str,
     pub module: &'a str,
+    pub src: Option<&'a str>,
     pub no: u32,
     pub entries: u32,
     pub individual_time_perc: f32,
     pub individual_alloc_perc: f32,
     pub inherited_time_perc: f32,
     pub inherited_alloc_perc: f32,
+    /// The individual ticks and bytes, only present in profiles made with
+    /// `+RTS -P`.
+    pub ticks: Option<u64>,
+    pub bytes: Option<u64>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct GHCProf<'a> {
     pub header: Header<'a>,
     pub summary: Summary<'a>,
@@ -96,6 +106,44 @@ pub fn parse_num<T>(input: &[u8]) -> IResult<&[u8], T>
     }
 }
 
+/// The SRC column of profiles made by GHC 8 or later, which is either a
+/// source span or a placeholder like `<built-in>` or `<no location info>`.
+/// Older profiles go straight to the numbers, so nothing is consumed then.
+pub fn opt_src(input: &[u8]) -> IResult<&[u8], Option<&str>> {
+    match input.first() {
+        None => IResult::Incomplete(Needed::Size(1)),
+        Some(&c) if is_digit(c) => IResult::Done(input, None),
+        Some(_) => {
+            let (leftover, src) = try_parse!(input, src_column);
+            let (leftover, _) = try_parse!(leftover, space);
+            IResult::Done(leftover, Some(src))
+        }
+    }
+}
+
+named!(src_placeholder<&[u8], &[u8]>, recognize!(do_parse!(
+    tag!("<") >> take_until!(">") >> tag!(">") >> (())
+)));
+
+named!(src_span<&[u8], &[u8]>, take_till!(is_space));
+
+named!(src_column<&[u8], &str>, map_res!(alt!(src_placeholder | src_span), str::from_utf8));
+
+/// The `ticks` and `bytes` columns which `+RTS -P` adds to the flat
+/// summary and the tree.
+pub fn opt_ticks_bytes(input: &[u8]) -> IResult<&[u8], Option<(u64, u64)>> {
+    let (leftover, _) = try_parse!(input, take_while!(is_space));
+    match leftover.first() {
+        Some(&c) if is_digit(c) => {
+            let (leftover, ticks) = try_parse!(leftover, parse_num);
+            let (leftover, _) = try_parse!(leftover, space);
+            let (leftover, bytes) = try_parse!(leftover, parse_num);
+            IResult::Done(leftover, Some((ticks, bytes)))
+        }
+        _ => IResult::Done(leftover, None),
+    }
+}
+
 named!(pub text_line<&[u8],&str>, do_parse!(
     opt!(space) >>
     res: map_res!(not_line_ending, str::from_utf8) >>
@@ -148,15 +196,20 @@ named!(pub parse_summary_line<&[u8], SummaryLine>, do_parse!(
         space >>
         module: map_res!(take_till!(is_space), str::from_utf8) >>
         take_while!(is_space) >>
+        src: opt_src >>
         time_perc: parse_num >>
         take_while!(is_space) >>
         alloc_perc: parse_num >>
+        ticks_bytes: opt_ticks_bytes >>
         line_ending >>
         (SummaryLine{
             cost_centre: cost_centre,
             module: module,
+            src: src,
             time_perc: time_perc,
             alloc_perc: alloc_perc,
+            ticks: ticks_bytes.map(|tb| tb.0),
+            bytes: ticks_bytes.map(|tb| tb.1),
         })
 ));
 
@@ -170,6 +223,7 @@ named!(pub parse_extended_summary_line<&[u8], ExtendedSummaryLine>, do_parse!(
     space >>
     module: map_res!(take_till!(is_space), str::from_utf8) >>
     take_while!(is_space) >>
+    src: opt_src >>
     no: parse_num >>
     take_while!(is_space) >>
     entries: parse_num >>
@@ -181,16 +235,20 @@ named!(pub parse_extended_summary_line<&[u8], ExtendedSummaryLine>, do_parse!(
     inherited_time_perc: parse_num >>
     take_while!(is_space) >>
     inherited_alloc_perc: parse_num >>
+    ticks_bytes: opt_ticks_bytes >>
     line_ending >>
     (ExtendedSummaryLine{
         cost_centre: cost_centre,
         module: module,
+        src: src,
         no: no,
         entries: entries,
         individual_time_perc:  individual_time_perc,
         individual_alloc_perc: individual_alloc_perc,
         inherited_time_perc:  inherited_time_perc,
         inherited_alloc_perc: inherited_alloc_perc,
+        ticks: ticks_bytes.map(|tb| tb.0),
+        bytes: ticks_bytes.map(|tb| tb.1),
     })
 ));
 
@@ -0,0 +1,227 @@
+use metric::Absolute;
+use parser::{ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree};
+use source::SrcSpan;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Writes `prof` as an uncompressed pprof `profile.proto`, for the pprof
+/// tools and the others which read it.
+///
+/// Every node of the cost centre tree is a sample, with the call path to
+/// it as stack and its individual costs as values, so that pprof adds them
+/// up to the inherited ones. The sample types are `time` in nanoseconds and
+/// `alloc` in bytes, followed by `ticks` and `bytes` when the profile has
+/// those columns. Each cost centre is a function named `Module.cost_centre`,
+/// with its SRC span as file and line, and a location of its own. Nodes
+/// costing nothing are left out.
+pub fn write_pprof<'a, W: Write>(out: &mut W, prof: &GHCProf<'a>) -> io::Result<()> {
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    let tt = &prof.header.total_time;
+    let mut profile = Profile {
+        // The first string is always the empty one.
+        strings: vec![String::new()],
+        string_index: HashMap::new(),
+        functions: Vec::new(),
+        function_index: HashMap::new(),
+        costs: Absolute::of(&prof.header),
+        ticks: tree.value.ticks.is_some(),
+        bytes: tree.value.bytes.is_some(),
+        samples: Message::new(),
+    };
+    profile.add_samples(tree, &mut Vec::new());
+
+    let mut msg = Message::new();
+    let mut sample_types = vec![("time", "nanoseconds"), ("alloc", "bytes")];
+    if profile.ticks {
+        sample_types.push(("ticks", "count"));
+    }
+    if profile.bytes {
+        sample_types.push(("bytes", "bytes"));
+    }
+    for &(name, unit) in &sample_types {
+        let value_type = profile.value_type(name, unit);
+        msg.message(1, &value_type);
+    }
+    msg.raw(&profile.samples.buf);
+    for (idx, f) in profile.functions.iter().enumerate() {
+        let id = idx as u64 + 1;
+        let mut line = Message::new();
+        line.uint(1, id);
+        line.uint(2, f.line as u64);
+        line.uint(3, f.column as u64);
+        let mut location = Message::new();
+        location.uint(1, id);
+        location.message(4, &line);
+        msg.message(4, &location);
+    }
+    for (idx, f) in profile.functions.iter().enumerate() {
+        let mut function = Message::new();
+        function.uint(1, idx as u64 + 1);
+        function.uint(2, f.name);
+        function.uint(3, f.name);
+        function.uint(4, f.file);
+        function.uint(5, f.line as u64);
+        msg.message(5, &function);
+    }
+    let duration = (tt.time as f64 * 1e9) as u64;
+    let period_type = profile.value_type("time", "nanoseconds");
+    let comment = profile.string(prof.header.program.trim());
+    let default_sample_type = profile.string("time");
+    for s in &profile.strings {
+        msg.bytes(6, s.as_bytes());
+    }
+    msg.uint(10, duration);
+    msg.message(11, &period_type);
+    msg.uint(12, (profile.costs.tick_micros() * 1000.0) as u64);
+    msg.uint(13, comment);
+    msg.uint(14, default_sample_type);
+    out.write_all(&msg.buf)
+}
+
+struct Function {
+    /// Indices in the string table.
+    name: u64,
+    file: u64,
+    line: usize,
+    column: usize,
+}
+
+struct Profile {
+    strings: Vec<String>,
+    string_index: HashMap<String, u64>,
+    functions: Vec<Function>,
+    /// The index in `functions` of each cost centre, by cost centre and module.
+    function_index: HashMap<(String, String), usize>,
+    costs: Absolute,
+    /// Whether the profile has the `ticks` and `bytes` columns.
+    ticks: bool,
+    bytes: bool,
+    samples: Message,
+}
+
+impl Profile {
+    /// `path` holds the location ids from the root to `tree`, excluded.
+    fn add_samples<'a>(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>, path: &mut Vec<u64>) {
+        let line = &tree.value;
+        path.push(self.location(line));
+        let time = self.costs.time(line) * 1000.0;
+        let alloc = self.costs.alloc(line);
+        let mut values = vec![time as u64, alloc as u64];
+        if self.ticks {
+            values.push(line.ticks.unwrap_or(0));
+        }
+        if self.bytes {
+            values.push(line.bytes.unwrap_or(0));
+        }
+        if values.iter().any(|&v| v > 0) {
+            // The leaf first.
+            let stack: Vec<u64> = path.iter().rev().cloned().collect();
+            let mut sample = Message::new();
+            sample.packed(1, &stack);
+            sample.packed(2, &values);
+            self.samples.message(2, &sample);
+        }
+        for t in &tree.sub_forest {
+            self.add_samples(t, path);
+        }
+        path.pop();
+    }
+
+    /// The id of the location, and function, of the cost centre of `line`.
+    fn location<'a>(&mut self, line: &ExtendedSummaryLine<'a>) -> u64 {
+        let key = (line.cost_centre.to_string(), line.module.to_string());
+        if let Some(&idx) = self.function_index.get(&key) {
+            return idx as u64 + 1;
+        }
+        let name = self.string(&format!("{}.{}", line.module, line.cost_centre));
+        let span = line.src.and_then(SrcSpan::parse);
+        let function = Function {
+            name,
+            file: match span {
+                Some(ref span) => self.string(span.file),
+                None => 0,
+            },
+            line: span.as_ref().map_or(0, |s| s.start_line),
+            column: span.as_ref().map_or(0, |s| s.start_col),
+        };
+        self.functions.push(function);
+        self.function_index.insert(key, self.functions.len() - 1);
+        self.functions.len() as u64
+    }
+
+    fn value_type(&mut self, name: &str, unit: &str) -> Message {
+        let mut msg = Message::new();
+        msg.uint(1, self.string(name));
+        msg.uint(2, self.string(unit));
+        msg
+    }
+
+    /// The index of `s` in the string table, added if new.
+    fn string(&mut self, s: &str) -> u64 {
+        if s.is_empty() {
+            return 0;
+        }
+        if let Some(&idx) = self.string_index.get(s) {
+            return idx;
+        }
+        let idx = self.strings.len() as u64;
+        self.strings.push(s.to_string());
+        self.string_index.insert(s.to_string(), idx);
+        idx
+    }
+}
+
+/// A protocol buffers message being encoded. Only what `profile.proto`
+/// needs is supported: unsigned integers, strings and embedded messages.
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn new() -> Message {
+        Message { buf: Vec::new() }
+    }
+
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    /// Zeros are left out, being the default value.
+    fn uint(&mut self, field: u32, v: u64) {
+        if v != 0 {
+            self.key(field, 0);
+            self.varint(v);
+        }
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, 2);
+        self.varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn message(&mut self, field: u32, msg: &Message) {
+        self.bytes(field, &msg.buf);
+    }
+
+    fn packed(&mut self, field: u32, values: &[u64]) {
+        let mut packed = Message::new();
+        for &v in values {
+            packed.varint(v);
+        }
+        self.message(field, &packed);
+    }
+
+    /// Appends fields encoded elsewhere.
+    fn raw(&mut self, buf: &[u8]) {
+        self.buf.extend_from_slice(buf);
+    }
+}
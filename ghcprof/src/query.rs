@@ -0,0 +1,301 @@
+use check::glob_matches;
+use parser::{ExtendedSummaryLine, RoseTree};
+use std::str::FromStr;
+
+/// Selects nodes of the cost centre tree by their call path, e.g.
+/// `main/**/pretty[inherited-alloc > 5]` for the calls to `pretty` from
+/// `main` allocating more than 5% of the total.
+///
+/// A query is a list of steps separated by `/`, each matching one node:
+///
+/// * a pattern with `*` and `?` wildcards, matched against the cost centre
+///   and against `Module.cost_centre`, in double quotes if it contains `/`
+///   or `[`, optionally followed by conditions on the node in brackets,
+///   such as `[entries >= 100, inherited-time > 1]`;
+/// * `**`, for any number of nodes in between.
+///
+/// Queries starting with `/` match call paths from the root, the others
+/// any part of them ending at the selected node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Descendants,
+    Node(String, Vec<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    field: Field,
+    op: Op,
+    value: f64,
+}
+
+/// A numeric column of the cost centre tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    No,
+    Entries,
+    IndividualTime,
+    IndividualAlloc,
+    InheritedTime,
+    InheritedAlloc,
+    /// Only in `+RTS -P` profiles, like `Bytes`.
+    Ticks,
+    Bytes,
+}
+
+pub const FIELDS: &[Field] = &[Field::No,
+                               Field::Entries,
+                               Field::IndividualTime,
+                               Field::IndividualAlloc,
+                               Field::InheritedTime,
+                               Field::InheritedAlloc,
+                               Field::Ticks,
+                               Field::Bytes];
+
+impl Field {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Field::No => "no",
+            Field::Entries => "entries",
+            Field::IndividualTime => "individual-time",
+            Field::IndividualAlloc => "individual-alloc",
+            Field::InheritedTime => "inherited-time",
+            Field::InheritedAlloc => "inherited-alloc",
+            Field::Ticks => "ticks",
+            Field::Bytes => "bytes",
+        }
+    }
+
+    pub fn of(&self, line: &ExtendedSummaryLine) -> Option<f64> {
+        match *self {
+            Field::No => Some(line.no as f64),
+            Field::Entries => Some(line.entries as f64),
+            Field::IndividualTime => Some(perc(line.individual_time_perc)),
+            Field::IndividualAlloc => Some(perc(line.individual_alloc_perc)),
+            Field::InheritedTime => Some(perc(line.inherited_time_perc)),
+            Field::InheritedAlloc => Some(perc(line.inherited_alloc_perc)),
+            Field::Ticks => line.ticks.map(|t| t as f64),
+            Field::Bytes => line.bytes.map(|b| b as f64),
+        }
+    }
+}
+
+/// `perc` as written in the profile: `4.4` rather than `4.400000095`, so
+/// that `individual-alloc = 4.4` holds.
+fn perc(perc: f32) -> f64 {
+    perc.to_string().parse().unwrap_or(perc as f64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// Longest first, so that `<=` isn't read as `<`.
+const OPS: &[(&str, Op)] = &[("<=", Op::Le),
+                             (">=", Op::Ge),
+                             ("!=", Op::Ne),
+                             ("==", Op::Eq),
+                             ("<", Op::Lt),
+                             (">", Op::Gt),
+                             ("=", Op::Eq)];
+
+impl Condition {
+    /// Fields missing from the profile satisfy no condition.
+    fn holds(&self, line: &ExtendedSummaryLine) -> bool {
+        let value = match self.field.of(line) {
+            Some(value) => value,
+            None => return false,
+        };
+        match self.op {
+            Op::Lt => value < self.value,
+            Op::Le => value <= self.value,
+            Op::Gt => value > self.value,
+            Op::Ge => value >= self.value,
+            Op::Eq => value == self.value,
+            Op::Ne => value != self.value,
+        }
+    }
+}
+
+impl Step {
+    fn matches(&self, line: &ExtendedSummaryLine) -> bool {
+        match *self {
+            Step::Descendants => true,
+            Step::Node(ref pattern, ref conditions) => {
+                (glob_matches(pattern, line.cost_centre) ||
+                 glob_matches(pattern, &format!("{}.{}", line.module, line.cost_centre))) &&
+                conditions.iter().all(|c| c.holds(line))
+            }
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Query, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("the query is empty".to_string());
+        }
+        let (anchored, rest) = match s.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let mut steps = if anchored { Vec::new() } else { vec![Step::Descendants] };
+        for step in split_steps(rest)? {
+            steps.push(parse_step(step)?);
+        }
+        Ok(Query { steps })
+    }
+}
+
+/// Splits on the `/` outside of quotes and brackets.
+fn split_steps(s: &str) -> Result<Vec<&str>, String> {
+    let mut steps = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' if !quoted => depth += 1,
+            ']' if !quoted && depth == 0 => return Err(format!("unopened bracket in `{}`", s)),
+            ']' if !quoted => depth -= 1,
+            '/' if !quoted && depth == 0 => {
+                steps.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if quoted {
+        return Err(format!("unclosed quote in `{}`", s));
+    }
+    if depth != 0 {
+        return Err(format!("unclosed bracket in `{}`", s));
+    }
+    steps.push(&s[start..]);
+    Ok(steps)
+}
+
+fn parse_step(step: &str) -> Result<Step, String> {
+    let trimmed = step.trim();
+    if trimmed == "**" {
+        return Ok(Step::Descendants);
+    }
+    let (pattern, rest) = if let Some(quoted) = trimmed.strip_prefix('"') {
+        match quoted.find('"') {
+            Some(end) => (&quoted[..end], quoted[end + 1..].trim()),
+            None => return Err(format!("unclosed quote in `{}`", trimmed)),
+        }
+    } else {
+        let end = trimmed.find('[').unwrap_or(trimmed.len());
+        (trimmed[..end].trim(), &trimmed[end..])
+    };
+    if pattern.is_empty() && rest.is_empty() {
+        return Err("empty step, use `*` for any cost centre or `**` for any path".to_string());
+    }
+    if pattern.contains(char::is_whitespace) && !trimmed.starts_with('"') {
+        return Err(format!("unexpected space in `{}`", trimmed));
+    }
+    if pattern == "**" {
+        return Err(format!("`**` takes no conditions, try `**/*{}`", rest));
+    }
+    let pattern = if pattern.is_empty() { "*" } else { pattern };
+    let conditions = if rest.is_empty() {
+        Vec::new()
+    } else if let Some(inner) = rest.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        let mut conditions = Vec::new();
+        for cond in inner.split(',').flat_map(|c| c.split(" and ")) {
+            conditions.push(parse_condition(cond.trim())?);
+        }
+        conditions
+    } else {
+        return Err(format!("expected conditions in brackets after `{}`, found `{}`", pattern, rest));
+    };
+    Ok(Step::Node(pattern.to_string(), conditions))
+}
+
+fn parse_condition(cond: &str) -> Result<Condition, String> {
+    let found = OPS.iter().filter_map(|&(text, op)| cond.find(text).map(|i| (i, text, op))).min_by_key(|&(i, _, _)| i);
+    let (i, text, op) = match found {
+        Some(found) => found,
+        None => return Err(format!("expected a comparison such as `entries > 100`, found `{}`", cond)),
+    };
+    let name = cond[..i].trim();
+    let field = match FIELDS.iter().find(|f| f.name() == name) {
+        Some(&field) => field,
+        None => {
+            let names: Vec<&str> = FIELDS.iter().map(|f| f.name()).collect();
+            return Err(format!("unknown field `{}`, expected one of: {}", name, names.join(", ")));
+        }
+    };
+    let raw = cond[i + text.len()..].trim();
+    let number = raw.strip_suffix('%').unwrap_or(raw);
+    match number.parse::<f64>() {
+        Ok(value) => Ok(Condition { field, op, value }),
+        Err(_) => Err(format!("expected a number after `{} {}`, found `{}`", name, text, raw)),
+    }
+}
+
+impl Query {
+    /// The nodes of `tree` the query selects, in order.
+    pub fn select<'t, 'a>(&self, tree: &'t RoseTree<ExtendedSummaryLine<'a>>) -> Vec<&'t RoseTree<ExtendedSummaryLine<'a>>> {
+        let mut start = vec![false; self.steps.len() + 1];
+        start[0] = true;
+        self.close(&mut start);
+        let mut selected = Vec::new();
+        self.visit(tree, &start, &mut selected);
+        selected
+    }
+
+    /// `states[i]` is whether the first `i` steps match the path to the
+    /// parent of `tree`.
+    fn visit<'t, 'a>(&self,
+                     tree: &'t RoseTree<ExtendedSummaryLine<'a>>,
+                     states: &[bool],
+                     selected: &mut Vec<&'t RoseTree<ExtendedSummaryLine<'a>>>) {
+        let mut next = vec![false; states.len()];
+        for (i, step) in self.steps.iter().enumerate() {
+            if !states[i] {
+                continue;
+            }
+            match *step {
+                // `**` takes the node and may take more.
+                Step::Descendants => next[i] = true,
+                _ if step.matches(&tree.value) => next[i + 1] = true,
+                _ => {}
+            }
+        }
+        self.close(&mut next);
+        if next[self.steps.len()] {
+            selected.push(tree);
+        }
+        if next[..self.steps.len()].iter().any(|&s| s) {
+            for t in &tree.sub_forest {
+                self.visit(t, &next, selected);
+            }
+        }
+    }
+
+    /// `**` may also take no node at all.
+    fn close(&self, states: &mut [bool]) {
+        for (i, step) in self.steps.iter().enumerate() {
+            if states[i] && *step == Step::Descendants {
+                states[i + 1] = true;
+            }
+        }
+    }
+}
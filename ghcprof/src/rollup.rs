@@ -0,0 +1,226 @@
+use parser::{ExtendedSummaryLine, RoseTree};
+use std::collections::HashMap;
+use transform::{add_option, own_costs};
+
+/// The costs of `tree` rolled up by module, as a tree of module prefixes:
+/// `Input` over `Input.Hoogle` and `Input.Cabal`, each over the cost
+/// centres of its module, wherever they are called from.
+///
+/// The root is the root cost centre of `tree`. A module node has the last
+/// component of its name as cost centre and the whole name as module, no
+/// source, a `no` counting down from `u32::MAX` and the entries of all the
+/// cost centres under it. Its individual costs are those of the cost
+/// centres of the module itself, its inherited costs those of its
+/// submodules too. The individual costs of a cost centre are taken from the
+/// differences between the inherited ones in `tree`, so that they add up to
+/// them exactly. Children come in the order of their first call in `tree`.
+pub fn by_module<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> RoseTree<ExtendedSummaryLine<'a>> {
+    let mut rollup = Rollup {
+        modules: vec![Module { name: "", children: Vec::new(), index: HashMap::new() }],
+        cost_centres: Vec::new(),
+        index: HashMap::new(),
+    };
+    for t in &tree.sub_forest {
+        rollup.add(t);
+    }
+    let mut root = tree.value.clone();
+    let own = own_costs(tree);
+    root.individual_time_perc = own.0 as f32;
+    root.individual_alloc_perc = own.1 as f32;
+    let (sub_forest, inherited, entries) = rollup.children(0, 1);
+    root.inherited_time_perc = (own.0 + inherited.0) as f32;
+    root.inherited_alloc_perc = (own.1 + inherited.1) as f32;
+    root.entries = root.entries.saturating_add(entries);
+    RoseTree { depth: 0, value: root, sub_forest }
+}
+
+enum Child {
+    Module(usize),
+    CostCentre(usize),
+}
+
+struct Module<'a> {
+    /// The whole name, e.g. `Input.Hoogle`.
+    name: &'a str,
+    children: Vec<Child>,
+    /// The submodules, by name.
+    index: HashMap<&'a str, usize>,
+}
+
+struct CostCentre<'a> {
+    line: ExtendedSummaryLine<'a>,
+    own: (f64, f64),
+}
+
+struct Rollup<'a> {
+    modules: Vec<Module<'a>>,
+    cost_centres: Vec<CostCentre<'a>>,
+    index: HashMap<(&'a str, &'a str), usize>,
+}
+
+impl<'a> Rollup<'a> {
+    fn add(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>) {
+        let line = &tree.value;
+        let own = own_costs(tree);
+        let key = (line.cost_centre, line.module);
+        match self.index.get(&key).cloned() {
+            Some(idx) => {
+                let cc = &mut self.cost_centres[idx];
+                cc.line.entries = cc.line.entries.saturating_add(line.entries);
+                cc.line.ticks = add_option(cc.line.ticks, line.ticks);
+                cc.line.bytes = add_option(cc.line.bytes, line.bytes);
+                cc.own.0 += own.0;
+                cc.own.1 += own.1;
+            }
+            None => {
+                let idx = self.cost_centres.len();
+                self.cost_centres.push(CostCentre { line: line.clone(), own });
+                self.index.insert(key, idx);
+                let module = self.module(line.module);
+                self.modules[module].children.push(Child::CostCentre(idx));
+            }
+        }
+        for t in &tree.sub_forest {
+            self.add(t);
+        }
+    }
+
+    /// The node of the module `name`, added along with its prefixes if new.
+    fn module(&mut self, name: &'a str) -> usize {
+        let ends = name.char_indices().filter(|&(_, c)| c == '.').map(|(i, _)| i).chain(Some(name.len()));
+        let mut parent = 0;
+        for end in ends {
+            let prefix = &name[..end];
+            parent = match self.modules[parent].index.get(prefix).cloned() {
+                Some(idx) => idx,
+                None => {
+                    let idx = self.modules.len();
+                    self.modules.push(Module { name: prefix, children: Vec::new(), index: HashMap::new() });
+                    self.modules[parent].index.insert(prefix, idx);
+                    self.modules[parent].children.push(Child::Module(idx));
+                    idx
+                }
+            };
+        }
+        parent
+    }
+
+    /// The children of the module `idx`, with their inherited costs and
+    /// entries.
+    fn children(&self, idx: usize, depth: usize) -> (Vec<RoseTree<ExtendedSummaryLine<'a>>>, (f64, f64), u32) {
+        let mut sub_forest = Vec::new();
+        let mut inherited = (0.0, 0.0);
+        let mut entries = 0u32;
+        for child in &self.modules[idx].children {
+            let (tree, costs) = match *child {
+                Child::Module(m) => self.module_tree(m, depth),
+                Child::CostCentre(c) => self.cost_centre_tree(c, depth),
+            };
+            inherited.0 += costs.0;
+            inherited.1 += costs.1;
+            entries = entries.saturating_add(tree.value.entries);
+            sub_forest.push(tree);
+        }
+        (sub_forest, inherited, entries)
+    }
+
+    fn module_tree(&self, idx: usize, depth: usize) -> (RoseTree<ExtendedSummaryLine<'a>>, (f64, f64)) {
+        let module = &self.modules[idx];
+        let mut own = (0.0, 0.0);
+        let mut ticks = None;
+        let mut bytes = None;
+        for child in &module.children {
+            if let Child::CostCentre(c) = *child {
+                let cc = &self.cost_centres[c];
+                own.0 += cc.own.0;
+                own.1 += cc.own.1;
+                ticks = add_option(ticks, cc.line.ticks);
+                bytes = add_option(bytes, cc.line.bytes);
+            }
+        }
+        // The cost centres of the module being children too.
+        let (sub_forest, inherited, entries) = self.children(idx, depth + 1);
+        let line = ExtendedSummaryLine {
+            cost_centre: module.name.rsplit('.').next().unwrap_or(module.name),
+            module: module.name,
+            src: None,
+            no: u32::MAX - idx as u32,
+            entries,
+            individual_time_perc: own.0 as f32,
+            individual_alloc_perc: own.1 as f32,
+            inherited_time_perc: inherited.0 as f32,
+            inherited_alloc_perc: inherited.1 as f32,
+            ticks,
+            bytes,
+        };
+        (RoseTree { depth, value: line, sub_forest }, inherited)
+    }
+
+    fn cost_centre_tree(&self, idx: usize, depth: usize) -> (RoseTree<ExtendedSummaryLine<'a>>, (f64, f64)) {
+        let cc = &self.cost_centres[idx];
+        let mut line = cc.line.clone();
+        line.individual_time_perc = cc.own.0 as f32;
+        line.individual_alloc_perc = cc.own.1 as f32;
+        line.inherited_time_perc = cc.own.0 as f32;
+        line.inherited_alloc_perc = cc.own.1 as f32;
+        (RoseTree { depth, value: line, sub_forest: Vec::new() }, cc.own)
+    }
+}
+
+/// The calls from the cost centres of a module to those of another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleCall<'a> {
+    pub caller: &'a str,
+    pub callee: &'a str,
+    pub entries: u64,
+    /// The inherited costs of the callees.
+    pub time_perc: f32,
+    pub alloc_perc: f32,
+}
+
+/// The calls between modules in `tree`, from each node to its children in
+/// another module, in the order of the first one. The costs of a call
+/// under another call between the same modules are already in those of the
+/// outer one, so only its entries are added.
+pub fn module_calls<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> Vec<ModuleCall<'a>> {
+    let mut calls = Vec::new();
+    add_calls(tree, &mut Vec::new(), &mut calls);
+    calls
+}
+
+/// `open` are the calls on the path to `tree`.
+fn add_calls<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                 open: &mut Vec<(&'a str, &'a str)>,
+                 calls: &mut Vec<ModuleCall<'a>>) {
+    let caller = tree.value.module;
+    for t in &tree.sub_forest {
+        let callee = &t.value;
+        let key = (caller, callee.module);
+        if caller == callee.module {
+            add_calls(t, open, calls);
+            continue;
+        }
+        let nested = open.contains(&key);
+        let idx = match calls.iter().position(|c| c.caller == caller && c.callee == callee.module) {
+            Some(idx) => idx,
+            None => {
+                calls.push(ModuleCall {
+                    caller,
+                    callee: callee.module,
+                    entries: 0,
+                    time_perc: 0.0,
+                    alloc_perc: 0.0,
+                });
+                calls.len() - 1
+            }
+        };
+        calls[idx].entries += callee.entries as u64;
+        if !nested {
+            calls[idx].time_perc += callee.inherited_time_perc;
+            calls[idx].alloc_perc += callee.inherited_alloc_perc;
+        }
+        open.push(key);
+        add_calls(t, open, calls);
+        open.pop();
+    }
+}
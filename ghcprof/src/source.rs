@@ -0,0 +1,258 @@
+use parser::{ExtendedSummaryLine, RoseTree};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// A span of source code, as found in the SRC column of GHC 8+ profiles:
+/// `file:line:col`, `file:line:col-col` or `file:(line,col)-(line,col)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrcSpan<'a> {
+    pub file: &'a str,
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl<'a> SrcSpan<'a> {
+    /// Parses a SRC column, returning `None` for placeholders such as
+    /// `<built-in>` or `<entire-module>`.
+    pub fn parse(src: &'a str) -> Option<SrcSpan<'a>> {
+        if src.starts_with('<') {
+            return None;
+        }
+        if src.ends_with(')') {
+            let idx = src.rfind(":(")?;
+            let mut ends = src[idx + 1..].split('-');
+            let (start_line, start_col) = ends.next().and_then(parse_pair)?;
+            let (end_line, end_col) = ends.next().and_then(parse_pair)?;
+            return Some(SrcSpan {
+                file: &src[..idx],
+                start_line,
+                start_col,
+                end_line,
+                end_col,
+            });
+        }
+        let mut parts = src.rsplitn(3, ':');
+        let cols = parts.next().unwrap_or("");
+        let line = parts.next().and_then(|l| l.parse().ok())?;
+        let file = match parts.next() {
+            Some(file) if !file.is_empty() => file,
+            _ => return None,
+        };
+        let mut cols = cols.split('-').map(|c| c.parse::<usize>());
+        let start_col = match cols.next() {
+            Some(Ok(col)) => col,
+            _ => return None,
+        };
+        let end_col = match cols.next() {
+            Some(Ok(col)) => col,
+            Some(Err(_)) => return None,
+            None => start_col,
+        };
+        Some(SrcSpan {
+            file,
+            start_line: line,
+            start_col,
+            end_line: line,
+            end_col,
+        })
+    }
+}
+
+fn parse_pair(s: &str) -> Option<(usize, usize)> {
+    let inner = s.strip_prefix('(')?.strip_suffix(')')?;
+    let mut nums = inner.split(',').map(|n| n.parse::<usize>());
+    match (nums.next(), nums.next(), nums.next()) {
+        (Some(Ok(line)), Some(Ok(col)), None) => Some((line, col)),
+        _ => None,
+    }
+}
+
+/// Where a cost centre is defined. `end` is the last line and column of
+/// its span, when the profile has one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub end: Option<(usize, usize)>,
+}
+
+/// Finds where a cost centre is defined. The SRC span is used when the
+/// profile has one, relative to `src_root` if given. Otherwise `src_root`
+/// is searched for the module and, within it, for the top-level binding
+/// the cost centre is named after.
+pub fn locate(src_root: Option<&Path>, cost_centre: &str, module: &str, src: Option<&str>) -> Option<Location> {
+    if let Some(span) = src.and_then(SrcSpan::parse) {
+        let file = match src_root {
+            Some(root) if root.join(span.file).is_file() => root.join(span.file),
+            _ => PathBuf::from(span.file),
+        };
+        return Some(Location {
+            file,
+            line: span.start_line,
+            column: span.start_col,
+            end: Some((span.end_line, span.end_col)),
+        });
+    }
+    let root = src_root?;
+    let file = find_module(root, module)?;
+    let mut contents = String::new();
+    let line = match File::open(&file).and_then(|mut f| f.read_to_string(&mut contents)) {
+        Ok(_) => binding_name(cost_centre).and_then(|name| find_binding(&contents, name)),
+        Err(_) => None,
+    };
+    Some(Location { file, line: line.unwrap_or(1), column: 1, end: None })
+}
+
+/// Directories which never hold the sources of the profiled program.
+const IGNORED_DIRS: &[&str] = &["dist", "dist-newstyle", ".stack-work", ".git"];
+
+/// Looks for `A/B/C.hs` (or `.lhs`) under `root`, at any depth, so that
+/// the usual `src/` or `lib/` directories don't need to be spelled out.
+pub fn find_module(root: &Path, module: &str) -> Option<PathBuf> {
+    let base: PathBuf = module.split('.').collect();
+    let candidates = [base.with_extension("hs"), base.with_extension("lhs")];
+    search(root, &candidates)
+}
+
+fn search(dir: &Path, candidates: &[PathBuf]) -> Option<PathBuf> {
+    for candidate in candidates {
+        let path = dir.join(candidate);
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+    let mut subdirs: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => {
+            entries.filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect()
+        }
+        Err(_) => return None,
+    };
+    subdirs.sort();
+    for subdir in subdirs {
+        let ignored = subdir.file_name()
+            .and_then(|n| n.to_str())
+            .is_none_or(|n| IGNORED_DIRS.contains(&n));
+        if ignored {
+            continue;
+        }
+        if let Some(path) = search(&subdir, candidates) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// The top-level binding a cost centre belongs to: `f` for `f.g.\`, or the
+/// whole name for operators. CAFs don't belong to any binding.
+pub fn binding_name(cost_centre: &str) -> Option<&str> {
+    if cost_centre == "CAF" || cost_centre.is_empty() {
+        return None;
+    }
+    if is_ident_start(cost_centre.chars().next().unwrap()) {
+        cost_centre.split('.').next()
+    } else {
+        Some(cost_centre)
+    }
+}
+
+/// The line (counting from 1) where `name` is first defined at the top
+/// level of `contents`, either prefix (`name x = ...`, `(op) x y = ...`,
+/// or a type signature) or infix (`x op y = ...`, ``x `name` y = ...``).
+pub fn find_binding(contents: &str, name: &str) -> Option<usize> {
+    let prefix = if is_ident_start(name.chars().next().unwrap_or(' ')) {
+        name.to_string()
+    } else {
+        format!("({})", name)
+    };
+    let infix = if prefix == name { format!("`{}`", name) } else { name.to_string() };
+    contents.lines()
+        .position(|line| {
+            if line.starts_with(|c: char| c.is_whitespace()) || line.starts_with("--") {
+                return false;
+            }
+            let defines_prefix = line.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| !rest.starts_with(is_ident_char));
+            defines_prefix || line.split_whitespace().nth(1) == Some(infix.as_str())
+        })
+        .map(|idx| idx + 1)
+}
+
+const KEYWORDS: &[&str] = &["module", "import", "data", "type", "newtype", "class",
+                            "instance", "deriving", "infix", "infixl", "infixr",
+                            "foreign", "default", "where", "pattern"];
+
+/// The top-level definitions of a module, as the line (counting from 1)
+/// where each starts and the name it defines. A type signature and the
+/// equations following it count as one definition.
+pub fn definitions(contents: &str) -> Vec<(usize, &str)> {
+    let mut defs: Vec<(usize, &str)> = Vec::new();
+    for (idx, line) in contents.lines().enumerate() {
+        if line.starts_with(|c: char| c.is_whitespace()) || line.starts_with("--") ||
+           line.starts_with("{-") || line.starts_with('#') {
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let first = match tokens.next() {
+            Some(first) => first,
+            None => continue,
+        };
+        let name = match tokens.next() {
+            Some(second) if second.len() > 2 && second.starts_with('`') && second.ends_with('`') => {
+                &second[1..second.len() - 1]
+            }
+            Some(second) if is_operator(second) => second,
+            _ if first.starts_with('(') => first[1..].split(')').next().unwrap_or(""),
+            _ => first.split(|c: char| !is_ident_char(c)).next().unwrap_or(""),
+        };
+        if name.is_empty() || KEYWORDS.contains(&name) || defs.last().is_some_and(|&(_, prev)| prev == name) {
+            continue;
+        }
+        defs.push((idx + 1, name));
+    }
+    defs
+}
+
+fn is_operator(token: &str) -> bool {
+    token != "=" && token != "::" && token != "|" && token != "<-" &&
+    token.chars().all(|c| "!#$%&*+./<=>?@\\^|-~:".contains(c))
+}
+
+/// The individual time and alloc percentages of the cost centres of
+/// `module`, summed by the top-level binding they belong to.
+pub fn binding_costs<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, module: &str) -> HashMap<&'a str, (f32, f32)> {
+    let mut costs = HashMap::new();
+    add_binding_costs(tree, module, &mut costs);
+    costs
+}
+
+fn add_binding_costs<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                         module: &str,
+                         costs: &mut HashMap<&'a str, (f32, f32)>) {
+    let line = &tree.value;
+    if line.module == module {
+        if let Some(name) = binding_name(line.cost_centre) {
+            let cost = costs.entry(name).or_insert((0.0, 0.0));
+            cost.0 += line.individual_time_perc;
+            cost.1 += line.individual_alloc_perc;
+        }
+    }
+    for t in &tree.sub_forest {
+        add_binding_costs(t, module, costs);
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '\''
+}
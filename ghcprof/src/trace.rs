@@ -0,0 +1,66 @@
+use flame::layout;
+use metric::Metric;
+use parser::{ExtendedSummary, GHCProf};
+use std::io::{self, Write};
+
+/// Writes `prof` in the Chrome Trace Event format, for `chrome://tracing`
+/// and Perfetto. The cost centre tree becomes nested complete events on a
+/// single thread, each as long as its inherited time, its children one
+/// after the other from its start, as laid out by `flame::layout`. The
+/// whole program lasts the total time of the profile, and events start and
+/// end on whole microseconds. Nodes costing no time are left out.
+///
+/// There is one event per line, in the order of the tree, so that the
+/// output is the same for the same profile.
+pub fn write_trace<'a, W: Write>(out: &mut W, prof: &GHCProf<'a>) -> io::Result<()> {
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    let tt = &prof.header.total_time;
+    // In microseconds, which is what timestamps are in.
+    let total = tt.ticks as f64 * tt.freq as f64;
+    let program = prof.header.program.trim();
+
+    writeln!(out, "{{\"traceEvents\":[")?;
+    write!(out, "{{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{{\"name\":{}}}}}",
+           json_string(program))?;
+    // Rounding both ends keeps children within their parents.
+    let micros = |perc: f64| (perc * total / 100.0).round() as u64;
+    for r in layout(tree, Metric::Time, 0.0) {
+        let line = &r.node.value;
+        let (start, end) = (micros(r.start), micros(r.start + r.width));
+        writeln!(out, ",")?;
+        write!(out,
+               "{{\"name\":{},\"cat\":{},\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":1,\
+                \"args\":{{\"module\":{},\"src\":{},\"no\":{},\"entries\":{},\
+                \"individual %time\":{:.1},\"individual %alloc\":{:.1},\
+                \"inherited %time\":{:.1},\"inherited %alloc\":{:.1}}}}}",
+               json_string(line.cost_centre),
+               json_string(line.module),
+               start,
+               end - start,
+               json_string(line.module),
+               json_string(line.src.unwrap_or("")),
+               line.no,
+               line.entries,
+               line.individual_time_perc,
+               line.individual_alloc_perc,
+               line.inherited_time_perc,
+               line.inherited_alloc_perc)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "],\"displayTimeUnit\":\"ms\",\"otherData\":{{\"program\":{}}}}}", json_string(program))
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
@@ -0,0 +1,296 @@
+use check::glob_matches;
+use parser::{ExtendedSummaryLine, RoseTree};
+use std::cmp::max;
+use std::collections::HashMap;
+
+/// A cost centre tree with recursive calls collapsed.
+#[derive(Debug, Clone)]
+pub struct Collapsed<'a> {
+    pub tree: RoseTree<ExtendedSummaryLine<'a>>,
+    /// How many nested frames of each recursive cost centre, by `no`, were
+    /// collapsed into it at most.
+    pub recursion: HashMap<u32, usize>,
+}
+
+/// Merges each call to a cost centre from below itself, such as
+/// `go → go → go` or `even → odd → even`, into the outer call: its
+/// individual costs and entries are added to the outer call's, and its
+/// children become the outer call's. The outer calls keep their `no`.
+///
+/// Inherited costs are recomputed, from their differences in the original
+/// tree rather than the rounded individual costs, so they stay the same
+/// wherever the tree didn't change.
+pub fn collapse_recursion<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> Collapsed<'a> {
+    let mut arena = Arena { nodes: Vec::new(), recursion: HashMap::new() };
+    let root = arena.add_child(None, tree);
+    let mut stack = vec![(root, 1)];
+    for t in &tree.sub_forest {
+        arena.collapse(t, root, &mut stack);
+    }
+    Collapsed { tree: arena.to_rose_tree(root, 0).0, recursion: arena.recursion }
+}
+
+/// Which cost centres to leave out of the tree, e.g. those of libraries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HideRules {
+    /// Patterns on `Module.cost_centre`, with `*` and `?` wildcards, such
+    /// as `GHC.*` or `*.CAF`.
+    pub hide: Vec<String>,
+    /// Exceptions to `hide`.
+    pub keep: Vec<String>,
+}
+
+impl HideRules {
+    pub fn hides(&self, cost_centre: &str, module: &str) -> bool {
+        let name = format!("{}.{}", module, cost_centre);
+        self.hide.iter().any(|p| glob_matches(p, &name)) && !self.keep.iter().any(|p| glob_matches(p, &name))
+    }
+}
+
+/// Leaves out the cost centres `rules` hide, except the root: their
+/// individual costs go to their closest visible caller, and their children
+/// become its own. Calls to the same cost centre from the same caller are
+/// merged, as in `collapse_recursion`, and the inherited costs stay the
+/// same.
+pub fn hide<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, rules: &HideRules) -> RoseTree<ExtendedSummaryLine<'a>> {
+    let mut arena = Arena { nodes: Vec::new(), recursion: HashMap::new() };
+    let root = arena.add_child(None, tree);
+    for t in &tree.sub_forest {
+        arena.hide(t, root, rules);
+    }
+    arena.to_rose_tree(root, 0).0
+}
+
+/// Whether `cost_centre` is a CAF, a top-level constant evaluated once and
+/// shared: `CAF` for a whole module, or `CAF:name` with `-fprof-cafs`.
+pub fn is_caf(cost_centre: &str) -> bool {
+    cost_centre == "CAF" || cost_centre.starts_with("CAF:")
+}
+
+/// The CAFs under the root of `tree`, grouped by module: one `CAF` node per
+/// module, with the `no` of its first CAF and the costs and entries of all
+/// of them, over the children of its `CAF` nodes and its `CAF:name` nodes.
+/// The other cost centres are left out, so that the root has the total cost
+/// of CAFs.
+pub fn group_cafs<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> RoseTree<ExtendedSummaryLine<'a>> {
+    let mut arena = Arena { nodes: Vec::new(), recursion: HashMap::new() };
+    let root = arena.add_child(None, tree);
+    arena.clear(root);
+    for t in tree.sub_forest.iter().filter(|t| is_caf(t.value.cost_centre)) {
+        let existing = arena.nodes[root].children.iter().cloned().find(|&c| arena.nodes[c].line.module == t.value.module);
+        let group = match existing {
+            Some(idx) => idx,
+            None => {
+                let idx = arena.add_child(Some(root), t);
+                arena.clear(idx);
+                arena.nodes[idx].line.cost_centre = "CAF";
+                idx
+            }
+        };
+        if t.value.cost_centre == "CAF" {
+            arena.absorb(group, t);
+            for c in &t.sub_forest {
+                arena.copy(c, group);
+            }
+        } else {
+            arena.copy(t, group);
+        }
+    }
+    arena.to_rose_tree(root, 0).0
+}
+
+/// Leaves the costs of CAFs out of the percentages: CAFs keep their entries
+/// and children but cost nothing themselves, and the other percentages are
+/// scaled up to add up to 100% again. Ticks and bytes stay absolute.
+pub fn exclude_cafs<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> RoseTree<ExtendedSummaryLine<'a>> {
+    let cafs = caf_costs(tree);
+    let scale = |caf: f64| if caf < 100.0 { 100.0 / (100.0 - caf) } else { 1.0 };
+    without_cafs(tree, (scale(cafs.0), scale(cafs.1))).0
+}
+
+/// The share of `tree` spent in CAFs themselves, rather than their children.
+fn caf_costs(tree: &RoseTree<ExtendedSummaryLine>) -> (f64, f64) {
+    let own = if is_caf(tree.value.cost_centre) { own_costs(tree) } else { (0.0, 0.0) };
+    tree.sub_forest.iter().map(caf_costs).fold(own, |acc, c| (acc.0 + c.0, acc.1 + c.1))
+}
+
+fn without_cafs<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                    scale: (f64, f64))
+                    -> (RoseTree<ExtendedSummaryLine<'a>>, (f64, f64)) {
+    let mut line = tree.value.clone();
+    let mut inherited = if is_caf(line.cost_centre) {
+        line.individual_time_perc = 0.0;
+        line.individual_alloc_perc = 0.0;
+        line.ticks = line.ticks.map(|_| 0);
+        line.bytes = line.bytes.map(|_| 0);
+        (0.0, 0.0)
+    } else {
+        line.individual_time_perc = (line.individual_time_perc as f64 * scale.0) as f32;
+        line.individual_alloc_perc = (line.individual_alloc_perc as f64 * scale.1) as f32;
+        let own = own_costs(tree);
+        (own.0 * scale.0, own.1 * scale.1)
+    };
+    let mut sub_forest = Vec::new();
+    for t in &tree.sub_forest {
+        let (sub, costs) = without_cafs(t, scale);
+        inherited.0 += costs.0;
+        inherited.1 += costs.1;
+        sub_forest.push(sub);
+    }
+    line.inherited_time_perc = inherited.0 as f32;
+    line.inherited_alloc_perc = inherited.1 as f32;
+    (RoseTree { depth: tree.depth, value: line, sub_forest }, inherited)
+}
+
+struct Node<'a> {
+    line: ExtendedSummaryLine<'a>,
+    /// The inherited costs of the original nodes merged here, minus those of
+    /// their children.
+    own: (f64, f64),
+    children: Vec<usize>,
+}
+
+struct Arena<'a> {
+    nodes: Vec<Node<'a>>,
+    recursion: HashMap<u32, usize>,
+}
+
+impl<'a> Arena<'a> {
+    /// Adds `tree` to the children of `parent` in the new tree, `stack`
+    /// being the path to `parent` with how many frames of each node are on
+    /// it.
+    fn collapse(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>, parent: usize, stack: &mut Vec<(usize, usize)>) {
+        let line = &tree.value;
+        let ancestor = stack.iter().rev().cloned().find(|&(idx, _)| self.same_cost_centre(idx, line));
+        let target = match ancestor {
+            Some((idx, frames)) => {
+                self.absorb(idx, tree);
+                let no = self.nodes[idx].line.no;
+                let depth = self.recursion.entry(no).or_insert(1);
+                *depth = max(*depth, frames + 1);
+                (idx, frames + 1)
+            }
+            None => (self.child(parent, tree), 1),
+        };
+        stack.push(target);
+        for t in &tree.sub_forest {
+            self.collapse(t, target.0, stack);
+        }
+        stack.pop();
+    }
+
+    /// Adds `tree` to the children of `parent` in the new tree, leaving out
+    /// the cost centres `rules` hide.
+    fn hide(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>, parent: usize, rules: &HideRules) {
+        let target = if rules.hides(tree.value.cost_centre, tree.value.module) {
+            // The entries of the caller stay its own.
+            let entries = self.nodes[parent].line.entries;
+            self.absorb(parent, tree);
+            self.nodes[parent].line.entries = entries;
+            parent
+        } else {
+            self.child(parent, tree)
+        };
+        for t in &tree.sub_forest {
+            self.hide(t, target, rules);
+        }
+    }
+
+    /// The child of `parent` for `tree`: a new one, or the one with the same
+    /// cost centre, which gets its costs.
+    fn child(&mut self, parent: usize, tree: &RoseTree<ExtendedSummaryLine<'a>>) -> usize {
+        let sibling = self.nodes[parent].children.iter().cloned().find(|&c| self.same_cost_centre(c, &tree.value));
+        match sibling {
+            Some(idx) => {
+                self.absorb(idx, tree);
+                idx
+            }
+            None => self.add_child(Some(parent), tree),
+        }
+    }
+
+    /// Adds `tree` to the children of `parent` in the new tree as it is.
+    fn copy(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>, parent: usize) {
+        let idx = self.child(parent, tree);
+        for t in &tree.sub_forest {
+            self.copy(t, idx);
+        }
+    }
+
+    /// Makes the node `idx` cost nothing itself.
+    fn clear(&mut self, idx: usize) {
+        let node = &mut self.nodes[idx];
+        node.own = (0.0, 0.0);
+        node.line.entries = 0;
+        node.line.individual_time_perc = 0.0;
+        node.line.individual_alloc_perc = 0.0;
+        node.line.ticks = node.line.ticks.map(|_| 0);
+        node.line.bytes = node.line.bytes.map(|_| 0);
+    }
+
+    fn same_cost_centre(&self, idx: usize, line: &ExtendedSummaryLine) -> bool {
+        let other = &self.nodes[idx].line;
+        other.cost_centre == line.cost_centre && other.module == line.module
+    }
+
+    fn add_child(&mut self, parent: Option<usize>, tree: &RoseTree<ExtendedSummaryLine<'a>>) -> usize {
+        let idx = self.nodes.len();
+        self.nodes.push(Node { line: tree.value.clone(), own: own_costs(tree), children: Vec::new() });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(idx);
+        }
+        idx
+    }
+
+    /// Adds the individual costs of `tree` to the node `idx`.
+    fn absorb(&mut self, idx: usize, tree: &RoseTree<ExtendedSummaryLine<'a>>) {
+        let own = own_costs(tree);
+        let node = &mut self.nodes[idx];
+        add_individual(&mut node.line, &tree.value);
+        node.own.0 += own.0;
+        node.own.1 += own.1;
+    }
+
+    /// Returns the tree rooted at `idx` and its inherited costs.
+    fn to_rose_tree(&self, idx: usize, depth: usize) -> (RoseTree<ExtendedSummaryLine<'a>>, (f64, f64)) {
+        let node = &self.nodes[idx];
+        let mut inherited = node.own;
+        let mut sub_forest = Vec::new();
+        for &c in &node.children {
+            let (tree, costs) = self.to_rose_tree(c, depth + 1);
+            inherited.0 += costs.0;
+            inherited.1 += costs.1;
+            sub_forest.push(tree);
+        }
+        let mut line = node.line.clone();
+        line.inherited_time_perc = inherited.0 as f32;
+        line.inherited_alloc_perc = inherited.1 as f32;
+        (RoseTree { depth, value: line, sub_forest }, inherited)
+    }
+}
+
+/// The inherited %time and %alloc of `tree` not inherited from its
+/// children. Unlike the individual ones, they add up to the inherited costs
+/// of any subtree exactly.
+pub(crate) fn own_costs(tree: &RoseTree<ExtendedSummaryLine>) -> (f64, f64) {
+    let line = &tree.value;
+    tree.sub_forest.iter().fold((line.inherited_time_perc as f64, line.inherited_alloc_perc as f64), |acc, t| {
+        (acc.0 - t.value.inherited_time_perc as f64, acc.1 - t.value.inherited_alloc_perc as f64)
+    })
+}
+
+fn add_individual<'a>(line: &mut ExtendedSummaryLine<'a>, other: &ExtendedSummaryLine<'a>) {
+    line.entries = line.entries.saturating_add(other.entries);
+    line.individual_time_perc += other.individual_time_perc;
+    line.individual_alloc_perc += other.individual_alloc_perc;
+    line.ticks = add_option(line.ticks, other.ticks);
+    line.bytes = add_option(line.bytes, other.bytes);
+}
+
+pub(crate) fn add_option(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
@@ -0,0 +1,126 @@
+use parser::{ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree, Summary};
+use std::cmp::max;
+use std::io::{self, Write};
+
+/// Writes `prof` in the .prof format GHC uses, which `parser` reads back.
+/// The SRC column is only written when the profile has one, and so are the
+/// `ticks` and `bytes` columns of `+RTS -P`.
+pub fn write_prof<'a, W: Write>(out: &mut W, prof: &GHCProf<'a>) -> io::Result<()> {
+    let header = &prof.header;
+    let tt = &header.total_time;
+    writeln!(out, "\t{}\n", header.title)?;
+    writeln!(out, "\t   {}\n", header.program)?;
+    writeln!(out,
+             "\ttotal time  = {:>11.2} secs   ({} ticks @ {} us, {} processor{})",
+             tt.time,
+             tt.ticks,
+             tt.freq,
+             tt.procs,
+             if tt.procs == 1 { "" } else { "s" })?;
+    writeln!(out,
+             "\ttotal alloc = {} bytes  (excludes profiling overheads)\n",
+             thousands(header.total_alloc.bytes))?;
+    write_summary(out, &prof.summary)?;
+    write_extended_summary(out, &prof.extended_summary)
+}
+
+fn write_summary<'a, W: Write>(out: &mut W, Summary(lines): &Summary<'a>) -> io::Result<()> {
+    let cc_width = lines.iter().map(|l| l.cost_centre.len()).fold("COST CENTRE".len(), max);
+    let mo_width = lines.iter().map(|l| l.module.len()).fold("MODULE".len(), max);
+    let src_width = lines.iter().filter_map(|l| l.src).map(|s| s.len()).fold(0, max);
+    let has_src = lines.iter().any(|l| l.src.is_some());
+    let has_ticks = lines.iter().any(|l| l.ticks.is_some());
+
+    write!(out, "{} {} ", pad("COST CENTRE", cc_width), pad("MODULE", mo_width))?;
+    if has_src {
+        write!(out, "{} ", pad("SRC", max(src_width, 3)))?;
+    }
+    write!(out, "%time %alloc")?;
+    if has_ticks {
+        write!(out, "        ticks          bytes")?;
+    }
+    writeln!(out, "\n")?;
+    for line in lines {
+        write!(out, "{} {} ", pad(line.cost_centre, cc_width), pad(line.module, mo_width))?;
+        if has_src {
+            write!(out, "{} ", pad(line.src.unwrap_or("<no location info>"), max(src_width, 3)))?;
+        }
+        write!(out, "{:>5.1} {:>6.1}", line.time_perc, line.alloc_perc)?;
+        if has_ticks {
+            write!(out, " {:>12} {:>14}", line.ticks.unwrap_or(0), line.bytes.unwrap_or(0))?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "\n")
+}
+
+fn write_extended_summary<'a, W: Write>(out: &mut W, ExtendedSummary(tree): &ExtendedSummary<'a>) -> io::Result<()> {
+    let mut lines = Vec::new();
+    flatten(tree, &mut lines);
+    let cc_width = lines.iter().map(|&(d, l)| d + l.cost_centre.len()).fold("COST CENTRE".len(), max);
+    let mo_width = lines.iter().map(|&(_, l)| l.module.len()).fold("MODULE".len(), max);
+    let src_width = lines.iter().filter_map(|&(_, l)| l.src).map(|s| s.len()).fold(3, max);
+    let has_src = lines.iter().any(|&(_, l)| l.src.is_some());
+    let has_ticks = lines.iter().any(|&(_, l)| l.ticks.is_some());
+
+    let mut columns = format!("{} {} ", pad("COST CENTRE", cc_width), pad("MODULE", mo_width));
+    if has_src {
+        columns.push_str(&format!("{} ", pad("SRC", src_width)));
+    }
+    let groups_at = columns.len() + 25;
+    columns.push_str("    no.     entries  %time %alloc   %time %alloc");
+    if has_ticks {
+        columns.push_str("        ticks          bytes");
+    }
+    writeln!(out, "{}individual      inherited", pad("", groups_at))?;
+    writeln!(out, "{}\n", columns)?;
+
+    for &(depth, line) in &lines {
+        write!(out,
+               "{}{} {} ",
+               pad("", depth),
+               pad(line.cost_centre, cc_width - depth),
+               pad(line.module, mo_width))?;
+        if has_src {
+            write!(out, "{} ", pad(line.src.unwrap_or("<no location info>"), src_width))?;
+        }
+        write!(out,
+               "{:>7} {:>11} {:>6.1} {:>6.1} {:>7.1} {:>6.1}",
+               line.no,
+               line.entries,
+               line.individual_time_perc,
+               line.individual_alloc_perc,
+               line.inherited_time_perc,
+               line.inherited_alloc_perc)?;
+        if has_ticks {
+            write!(out, " {:>12} {:>14}", line.ticks.unwrap_or(0), line.bytes.unwrap_or(0))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The lines of the tree in order, with their depth.
+fn flatten<'t, 'a>(tree: &'t RoseTree<ExtendedSummaryLine<'a>>, lines: &mut Vec<(usize, &'t ExtendedSummaryLine<'a>)>) {
+    lines.push((tree.depth, &tree.value));
+    for t in &tree.sub_forest {
+        flatten(t, lines);
+    }
+}
+
+fn pad(s: &str, width: usize) -> String {
+    format!("{:<1$}", s, width)
+}
+
+/// `1234567` as `1,234,567`.
+fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut res = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            res.push(',');
+        }
+        res.push(c);
+    }
+    res
+}
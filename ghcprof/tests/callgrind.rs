@@ -0,0 +1,86 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile};
+use ghcprof::callgrind::write_callgrind;
+use ghcprof::parser::GHCProf;
+
+fn callgrind(prof: &GHCProf) -> String {
+    let mut out = Vec::new();
+    write_callgrind(&mut out, prof).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn functions_and_calls() {
+    let input = read_profile("../example_format/recursive.prof");
+    let out = callgrind(&parse_profile(&input));
+    assert!(out.starts_with("# callgrind format\n\
+                             version: 1\n\
+                             creator: profiv\n\
+                             cmd: recursive +RTS -p -RTS\n\
+                             positions: line\n\
+                             event: time : Time (microseconds)\n\
+                             event: alloc : Allocation (bytes)\n\
+                             events: time alloc\n\
+                             summary: 1000000 100000000\n"));
+    // main, with its own costs at its line, then its calls.
+    assert!(out.contains("\nfl=(2)\n\
+                          fn=(3)\n\
+                          5 50000 5000000\n\
+                          cfi=(2)\n\
+                          cfn=(4) Main.go\n\
+                          calls=1 10\n\
+                          5 600000 60000000\n\
+                          cfi=(2)\n\
+                          cfn=(5) Main.even\n\
+                          calls=1 13\n\
+                          5 350000 35000000\n\
+                          \n"));
+    // Names are given once, with their ids.
+    assert!(out.contains("cfi=(2) src/Main.hs\ncfn=(3) Main.main\n"));
+    assert_eq!(out.matches("Main.go").count(), 1);
+    assert_eq!(out.matches("\nfn=(4)\n").count(), 4);
+}
+
+#[test]
+fn individual_costs_add_up_to_the_summary() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let out = callgrind(&parse_profile(&input));
+    let lines: Vec<&str> = out.lines().collect();
+    let mut total = [0u64, 0];
+    for (i, l) in lines.iter().enumerate() {
+        if l.starts_with("fn=") {
+            for (sum, cost) in total.iter_mut().zip(lines[i + 1].split(' ').skip(1)) {
+                *sum += cost.parse::<u64>().unwrap();
+            }
+        }
+    }
+    assert_eq!(format!("summary: {} {}", total[0], total[1]),
+               lines.iter().find(|l| l.starts_with("summary:")).unwrap().to_string());
+    // 449 ticks of 1ms, and the bytes rounded in each node.
+    assert_eq!(total[0], 449000);
+    assert!((total[1] as f64 - 1073546184.0).abs() < 1073546184.0 * 1e-3);
+}
+
+#[test]
+fn ticks_and_bytes_when_present() {
+    let input = read_profile("../example_format/ghc8-ticks.prof");
+    let out = callgrind(&parse_profile(&input));
+    assert!(out.contains("event: ticks : Ticks\nevent: bytes : Bytes\nevents: time alloc ticks bytes\n"));
+    assert!(out.contains("\nsummary: 449000 1073546184 449 1073546184\n"));
+    assert!(out.contains("cfn=(8) Main.countWords.bump\ncalls=181733 18\n"));
+    assert!(out.contains("\nfn=(8)\n18 218000 591523944 218 591523944\n"));
+}
+
+#[test]
+fn missing_ticks_leave_bytes_in_their_slot() {
+    let input = read_profile("../example_format/ghc8-ticks.prof");
+    let mut prof = parse_profile(&input);
+    prof.extended_summary.0.sub_forest[2].sub_forest[0].sub_forest[0].sub_forest[0].value.ticks = None;
+    let out = callgrind(&prof);
+    let bump = out.lines().skip_while(|l| *l != "fn=(8)").nth(1).unwrap();
+    // The time comes from the percentage then.
+    assert_eq!(bump, "18 218214 591523944 0 591523944");
+}
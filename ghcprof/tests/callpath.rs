@@ -0,0 +1,55 @@
+
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::callpath::*;
+
+#[test]
+fn can_compute_call_paths() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    assert_eq!(call_path(tree, 215),
+               Some(vec![("MAIN", "MAIN"),
+                         ("CAF", "Main"),
+                         ("main", "Main"),
+                         ("countWords", "Main"),
+                         ("countWords.bump", "Main")]));
+    assert_eq!(call_path(tree, 106), Some(vec![("MAIN", "MAIN")]));
+    assert_eq!(call_path(tree, 9999), None);
+}
+
+#[test]
+fn can_follow_call_paths() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+
+    let path = call_path(tree, 213).unwrap();
+    let nodes = follow(tree, &path);
+    assert_eq!(nodes.len(), 4);
+    assert_eq!(nodes[3].value.no, 213);
+
+    // Only the prefix which exists is followed.
+    let nodes = follow(tree, &[("MAIN", "MAIN"), ("CAF", "Main"), ("main", "Main"), ("go", "Main")]);
+    assert_eq!(nodes.iter().map(|n| n.value.no).collect::<Vec<_>>(), vec![106, 211, 212]);
+
+    // Modules tell cost centres apart.
+    assert!(follow(tree, &[("MAIN", "MAIN"), ("CAF", "Data.Map")]).len() == 1);
+    assert!(follow(tree, &[("main", "Main")]).is_empty());
+}
+
+#[test]
+fn call_paths_match_across_profiles() {
+    let first = read_profile("../example_format/rncryptor-tests.prof");
+    let first = parse_profile(&first);
+    let second = read_profile("../example_format/hoogle.prof");
+    let second = parse_profile(&second);
+    let other = tree(&second);
+    let tree = tree(&first);
+    // Both profiles share the root, and nothing more.
+    let path = call_path(tree, tree.sub_forest[0].value.no).unwrap();
+    assert_eq!(follow(other, &path).len(), 1);
+}
@@ -0,0 +1,156 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile};
+use ghcprof::check::{compare, evaluate, glob_matches, write_junit, write_text, Budget, Comparison, Granularity,
+                     Measure, Rule, TestCase};
+use ghcprof::metric::Metric;
+
+fn find<'c>(comparisons: &'c [Comparison], granularity: Granularity, name: &str, metric: Metric) -> &'c Comparison {
+    comparisons.iter()
+        .find(|c| c.granularity == granularity && c.name == name && c.metric == metric)
+        .expect(name)
+}
+
+fn run_budget(time: f32, alloc: f32, scope: &[&str]) -> Vec<Comparison> {
+    let budget = Budget {
+        scope: scope.iter().map(|s| s.to_string()).collect(),
+        max_time_increase: Some(time),
+        max_alloc_increase: Some(alloc),
+        ..Budget::default()
+    };
+    let baseline = read_profile("../example_format/ghc8.prof");
+    let current = read_profile("../example_format/ghc8-run2.prof");
+    compare(&parse_profile(&baseline),
+            &parse_profile(&current),
+            &budget)
+}
+
+#[test]
+fn glob_patterns() {
+    assert!(glob_matches("Main.*", "Main.countWords.bump"));
+    assert!(glob_matches("*.CAF", "GHC.IO.Encoding.CAF"));
+    assert!(glob_matches("Main.main", "Main.main"));
+    assert!(glob_matches("Main.mai?", "Main.main"));
+    assert!(!glob_matches("Main.*", "GHC.IO.Encoding.CAF"));
+    assert!(!glob_matches("Main.main", "Main.mainLoop"));
+}
+
+#[test]
+fn compares_call_paths_with_inherited_costs() {
+    let comparisons = run_budget(5.0, 10.0, &[]);
+    // tokenize got cheaper, but its new lambda makes the subtree dearer.
+    let path = "MAIN.MAIN > Main.CAF > Main.main > Main.tokenize";
+    let c = find(&comparisons, Granularity::CallPath, path, Metric::Time);
+    assert!((c.baseline - 0.301 * 449_000.0).abs() < 1.0, "{}", c.baseline);
+    assert!((c.current - 0.327 * 551_000.0).abs() < 1.0, "{}", c.current);
+    assert!(c.failed());
+
+    let flat = find(&comparisons, Granularity::CostCentre, "Main.tokenize", Metric::Time);
+    assert!(flat.increase() < 0.0);
+    assert!(!flat.failed());
+}
+
+#[test]
+fn new_cost_centres_fail_and_cheap_ones_are_skipped() {
+    let comparisons = run_budget(5.0, 10.0, &[]);
+    let lambda = find(&comparisons, Granularity::CostCentre, "Main.tokenize.\\", Metric::Time);
+    assert_eq!(lambda.baseline, 0.0);
+    assert!(lambda.increase().is_infinite() && lambda.failed());
+    // Under 1% of the total in both runs.
+    assert!(comparisons.iter().all(|c| !c.name.ends_with("GHC.IO.Handle.FD.CAF")));
+}
+
+#[test]
+fn removed_cost_centres_cost_nothing_now() {
+    let budget = Budget { max_time_increase: Some(0.0), ..Budget::default() };
+    let baseline = read_profile("../example_format/ghc8-run2.prof");
+    let current = read_profile("../example_format/ghc8.prof");
+    let comparisons = compare(&parse_profile(&baseline),
+                              &parse_profile(&current),
+                              &budget);
+    let lambda = find(&comparisons, Granularity::CostCentre, "Main.tokenize.\\", Metric::Time);
+    assert_eq!(lambda.current, 0.0);
+    assert!(lambda.baseline > 0.0 && !lambda.failed());
+    let path = "MAIN.MAIN > Main.CAF > Main.main > Main.tokenize > Main.tokenize.\\";
+    assert_eq!(find(&comparisons, Granularity::CallPath, path, Metric::Time).current, 0.0);
+}
+
+#[test]
+fn scope_and_limits() {
+    let comparisons = run_budget(50.0, 50.0, &["Main.countWords*"]);
+    assert!(!comparisons.is_empty());
+    assert!(comparisons.iter().all(|c| c.name.contains("Main.countWords")));
+    assert!(comparisons.iter().all(|c| !c.failed()));
+
+    let cases: Vec<TestCase> = comparisons.iter().map(|c| c.test_case()).collect();
+    let mut out = Vec::new();
+    write_text(&mut out, &cases).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               format!("All {} checks within budget.\n", comparisons.len()));
+}
+
+#[test]
+fn junit_report() {
+    let comparisons = run_budget(5.0, 10.0, &["Main.tokenize*"]);
+    let cases: Vec<TestCase> = comparisons.iter().map(|c| c.test_case()).collect();
+    let mut out = Vec::new();
+    write_junit(&mut out, &cases).unwrap();
+    let xml = String::from_utf8(out).unwrap();
+    let failures = comparisons.iter().filter(|c| c.failed()).count();
+    assert!(xml.starts_with("<?xml"));
+    assert!(xml.contains(&format!("<testsuites name=\"profiv check\" tests=\"{}\" failures=\"{}\">",
+                                  comparisons.len(),
+                                  failures)));
+    assert!(xml.contains("name=\"MAIN.MAIN &gt; Main.CAF &gt; Main.main &gt; Main.tokenize (time)\">"));
+    assert_eq!(xml.matches("<failure ").count(), failures);
+    assert_eq!(xml.matches("<testsuite ").count(), 2);
+    assert!(xml.trim().ends_with("</testsuites>"));
+}
+
+fn rule(cost_centre: &str, limits: &[(Measure, f64)]) -> Rule {
+    Rule {
+        name: "test".to_string(),
+        cost_centre: cost_centre.to_string(),
+        module: "Main".to_string(),
+        limits: limits.to_vec(),
+    }
+}
+
+#[test]
+fn rules_are_checked_at_each_matching_call_path() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let rules = vec![rule("countWords*", &[(Measure::InheritedAlloc, 60.0), (Measure::Entries, 1000.0)])];
+    let verdicts = evaluate(&prof, &rules);
+    assert_eq!(verdicts.len(), 4);
+    let failed: Vec<(&str, Measure)> = verdicts.iter()
+        .filter(|v| v.failed())
+        .map(|v| (v.path.as_str(), v.measure))
+        .collect();
+    assert_eq!(failed,
+               vec![("MAIN.MAIN > Main.CAF > Main.main > Main.countWords", Measure::InheritedAlloc),
+                    ("MAIN.MAIN > Main.CAF > Main.main > Main.countWords > Main.countWords.bump", Measure::Entries)]);
+
+    let case = verdicts[1].test_case();
+    assert_eq!(case.suite, "rule test");
+    assert_eq!(case.failure, None);
+    let case = verdicts[3].test_case();
+    assert_eq!(case.failure, Some("181733, over the 1000 allowed by rule `test`".to_string()));
+}
+
+#[test]
+fn byte_limits() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let rules = vec![rule("tokenize", &[(Measure::IndividualBytes, 300e6), (Measure::InheritedBytes, 250e6)])];
+    let verdicts = evaluate(&prof, &rules);
+    assert_eq!(verdicts.len(), 2);
+    // 25.4% of 1,073,546,184 bytes.
+    assert!((verdicts[0].value - 272.68e6).abs() < 0.01e6, "{}", verdicts[0].value);
+    assert!(!verdicts[0].failed());
+    assert!(verdicts[1].failed());
+    assert_eq!(verdicts[1].test_case().failure,
+               Some("272.68 MB, over the 250.00 MB allowed by rule `test`".to_string()));
+}
@@ -0,0 +1,24 @@
+// Each test crate uses only some of these.
+#![allow(dead_code)]
+
+use ghcprof::parser::{parse_prof_file, ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree};
+use std::fs::File;
+use std::io::prelude::*;
+
+pub fn read_profile(path: &str) -> String {
+    let mut prof_file = File::open(path).unwrap();
+    let mut profile = String::new();
+    prof_file.read_to_string(&mut profile).unwrap();
+    profile
+}
+
+/// Parses a profile read with `read_profile`.
+pub fn parse_profile<'a>(profile: &'a str) -> GHCProf<'a> {
+    parse_prof_file(profile.as_bytes()).unwrap()
+}
+
+/// The cost centre tree of a profile.
+pub fn tree<'t, 'a>(prof: &'t GHCProf<'a>) -> &'t RoseTree<ExtendedSummaryLine<'a>> {
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    tree
+}
@@ -0,0 +1,56 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::dot::{write_call_graph, GraphOptions};
+use ghcprof::metric::Metric;
+
+fn call_graph(path: &str, opts: &GraphOptions) -> String {
+    let input = read_profile(path);
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let mut out = Vec::new();
+    write_call_graph(&mut out, tree, opts).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn cheap_cost_centres_are_left_out() {
+    let dot = call_graph("../example_format/ghc8.prof", &GraphOptions::default());
+    assert!(dot.starts_with("digraph calls {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(!dot.contains("GHC.IO"));
+    assert_eq!(dot.matches(" [label=").count(), 6 + 5);
+    assert!(dot.contains("  n0 [label=\"MAIN\\nMAIN\\n0.0% time (100.0% inherited)\", fontsize=10.0, \
+                          fillcolor=\"0.650 0.600 1.000\"];\n"),
+            "{}",
+            dot);
+    assert!(dot.contains("label=\"countWords.bump\\nMain\\n48.6% time (48.6% inherited)\", fontsize=19.7"));
+    assert!(dot.contains(" [label=\"48.6%\\n181733 entries\", penwidth=4.40];\n"));
+}
+
+#[test]
+fn merged_recursion_counts_the_outermost_calls() {
+    let dot = call_graph("../example_format/recursive.prof",
+                         &GraphOptions { metric: Metric::Alloc, ..GraphOptions::default() });
+    // go, and even and odd calling each other.
+    assert_eq!(dot.matches("label=\"go\\n").count(), 1);
+    assert!(dot.contains("label=\"go\\nMain\\n50.0% alloc (60.0% inherited)\""), "{}", dot);
+    assert!(dot.contains("label=\"even\\nMain\\n15.0% alloc (35.0% inherited)\""), "{}", dot);
+    assert!(dot.contains("[label=\"50.0%\\n3 entries\""), "{}", dot);
+}
+
+#[test]
+fn trees_and_node_limits() {
+    let tree = GraphOptions { merge: false, ..GraphOptions::default() };
+    let dot = call_graph("../example_format/recursive.prof", &tree);
+    assert_eq!(dot.matches("label=\"go\\n").count(), 4);
+
+    let limited = GraphOptions { max_nodes: Some(3), ..tree };
+    let dot = call_graph("../example_format/recursive.prof", &limited);
+    // Besides the one of edges.
+    assert_eq!(dot.matches("fontsize=").count() - 1, 3);
+    assert!(dot.contains("label=\"MAIN\\n") && dot.contains("label=\"CAF\\n") && dot.contains("label=\"main\\n"));
+    assert_eq!(dot.matches(" -> ").count(), 2);
+}
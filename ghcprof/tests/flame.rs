@@ -0,0 +1,55 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::flame::layout;
+use ghcprof::metric::Metric;
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+}
+
+#[test]
+fn children_sit_side_by_side_over_their_parent() {
+    let input = read_profile("../example_format/recursive.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let rects = layout(tree, Metric::Time, 0.0);
+
+    let names: Vec<(usize, &str)> = rects.iter().map(|r| (r.depth, r.node.value.cost_centre)).collect();
+    assert_eq!(names,
+               vec![(0, "MAIN"), (1, "CAF"), (2, "main"), (3, "go"), (4, "go"), (5, "go"), (6, "go"),
+                    (7, "step"), (3, "even"), (4, "odd"), (5, "even"), (6, "odd")]);
+    // main, then its two children from its left edge.
+    assert_close(rects[2].start, 0.0);
+    assert_close(rects[2].width, 100.0);
+    assert_close(rects[3].width, 60.0);
+    assert_close(rects[8].start, 60.0);
+    assert_close(rects[8].width, 35.0);
+    assert_close(rects[11].start, 60.0);
+    assert_close(rects[11].width, 15.0);
+}
+
+#[test]
+fn narrow_nodes_are_left_out() {
+    let input = read_profile("../example_format/recursive.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let rects = layout(tree, Metric::Alloc, 20.0);
+
+    let names: Vec<&str> = rects.iter().map(|r| r.node.value.cost_centre).collect();
+    assert_eq!(names, vec!["MAIN", "CAF", "main", "go", "go", "go", "go", "even", "odd", "even"]);
+}
+
+#[test]
+fn children_are_cut_to_fit() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let rects = layout(tree, Metric::Time, 0.0);
+
+    for r in &rects {
+        assert!(r.start >= 0.0 && r.start + r.width <= 100.0 + 1e-9, "{:?}", r);
+    }
+}
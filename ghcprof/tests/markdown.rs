@@ -0,0 +1,84 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile};
+use ghcprof::markdown::{write_diff, write_summary};
+use ghcprof::metric::Metric;
+
+#[test]
+fn top_cost_centres_and_call_paths() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let mut out = Vec::new();
+    write_summary(&mut out, &prof, Metric::Time, 3).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "### Top cost centres by time\n\
+                \n\
+                | Cost centre | Module | %time | %alloc |\n\
+                |---|---|--:|--:|\n\
+                | `countWords.bump` | `Main` | 48.6 | 55.1 |\n\
+                | `tokenize` | `Main` | 30.1 | 25.4 |\n\
+                | `countWords` | `Main` | 12.9 | 15.1 |\n\
+                \n\
+                ### Hot call paths by time\n\
+                \n\
+                | Call path | entries | individual %time | inherited %time |\n\
+                |---|--:|--:|--:|\n\
+                | `Main.CAF > Main.main > Main.countWords > Main.countWords.bump` | 181733 | 48.6 | 48.6 |\n\
+                | `Main.CAF > Main.main > Main.tokenize` | 1 | 30.1 | 30.1 |\n\
+                | `Main.CAF > Main.main > Main.countWords` | 1 | 12.9 | 61.5 |\n");
+}
+
+#[test]
+fn changes_since_a_baseline() {
+    let baseline = read_profile("../example_format/ghc8.prof");
+    let current = read_profile("../example_format/ghc8-run2.prof");
+    let mut out = Vec::new();
+    write_diff(&mut out,
+               &parse_profile(&baseline),
+               &parse_profile(&current),
+               Metric::Alloc,
+               3).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "### Changes in alloc\n\
+                \n\
+                | Cost centre | Before | After | Change |\n\
+                |---|--:|--:|--:|\n\
+                | `Main.countWords.bump` | 591.5 MB | 672.0 MB | ↑ 13.6% |\n\
+                | `Main.tokenize.\\` | 0 B | 72.0 MB | ↑ new |\n\
+                | `Main.tokenize` | 272.7 MB | 240.0 MB | ↓ 12.0% |\n");
+}
+
+#[test]
+fn removed_cost_centres() {
+    // The other way round, the lambda of tokenize is gone.
+    let baseline = read_profile("../example_format/ghc8-run2.prof");
+    let current = read_profile("../example_format/ghc8.prof");
+    let mut out = Vec::new();
+    write_diff(&mut out,
+               &parse_profile(&baseline),
+               &parse_profile(&current),
+               Metric::Time,
+               10).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("| `Main.tokenize.\\` | 70.0 ms | 0 µs | ↓ removed |\n"), "{}", out);
+}
+
+#[test]
+fn no_changes() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let mut out = Vec::new();
+    write_diff(&mut out, &prof, &prof, Metric::Time, 10).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "### Changes in time\n\nNo changes.\n");
+}
+
+#[test]
+fn pipes_are_escaped() {
+    let input = read_profile("../example_format/ghc8.prof").replace("tokenize", "<|>");
+    let prof = parse_profile(&input);
+    let mut out = Vec::new();
+    write_summary(&mut out, &prof, Metric::Time, 2).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("| `<\\|>` | `Main` | 30.1 | 25.4 |\n"));
+}
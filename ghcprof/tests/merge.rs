@@ -0,0 +1,128 @@
+
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::callpath::{call_path, follow};
+use ghcprof::merge::{merge, Combine};
+use ghcprof::parser::{ExtendedSummaryLine, GHCProf, RoseTree};
+
+fn node<'t, 'a>(prof: &'t GHCProf<'a>, path: &[(&str, &str)]) -> &'t RoseTree<ExtendedSummaryLine<'a>> {
+    let tree = tree(prof);
+    let nodes = follow(tree, path);
+    assert_eq!(nodes.len(), path.len(), "no node at {:?}", path);
+    nodes[nodes.len() - 1]
+}
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 0.05, "{} != {}", a, b);
+}
+
+const BUMP: &[(&str, &str)] = &[("MAIN", "MAIN"),
+                                ("CAF", "Main"),
+                                ("main", "Main"),
+                                ("countWords", "Main"),
+                                ("countWords.bump", "Main")];
+
+#[test]
+fn merging_nothing_gives_nothing() {
+    assert_eq!(merge(&[], Combine::Sum), Err("no profile to merge".to_string()));
+}
+
+#[test]
+fn profiles_must_share_their_root() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let mut other = prof.clone();
+    other.extended_summary.0.value.cost_centre = "OTHER";
+    assert_eq!(merge(&[prof, other], Combine::Sum),
+               Err("cannot merge a profile rooted at `MAIN.MAIN` with one rooted at `MAIN.OTHER`".to_string()));
+}
+
+#[test]
+fn summing_a_profile_with_itself_doubles_the_totals() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let merged = merge(&[prof.clone(), prof.clone()], Combine::Sum).unwrap();
+    assert_eq!(merged.header.total_time.ticks, 898);
+    assert_eq!(merged.header.total_alloc.bytes, 2 * 1_073_546_184);
+    assert_close(merged.header.total_time.time, 0.9);
+    let bump = node(&merged, BUMP);
+    assert_eq!(bump.value.entries, 2 * 181733);
+    assert_close(bump.value.individual_time_perc, 48.6);
+    assert_close(bump.value.inherited_alloc_perc, 55.1);
+    assert_close(node(&merged, &BUMP[..4]).value.inherited_time_perc, 61.5);
+}
+
+#[test]
+fn averaging_a_profile_with_itself_changes_nothing() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let merged = merge(&[prof.clone(), prof.clone(), prof.clone()], Combine::Average).unwrap();
+    assert_eq!(merged.header.total_time.ticks, 449);
+    assert_eq!(merged.header.total_alloc.bytes, 1_073_546_184);
+    assert_eq!(node(&merged, BUMP).value.entries, 181733);
+    assert_eq!(merged.summary.0.len(), prof.summary.0.len());
+    for (merged, orig) in merged.summary.0.iter().zip(prof.summary.0.iter()) {
+        assert_eq!(merged.cost_centre, orig.cost_centre);
+        assert_close(merged.time_perc, orig.time_perc);
+        assert_close(merged.alloc_perc, orig.alloc_perc);
+    }
+}
+
+#[test]
+fn merges_by_call_path() {
+    let first = read_profile("../example_format/ghc8.prof");
+    let first = parse_profile(&first);
+    let second = read_profile("../example_format/ghc8-run2.prof");
+    let second = parse_profile(&second);
+    let merged = merge(&[first, second], Combine::Sum).unwrap();
+
+    assert_eq!(merged.header.total_time.ticks, 1000);
+    assert_eq!(merged.header.program, "wordfreq +RTS -p -RTS corpus.txt");
+
+    // 48.6% of 449 ticks and 50% of 551.
+    let bump = node(&merged, BUMP);
+    assert_eq!(bump.value.entries, 181733 + 201554);
+    // Neither run has the ticks and bytes columns.
+    assert_eq!(bump.value.ticks, None);
+    assert_eq!(bump.value.bytes, None);
+    assert!(merged.summary.0.iter().all(|l| l.ticks.is_none()));
+    assert_close(bump.value.individual_time_perc, 49.4);
+
+    // Only in the second run.
+    let lambda = node(&merged,
+                      &[("MAIN", "MAIN"), ("CAF", "Main"), ("main", "Main"), ("tokenize", "Main"), ("tokenize.\\", "Main")]);
+    assert_eq!(lambda.value.entries, 988120);
+    assert_eq!(lambda.value.src, Some("src/Main.hs:21:25-65"));
+    assert_close(lambda.value.individual_time_perc, 7.0);
+
+    // The root inherits everything, and nodes are numbered anew.
+    let tree = tree(&merged);
+    assert_close(tree.value.inherited_time_perc, 100.0);
+    assert_close(tree.value.inherited_alloc_perc, 100.0);
+    assert_eq!(call_path(tree, 1), Some(vec![("MAIN", "MAIN")]));
+
+    let summary: Vec<&str> = merged.summary.0.iter().map(|l| l.cost_centre).collect();
+    assert_eq!(summary, vec!["countWords.bump", "tokenize", "countWords", "main", "tokenize.\\"]);
+}
+
+#[test]
+fn keeps_ticks_and_bytes_when_every_profile_has_them() {
+    let with = read_profile("../example_format/ghc8-ticks.prof");
+    let with = parse_profile(&with);
+    let without = read_profile("../example_format/ghc8.prof");
+    let without = parse_profile(&without);
+
+    let merged = merge(&[with.clone(), with.clone()], Combine::Sum).unwrap();
+    let bump = node(&merged, BUMP);
+    assert_eq!(bump.value.ticks, Some(2 * 218));
+    assert_eq!(bump.value.bytes, Some(2 * 591523944));
+    assert_eq!(merged.summary.0[0].cost_centre, "countWords.bump");
+    assert_eq!(merged.summary.0[0].ticks, Some(2 * 218));
+
+    let merged = merge(&[with, without], Combine::Sum).unwrap();
+    assert_eq!(node(&merged, BUMP).value.ticks, None);
+    assert_eq!(merged.summary.0[0].ticks, None);
+}
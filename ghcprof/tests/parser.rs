@@ -100,7 +100,8 @@ fn can_parse_summary_line() {
         .as_bytes()) {
         IResult::Done(leftover, x) => {
             assert!(leftover.is_empty());
-            assert_eq!(x.module, "Crypto.RNCryptor.V3.Encrypt")
+            assert_eq!(x.module, "Crypto.RNCryptor.V3.Encrypt");
+            assert_eq!(x.src, None)
         },
         IResult::Error(Err::Position(_, bytes)) => {
             panic!("error char -> {:?}", str::from_utf8(bytes))
@@ -250,3 +251,103 @@ fn can_parse_ghc_profile() {
         e => panic!("{:?}", e),
     }
 }
+
+#[test]
+fn can_parse_summary_line_with_src() {
+    match parse_summary_line("countWords        Main   src/Main.hs:(16,1)-(18,35)   12.9   15.1
+"
+        .as_bytes()) {
+        IResult::Done(leftover, x) => {
+            assert!(leftover.is_empty());
+            assert_eq!(x.src, Some("src/Main.hs:(16,1)-(18,35)"));
+            assert_eq!(x.time_perc, 12.9)
+        },
+        IResult::Error(Err::Position(_, bytes)) => {
+            panic!("error char -> {:?}", str::from_utf8(bytes))
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn can_parse_extended_summary_line_with_placeholder_src() {
+    match parse_extended_summary_line("MAIN                MAIN                  <no location info>          106          0    0.0    0.0   100.0  100.0
+"
+        .as_bytes()) {
+        IResult::Done(leftover, x) => {
+            assert!(leftover.is_empty());
+            assert_eq!(x.src, Some("<no location info>"));
+            assert_eq!(x.no, 106)
+        },
+        IResult::Error(Err::Position(_, bytes)) => {
+            panic!("error char -> {:?}", str::from_utf8(bytes))
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn can_parse_ghc8_profile() {
+    let mut prof_file = File::open("../example_format/ghc8.prof").unwrap();
+    let mut profile   = String::new();
+    prof_file.read_to_string(&mut profile).unwrap();
+    match parse_prof(profile.as_bytes()) {
+        IResult::Done(_, prof) => {
+            assert_eq!(prof.summary.0[0].src, Some("src/Main.hs:18:5-35"));
+            let ExtendedSummary(ref tree) = prof.extended_summary;
+            assert_eq!(tree.value.src, Some("<built-in>"));
+            let ref main = tree.sub_forest[2].sub_forest[0];
+            assert_eq!(main.value.cost_centre, "main");
+            assert_eq!(main.value.src, Some("src/Main.hs:(10,1)-(13,77)"));
+            assert_eq!(main.sub_forest[0].sub_forest[0].value.entries, 181733);
+        },
+        IResult::Error(Err::Position(_, bytes)) => {
+            panic!("error char -> {:?}", str::from_utf8(bytes))
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn can_parse_rts_p_profile() {
+    let mut prof_file = File::open("../example_format/ghc8-ticks.prof").unwrap();
+    let mut profile   = String::new();
+    prof_file.read_to_string(&mut profile).unwrap();
+    match parse_prof(profile.as_bytes()) {
+        IResult::Done(_, prof) => {
+            assert_eq!(prof.header.program, "wordfreq +RTS -P -RTS corpus.txt");
+            let ref bump = prof.summary.0[0];
+            assert_eq!(bump.cost_centre, "countWords.bump");
+            assert_eq!(bump.ticks, Some(218));
+            assert_eq!(bump.bytes, Some(591523944));
+            let ExtendedSummary(ref tree) = prof.extended_summary;
+            assert_eq!(tree.value.ticks, Some(0));
+            let ref main = tree.sub_forest[2].sub_forest[0];
+            assert_eq!(main.value.ticks, Some(38));
+            assert_eq!(main.value.bytes, Some(47236032));
+            assert_eq!(main.sub_forest[0].sub_forest[0].value.ticks, Some(218));
+        },
+        IResult::Error(Err::Position(_, bytes)) => {
+            panic!("error char -> {:?}", str::from_utf8(bytes))
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn can_parse_summary_line_with_ticks_and_bytes() {
+    match parse_summary_line("tokenize          Main   src/Main.hs:21:1-67          30.1   25.4    135 272680728
+"
+        .as_bytes()) {
+        IResult::Done(leftover, x) => {
+            assert!(leftover.is_empty());
+            assert_eq!(x.alloc_perc, 25.4);
+            assert_eq!(x.ticks, Some(135));
+            assert_eq!(x.bytes, Some(272680728))
+        },
+        IResult::Error(Err::Position(_, bytes)) => {
+            panic!("error char -> {:?}", str::from_utf8(bytes))
+        }
+        e => panic!("{:?}", e),
+    }
+}
@@ -0,0 +1,165 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile};
+use ghcprof::parser::GHCProf;
+use ghcprof::pprof::write_pprof;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Varint(u64),
+    Bytes(Vec<u8>),
+}
+
+fn varint(buf: &[u8], pos: &mut usize) -> u64 {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = buf[*pos];
+        *pos += 1;
+        v |= ((b & 0x7f) as u64) << shift;
+        if b < 0x80 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+/// The fields of a message, in order.
+fn decode(buf: &[u8]) -> Vec<(u64, Value)> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let key = varint(buf, &mut pos);
+        let value = match key & 7 {
+            0 => Value::Varint(varint(buf, &mut pos)),
+            2 => {
+                let len = varint(buf, &mut pos) as usize;
+                pos += len;
+                Value::Bytes(buf[pos - len..pos].to_vec())
+            }
+            t => panic!("unexpected wire type {}", t),
+        };
+        fields.push((key >> 3, value));
+    }
+    fields
+}
+
+fn bytes(fields: &[(u64, Value)], field: u64) -> Vec<Vec<u8>> {
+    fields.iter()
+        .filter_map(|&(f, ref v)| match *v {
+            Value::Bytes(ref b) if f == field => Some(b.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn uint(fields: &[(u64, Value)], field: u64) -> u64 {
+    fields.iter()
+        .filter_map(|&(f, ref v)| match *v {
+            Value::Varint(n) if f == field => Some(n),
+            _ => None,
+        })
+        .next()
+        .unwrap_or(0)
+}
+
+fn packed(buf: &[u8]) -> Vec<u64> {
+    let mut values = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        values.push(varint(buf, &mut pos));
+    }
+    values
+}
+
+struct Pprof {
+    strings: Vec<String>,
+    sample_types: Vec<(String, String)>,
+    /// The function names from the leaf, with the values.
+    samples: Vec<(Vec<String>, Vec<u64>)>,
+    /// Name, file and line of each function.
+    functions: Vec<(String, String, u64)>,
+}
+
+fn pprof(prof: &GHCProf) -> Pprof {
+    let mut out = Vec::new();
+    write_pprof(&mut out, prof).unwrap();
+
+    let fields = decode(&out);
+    let strings: Vec<String> = bytes(&fields, 6).into_iter().map(|s| String::from_utf8(s).unwrap()).collect();
+    let string = |f: &[(u64, Value)], field: u64| strings[uint(f, field) as usize].clone();
+    let sample_types = bytes(&fields, 1).iter().map(|t| {
+        let t = decode(t);
+        (string(&t, 1), string(&t, 2))
+    }).collect();
+    let functions: Vec<(u64, (String, String, u64))> = bytes(&fields, 5).iter().map(|f| {
+        let f = decode(f);
+        (uint(&f, 1), (string(&f, 2), string(&f, 4), uint(&f, 5)))
+    }).collect();
+    let locations: Vec<(u64, u64)> = bytes(&fields, 4).iter().map(|l| {
+        let l = decode(l);
+        let line = decode(&bytes(&l, 4)[0]);
+        (uint(&l, 1), uint(&line, 1))
+    }).collect();
+    let function_name = |location: u64| {
+        let function = locations.iter().find(|l| l.0 == location).unwrap().1;
+        functions.iter().find(|f| f.0 == function).unwrap().1 .0.clone()
+    };
+    let samples = bytes(&fields, 2).iter().map(|s| {
+        let s = decode(s);
+        let stack = packed(&bytes(&s, 1)[0]).into_iter().map(&function_name).collect();
+        (stack, packed(&bytes(&s, 2)[0]))
+    }).collect();
+    Pprof {
+        strings: strings.clone(),
+        sample_types,
+        samples,
+        functions: functions.into_iter().map(|f| f.1).collect(),
+    }
+}
+
+fn assert_close(a: u64, b: u64) {
+    assert!((a as f64 - b as f64).abs() <= b as f64 * 1e-6, "{} != {}", a, b);
+}
+
+#[test]
+fn call_paths_as_samples() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let p = pprof(&parse_profile(&input));
+    assert_eq!(p.strings[0], "");
+    assert_eq!(p.sample_types,
+               vec![("time".to_string(), "nanoseconds".to_string()), ("alloc".to_string(), "bytes".to_string())]);
+    let stacks: Vec<Vec<&str>> = p.samples.iter().map(|s| s.0.iter().map(|n| n.as_str()).collect()).collect();
+    assert_eq!(stacks,
+               vec![vec!["Main.main", "Main.CAF", "MAIN.MAIN"],
+                    vec!["Main.countWords", "Main.main", "Main.CAF", "MAIN.MAIN"],
+                    vec!["Main.countWords.bump", "Main.countWords", "Main.main", "Main.CAF", "MAIN.MAIN"],
+                    vec!["Main.tokenize", "Main.main", "Main.CAF", "MAIN.MAIN"]]);
+    // 48.6% of 449 ticks of 1ms, and 55.1% of 1,073,546,184 bytes.
+    assert_close(p.samples[2].1[0], 218214000);
+    assert_close(p.samples[2].1[1], 591523943);
+}
+
+#[test]
+fn functions_from_src_spans() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let p = pprof(&parse_profile(&input));
+    assert!(p.functions.contains(&("Main.countWords".to_string(), "src/Main.hs".to_string(), 16)));
+    assert!(p.functions.contains(&("Main.countWords.bump".to_string(), "src/Main.hs".to_string(), 18)));
+    assert!(p.functions.contains(&("MAIN.MAIN".to_string(), "".to_string(), 0)));
+    // One per cost centre.
+    assert_eq!(p.functions.len(), 8);
+}
+
+#[test]
+fn ticks_and_bytes_when_present() {
+    let input = read_profile("../example_format/ghc8-ticks.prof");
+    let p = pprof(&parse_profile(&input));
+    let types: Vec<&str> = p.sample_types.iter().map(|t| t.0.as_str()).collect();
+    assert_eq!(types, vec!["time", "alloc", "ticks", "bytes"]);
+    let total: Vec<u64> = (0..4).map(|i| p.samples.iter().map(|s| s.1[i]).sum()).collect();
+    // Time is taken from the ticks, 1ms each.
+    assert_eq!(total, vec![449 * 1000000, 1073546184, 449, 1073546184]);
+}
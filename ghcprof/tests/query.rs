@@ -0,0 +1,61 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::query::Query;
+
+/// The `no` of the nodes of ghc8.prof selected by `query`.
+fn select(query: &str) -> Vec<u32> {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let query: Query = query.parse().unwrap();
+    query.select(tree).iter().map(|t| t.value.no).collect()
+}
+
+#[test]
+fn path_patterns() {
+    assert_eq!(select("main/**/countWords.bump"), vec![215]);
+    assert_eq!(select("main/*"), vec![214, 213]);
+    assert_eq!(select("CAF"), vec![170, 162, 211]);
+    assert_eq!(select("/MAIN/CAF"), vec![170, 162, 211]);
+    assert_eq!(select("/CAF"), Vec::<u32>::new());
+    assert_eq!(select("GHC.IO.*.CAF"), vec![170, 162]);
+    assert_eq!(select("countWords*"), vec![214, 215]);
+    assert_eq!(select("\"main\"/tokenize"), vec![213]);
+}
+
+#[test]
+fn descendants() {
+    assert_eq!(select("**"), vec![106, 170, 162, 211, 212, 214, 215, 213]);
+    assert_eq!(select("main/**"), vec![212, 214, 215, 213]);
+    assert_eq!(select("/MAIN/**/bump"), Vec::<u32>::new());
+    assert_eq!(select("/MAIN/**/*.bump"), vec![215]);
+}
+
+#[test]
+fn predicates() {
+    assert_eq!(select("Main.*[inherited-alloc > 50]"), vec![211, 212, 214, 215]);
+    assert_eq!(select("[entries >= 1000]"), vec![215]);
+    assert_eq!(select("main/*[individual-time < 20%, inherited-time > 20]"), vec![214]);
+    assert_eq!(select("*[entries = 1 and individual-alloc != 4.4]"), vec![214, 213]);
+    assert_eq!(select("CAF[inherited-time > 0]/main[no == 212]"), vec![212]);
+    // Only `+RTS -P` profiles have ticks.
+    assert_eq!(select("*[ticks >= 0]"), Vec::<u32>::new());
+}
+
+#[test]
+fn invalid_queries() {
+    for query in &["", "main[foo > 1]", "main[entries]", "main[entries > lots]", "main//tokenize",
+                   "\"main", "main tokenize", "main[entries > 1", "**[entries > 1]",
+                   "main]/tokenize[entries > 1]", "main[entries > 1/tokenize"] {
+        assert!(query.parse::<Query>().is_err(), "{:?} should not parse", query);
+    }
+    let err = "main[foo > 1]".parse::<Query>().unwrap_err();
+    assert!(err.starts_with("unknown field `foo`"), "{}", err);
+    let err = "main[entries > 1/tokenize".parse::<Query>().unwrap_err();
+    assert_eq!(err, "unclosed bracket in `main[entries > 1/tokenize`");
+    let err = "main]/tokenize".parse::<Query>().unwrap_err();
+    assert_eq!(err, "unopened bracket in `main]/tokenize`");
+}
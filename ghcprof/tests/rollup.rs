@@ -0,0 +1,171 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::parser::{ExtendedSummaryLine, RoseTree};
+use ghcprof::dot::write_module_calls;
+use ghcprof::metric::Metric;
+use ghcprof::rollup::{by_module, module_calls, ModuleCall};
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+}
+
+/// Each node as (depth, cost centre, module).
+fn outline<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, res: &mut Vec<(usize, &'a str, &'a str)>) {
+    res.push((tree.depth, tree.value.cost_centre, tree.value.module));
+    for t in &tree.sub_forest {
+        outline(t, res);
+    }
+}
+
+#[test]
+fn rolls_up_by_module_prefix() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let rollup = by_module(tree);
+
+    let mut nodes = Vec::new();
+    outline(&rollup, &mut nodes);
+    assert_eq!(nodes,
+               vec![(0, "MAIN", "MAIN"),
+                    (1, "GHC", "GHC"),
+                    (2, "IO", "GHC.IO"),
+                    (3, "Handle", "GHC.IO.Handle"),
+                    (4, "FD", "GHC.IO.Handle.FD"),
+                    (5, "CAF", "GHC.IO.Handle.FD"),
+                    (3, "Encoding", "GHC.IO.Encoding"),
+                    (4, "CAF", "GHC.IO.Encoding"),
+                    (1, "Main", "Main"),
+                    (2, "CAF", "Main"),
+                    (2, "main", "Main"),
+                    (2, "countWords", "Main"),
+                    (2, "countWords.bump", "Main"),
+                    (2, "tokenize", "Main")]);
+
+    let main = &rollup.sub_forest[1];
+    assert_eq!(main.value.entries, 181736);
+    assert_eq!(main.value.src, None);
+    assert_close(main.value.individual_time_perc, 100.0);
+    assert_close(main.value.inherited_alloc_perc, 100.0);
+    assert_close(main.sub_forest[2].value.individual_time_perc, 12.9);
+    assert_close(main.sub_forest[2].value.inherited_time_perc, 12.9);
+    assert_eq!(main.sub_forest[2].value.no, 214);
+    assert_close(rollup.sub_forest[0].value.individual_time_perc, 0.0);
+}
+
+#[test]
+fn adds_up_the_calls_of_each_cost_centre() {
+    let input = read_profile("../example_format/hoogle.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let rollup = by_module(tree);
+    assert_close(rollup.value.inherited_time_perc, tree.value.inherited_time_perc);
+    assert_close(rollup.value.inherited_alloc_perc, tree.value.inherited_alloc_perc);
+
+    fn check(tree: &RoseTree<ExtendedSummaryLine>) {
+        // Cost centres are the leaves, under their module.
+        let total = if tree.sub_forest.is_empty() {
+            tree.value.individual_time_perc
+        } else {
+            tree.sub_forest.iter().map(|t| t.value.inherited_time_perc).sum()
+        };
+        assert_close(total, tree.value.inherited_time_perc);
+        for t in &tree.sub_forest {
+            assert_eq!(t.depth, tree.depth + 1);
+            check(t);
+        }
+    }
+    for t in &rollup.sub_forest {
+        check(t);
+    }
+
+    // unGet is called from many places, but listed once.
+    let input = rollup.sub_forest.iter().find(|t| t.value.module == "Input").expect("Input");
+    let mut nodes = Vec::new();
+    outline(input, &mut nodes);
+    assert!(nodes.iter().all(|&(_, _, module)| module.starts_with("Input")));
+    let mut names: Vec<(&str, &str)> = nodes.iter().map(|&(_, cc, module)| (cc, module)).collect();
+    let count = names.len();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), count);
+}
+
+fn node<'a>(cost_centre: &'a str,
+            module: &'a str,
+            entries: u32,
+            time: f32,
+            sub_forest: Vec<RoseTree<ExtendedSummaryLine<'a>>>)
+            -> RoseTree<ExtendedSummaryLine<'a>> {
+    let line = ExtendedSummaryLine {
+        cost_centre,
+        module,
+        src: None,
+        no: 0,
+        entries,
+        individual_time_perc: 0.0,
+        individual_alloc_perc: 0.0,
+        inherited_time_perc: time,
+        inherited_alloc_perc: time,
+        ticks: None,
+        bytes: None,
+    };
+    RoseTree { depth: 0, value: line, sub_forest }
+}
+
+#[test]
+fn calls_between_modules() {
+    let input = read_profile("../example_format/cafs.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let calls = module_calls(tree);
+    let edges: Vec<(&str, &str)> = calls.iter().map(|c| (c.caller, c.callee)).collect();
+    assert_eq!(edges,
+               vec![("MAIN", "Main"), ("MAIN", "Data.Map.Internal"), ("MAIN", "GHC.IO.Encoding")]);
+    // Both CAFs of Main.
+    assert_close(calls[0].time_perc, 80.0);
+    assert_close(calls[0].alloc_perc, 80.0);
+    assert_eq!(calls[1].entries, 0);
+}
+
+#[test]
+fn nested_calls_between_the_same_modules_count_once() {
+    // Output calls into Input, which calls back into Output and Input again.
+    let tree = node("MAIN", "MAIN", 0, 100.0, vec![
+        node("render", "Output", 1, 90.0, vec![
+            node("parse", "Input", 2, 60.0, vec![
+                node("pretty", "Output", 3, 40.0, vec![
+                    node("lex", "Input", 4, 10.0, vec![])])]),
+            node("lex", "Input", 5, 20.0, vec![])])]);
+    let calls = module_calls(&tree);
+    let find = |caller: &str, callee: &str| -> ModuleCall {
+        calls.iter().find(|c| c.caller == caller && c.callee == callee).unwrap().clone()
+    };
+    assert_eq!(calls.len(), 3);
+    let output_input = find("Output", "Input");
+    assert_eq!(output_input.entries, 11);
+    assert_close(output_input.time_perc, 80.0);
+    assert_close(find("Input", "Output").time_perc, 40.0);
+}
+
+#[test]
+fn module_calls_as_dot() {
+    let calls = vec![ModuleCall {
+                         caller: "Main",
+                         callee: "Data.Map",
+                         entries: 12,
+                         time_perc: 50.0,
+                         alloc_perc: 25.0,
+                     }];
+    let mut out = Vec::new();
+    write_module_calls(&mut out, &calls, Metric::Alloc).unwrap();
+    let dot = String::from_utf8(out).unwrap();
+    assert!(dot.starts_with("digraph modules {\n"));
+    assert!(dot.contains("  \"Main\" -> \"Data.Map\" [label=\"50.0% time\\n25.0% alloc\\n12 entries\", penwidth=2.75];\n"),
+            "{}",
+            dot);
+    assert!(dot.ends_with("}\n"));
+}
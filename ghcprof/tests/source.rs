@@ -0,0 +1,147 @@
+
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::source::*;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn can_parse_single_line_span() {
+    assert_eq!(SrcSpan::parse("src/Main.hs:21:1-67"),
+               Some(SrcSpan {
+                   file: "src/Main.hs",
+                   start_line: 21,
+                   start_col: 1,
+                   end_line: 21,
+                   end_col: 67,
+               }));
+}
+
+#[test]
+fn can_parse_point_span() {
+    let span = SrcSpan::parse("Main.hs:3:7").unwrap();
+    assert_eq!((span.start_line, span.start_col, span.end_col), (3, 7, 7));
+}
+
+#[test]
+fn can_parse_multi_line_span() {
+    assert_eq!(SrcSpan::parse("C:\\work\\src\\Main.hs:(10,1)-(13,77)"),
+               Some(SrcSpan {
+                   file: "C:\\work\\src\\Main.hs",
+                   start_line: 10,
+                   start_col: 1,
+                   end_line: 13,
+                   end_col: 77,
+               }));
+}
+
+#[test]
+fn placeholders_are_not_spans() {
+    assert_eq!(SrcSpan::parse("<built-in>"), None);
+    assert_eq!(SrcSpan::parse("<no location info>"), None);
+    assert_eq!(SrcSpan::parse("Main.hs"), None);
+}
+
+#[test]
+fn cost_centres_belong_to_their_top_level_binding() {
+    assert_eq!(binding_name("encryptStreamWithContext.finaliseEncryption.(...)"),
+               Some("encryptStreamWithContext"));
+    assert_eq!(binding_name("downloadFile.\\"), Some("downloadFile"));
+    assert_eq!(binding_name("<>"), Some("<>"));
+    assert_eq!(binding_name("CAF"), None);
+}
+
+#[test]
+fn can_find_top_level_bindings() {
+    let contents = "module Ops where\n\
+                    \n\
+                    modulus :: Int\n\
+                    modulus = 7\n\
+                    \n\
+                    (<+>) :: Int -> Int -> Int\n\
+                    a <+> b = (a + b) `mod` modulus\n\
+                    \n\
+                    x `minus` y = x - y\n\
+                    \x20 where helper = minus\n";
+    assert_eq!(find_binding(contents, "modulus"), Some(3));
+    assert_eq!(find_binding(contents, "mod"), None);
+    assert_eq!(find_binding(contents, "<+>"), Some(6));
+    assert_eq!(find_binding(contents, "minus"), Some(9));
+    assert_eq!(find_binding(contents, "helper"), None);
+}
+
+#[test]
+fn can_find_modules_below_the_source_root() {
+    assert_eq!(find_module(Path::new("../example_format"), "Main"),
+               Some(PathBuf::from("../example_format/src/Main.hs")));
+    assert_eq!(find_module(Path::new("../example_format"), "Data.Missing"), None);
+}
+
+#[test]
+fn spans_take_precedence_over_searching() {
+    let loc = locate(Some(Path::new("../example_format")),
+                     "countWords",
+                     "Main",
+                     Some("src/Main.hs:(16,1)-(18,35)"));
+    assert_eq!(loc,
+               Some(Location {
+                   file: PathBuf::from("../example_format/src/Main.hs"),
+                   line: 16,
+                   column: 1,
+                   end: Some((18, 35)),
+               }));
+}
+
+#[test]
+fn falls_back_on_searching_the_source_root() {
+    let loc = locate(Some(Path::new("../example_format")), "tokenize", "Main", None);
+    assert_eq!(loc,
+               Some(Location {
+                   file: PathBuf::from("../example_format/src/Main.hs"),
+                   line: 20,
+                   column: 1,
+                   end: None,
+               }));
+    assert_eq!(locate(None, "tokenize", "Main", None), None);
+}
+
+#[test]
+fn can_list_top_level_definitions() {
+    let contents = "{-# LANGUAGE BangPatterns #-}\n\
+                    module Ops (modulus, (<+>), minus) where\n\
+                    \n\
+                    import Data.List (foldl')\n\
+                    \n\
+                    -- | The modulus.\n\
+                    modulus :: Int\n\
+                    modulus = 7\n\
+                    \n\
+                    data Op = Plus | Minus\n\
+                    \n\
+                    (<+>) :: Int -> Int -> Int\n\
+                    a <+> b = (a + b) `mod` modulus\n\
+                    \n\
+                    x `minus` y = x - y\n\
+                    \x20 where helper = minus\n\
+                    \n\
+                    sum' !acc [] = acc\n\
+                    sum' !acc (x:xs) = sum' (acc + x) xs\n";
+    assert_eq!(definitions(contents),
+               vec![(7, "modulus"), (12, "<+>"), (15, "minus"), (18, "sum'")]);
+}
+
+#[test]
+fn costs_are_summed_by_binding() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let costs = binding_costs(tree, "Main");
+    assert_eq!(costs.len(), 3);
+    let (time, alloc) = costs["countWords"];
+    assert!((time - 61.5).abs() < 0.01);
+    assert!((alloc - 70.2).abs() < 0.01);
+    assert_eq!(costs["tokenize"], (30.1, 25.4));
+    assert!(!costs.contains_key("CAF"));
+}
@@ -0,0 +1,74 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile};
+use ghcprof::parser::GHCProf;
+use ghcprof::trace::write_trace;
+
+fn trace(prof: &GHCProf) -> String {
+    let mut out = Vec::new();
+    write_trace(&mut out, prof).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// The value of `key` in an event, up to the next comma.
+fn field<'a>(event: &'a str, key: &str) -> &'a str {
+    let start = event.find(&format!("\"{}\":", key)).unwrap() + key.len() + 3;
+    let len = event[start..].find(',').unwrap();
+    &event[start..start + len]
+}
+
+#[test]
+fn nested_events_as_long_as_the_inherited_time() {
+    let input = read_profile("../example_format/recursive.prof");
+    let prof = parse_profile(&input);
+    let out = trace(&prof);
+    let events: Vec<(&str, &str, &str)> = out.lines()
+        .filter(|l| l.contains("\"ph\":\"X\""))
+        .map(|l| (field(l, "name"), field(l, "ts"), field(l, "dur")))
+        .collect();
+    // 1000 ticks of 1ms.
+    assert_eq!(events,
+               vec![("\"MAIN\"", "0", "1000000"),
+                    ("\"CAF\"", "0", "1000000"),
+                    ("\"main\"", "0", "1000000"),
+                    ("\"go\"", "0", "600000"),
+                    ("\"go\"", "0", "500000"),
+                    ("\"go\"", "0", "400000"),
+                    ("\"go\"", "0", "300000"),
+                    ("\"step\"", "0", "100000"),
+                    ("\"even\"", "600000", "350000"),
+                    ("\"odd\"", "600000", "300000"),
+                    ("\"even\"", "600000", "250000"),
+                    ("\"odd\"", "600000", "150000")]);
+}
+
+#[test]
+fn events_carry_the_costs() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let out = trace(&prof);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "{\"traceEvents\":[");
+    assert_eq!(lines[1],
+               "{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\
+                \"args\":{\"name\":\"wordfreq +RTS -p -RTS corpus.txt\"}},");
+    assert_eq!(lines[6],
+               "{\"name\":\"countWords.bump\",\"cat\":\"Main\",\"ph\":\"X\",\"ts\":0,\"dur\":218214,\"pid\":1,\"tid\":1,\
+                \"args\":{\"module\":\"Main\",\"src\":\"src/Main.hs:18:5-35\",\"no\":215,\"entries\":181733,\
+                \"individual %time\":48.6,\"individual %alloc\":55.1,\
+                \"inherited %time\":48.6,\"inherited %alloc\":55.1}},");
+    assert_eq!(lines[lines.len() - 1],
+               "],\"displayTimeUnit\":\"ms\",\"otherData\":{\"program\":\"wordfreq +RTS -p -RTS corpus.txt\"}}");
+    // The same profile gives the same trace.
+    assert_eq!(trace(&prof), out);
+}
+
+#[test]
+fn names_are_escaped() {
+    let input = read_profile("../example_format/ghc8-run2.prof");
+    let prof = parse_profile(&input);
+    let out = trace(&prof);
+    assert!(out.contains("{\"name\":\"tokenize.\\\\\",\"cat\":\"Main\""));
+}
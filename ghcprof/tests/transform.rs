@@ -0,0 +1,206 @@
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile, tree};
+use ghcprof::parser::{ExtendedSummaryLine, RoseTree};
+use ghcprof::transform::{collapse_recursion, exclude_cafs, group_cafs, hide, is_caf, HideRules};
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+}
+
+/// Each node as (depth, cost centre, no, entries).
+fn outline<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, res: &mut Vec<(usize, &'a str, u32, u32)>) {
+    res.push((tree.depth, tree.value.cost_centre, tree.value.no, tree.value.entries));
+    for t in &tree.sub_forest {
+        outline(t, res);
+    }
+}
+
+#[test]
+fn collapses_direct_and_mutual_recursion() {
+    let input = read_profile("../example_format/recursive.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let collapsed = collapse_recursion(tree);
+
+    let mut nodes = Vec::new();
+    outline(&collapsed.tree, &mut nodes);
+    assert_eq!(nodes,
+               vec![(0, "MAIN", 100, 0),
+                    (1, "CAF", 101, 0),
+                    (2, "main", 102, 1),
+                    (3, "go", 103, 4),
+                    (4, "step", 107, 3),
+                    (3, "even", 110, 2),
+                    (4, "odd", 111, 2)]);
+
+    let go = &collapsed.tree.sub_forest[0].sub_forest[0].sub_forest[0];
+    assert_close(go.value.individual_time_perc, 50.0);
+    assert_close(go.value.inherited_time_perc, 60.0);
+    let even = &collapsed.tree.sub_forest[0].sub_forest[0].sub_forest[1];
+    assert_close(even.value.individual_alloc_perc, 15.0);
+    assert_close(even.value.inherited_alloc_perc, 35.0);
+    assert_close(even.sub_forest[0].value.individual_alloc_perc, 20.0);
+    assert_close(even.sub_forest[0].value.inherited_alloc_perc, 20.0);
+    assert_close(collapsed.tree.value.inherited_time_perc, 100.0);
+
+    assert_eq!(collapsed.recursion.get(&103), Some(&4));
+    assert_eq!(collapsed.recursion.get(&110), Some(&2));
+    assert_eq!(collapsed.recursion.get(&111), Some(&2));
+    assert_eq!(collapsed.recursion.get(&102), None);
+}
+
+#[test]
+fn keeps_inherited_costs_of_unchanged_subtrees() {
+    // Many cost centres of hoogle.prof show 0.0 individually but not inherited.
+    let input = read_profile("../example_format/hoogle.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let collapsed = collapse_recursion(tree);
+    assert_close(collapsed.tree.value.inherited_time_perc, tree.value.inherited_time_perc);
+    assert_close(collapsed.tree.value.inherited_alloc_perc, tree.value.inherited_alloc_perc);
+    assert!(!collapsed.recursion.is_empty());
+}
+
+#[test]
+fn trees_without_recursion_are_unchanged() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let collapsed = collapse_recursion(tree);
+    assert!(collapsed.recursion.is_empty());
+    let (mut before, mut after) = (Vec::new(), Vec::new());
+    outline(tree, &mut before);
+    outline(&collapsed.tree, &mut after);
+    assert_eq!(before, after);
+    assert_close(collapsed.tree.sub_forest[2].sub_forest[0].value.inherited_time_perc, 100.0);
+}
+
+fn rules(hide: &[&str], keep: &[&str]) -> HideRules {
+    HideRules {
+        hide: hide.iter().map(|s| s.to_string()).collect(),
+        keep: keep.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+#[test]
+fn hide_rules() {
+    let rules = rules(&["GHC.*", "*.CAF", "*.CAF:*", "*\\*"], &["GHC.Keep.*"]);
+    assert!(rules.hides("CAF", "Main"));
+    assert!(rules.hides("CAF:lvl3_r1dk", "Main"));
+    assert!(rules.hides("writeItems.\\.\\.bs", "Main"));
+    assert!(rules.hides("hPutStr", "GHC.IO.Handle.Text"));
+    assert!(!rules.hides("hPutStr", "GHC.Keep.Text"));
+    assert!(!rules.hides("main", "Main"));
+}
+
+#[test]
+fn hidden_costs_go_to_the_closest_visible_caller() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let hidden = hide(tree, &rules(&["*.CAF", "Main.countWords.bump"], &[]));
+
+    let mut nodes = Vec::new();
+    outline(&hidden, &mut nodes);
+    assert_eq!(nodes,
+               vec![(0, "MAIN", 106, 0), (1, "main", 212, 1), (2, "countWords", 214, 1), (2, "tokenize", 213, 1)]);
+    let count_words = &hidden.sub_forest[0].sub_forest[0];
+    assert_close(count_words.value.individual_time_perc, 61.5);
+    assert_close(count_words.value.inherited_time_perc, 61.5);
+    assert_close(hidden.value.inherited_alloc_perc, 100.0);
+    assert_close(hidden.sub_forest[0].value.inherited_time_perc, 100.0);
+}
+
+#[test]
+fn kept_cost_centres_move_up_and_merge() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let hidden = hide(tree, &rules(&["*"], &["Main.tokenize"]));
+
+    let mut nodes = Vec::new();
+    outline(&hidden, &mut nodes);
+    assert_eq!(nodes, vec![(0, "MAIN", 106, 0), (1, "tokenize", 213, 1)]);
+    assert_close(hidden.value.individual_time_perc, 69.9);
+    assert_close(hidden.value.inherited_time_perc, 100.0);
+}
+
+#[test]
+fn hiding_libraries_keeps_the_totals() {
+    let input = read_profile("../example_format/hoogle.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let hidden = hide(tree, &rules(&["GHC.*", "Data.*", "Control.*", "*.CAF", "*.CAF:*"], &[]));
+    assert_close(hidden.value.inherited_time_perc, tree.value.inherited_time_perc);
+    assert_close(hidden.value.inherited_alloc_perc, tree.value.inherited_alloc_perc);
+
+    fn check(tree: &RoseTree<ExtendedSummaryLine>) {
+        assert!(!tree.value.module.starts_with("Data.") && tree.value.cost_centre != "CAF");
+        let children: f32 = tree.sub_forest.iter().map(|t| t.value.inherited_time_perc).sum();
+        assert!(children <= tree.value.inherited_time_perc + 0.01);
+        for t in &tree.sub_forest {
+            assert_eq!(t.depth, tree.depth + 1);
+            check(t);
+        }
+    }
+    check(&hidden);
+}
+
+#[test]
+fn caf_names() {
+    assert!(is_caf("CAF"));
+    assert!(is_caf("CAF:main1"));
+    assert!(!is_caf("CAFE"));
+    assert!(!is_caf("main"));
+}
+
+#[test]
+fn groups_cafs_by_module() {
+    let input = read_profile("../example_format/cafs.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let cafs = group_cafs(tree);
+
+    let mut nodes = Vec::new();
+    outline(&cafs, &mut nodes);
+    assert_eq!(nodes,
+               vec![(0, "MAIN", 100, 0),
+                    (1, "CAF", 101, 0),
+                    (2, "main", 102, 1),
+                    (3, "render", 103, 1),
+                    (2, "CAF:table", 104, 0),
+                    (3, "mkTable", 105, 1),
+                    (1, "CAF", 106, 0),
+                    (2, "fromList", 107, 1),
+                    (1, "CAF", 108, 0)]);
+    // The costs of MAIN itself aren't those of a CAF.
+    assert_close(cafs.value.individual_time_perc, 0.0);
+    assert_close(cafs.value.inherited_time_perc, 90.0);
+    assert_close(cafs.value.inherited_alloc_perc, 90.0);
+    assert_close(cafs.sub_forest[0].value.inherited_alloc_perc, 80.0);
+    assert_close(cafs.sub_forest[1].value.individual_time_perc, 5.0);
+    assert_eq!(cafs.sub_forest[1].value.module, "Data.Map.Internal");
+}
+
+#[test]
+fn leaves_cafs_out_of_the_percentages() {
+    let input = read_profile("../example_format/cafs.prof");
+    let prof = parse_profile(&input);
+    let tree = tree(&prof);
+    let excluded = exclude_cafs(tree);
+
+    // CAFs cost 20% of the time and 25% of the allocations themselves.
+    assert_close(excluded.value.inherited_time_perc, 100.0);
+    assert_close(excluded.value.inherited_alloc_perc, 100.0);
+    assert_close(excluded.value.individual_time_perc, 12.5);
+    let main = &excluded.sub_forest[0].sub_forest[0];
+    assert_close(main.value.inherited_time_perc, 75.0);
+    assert_close(main.sub_forest[0].value.individual_alloc_perc, 53.33);
+    let table = &excluded.sub_forest[1];
+    assert_close(table.value.individual_time_perc, 0.0);
+    assert_close(table.value.inherited_time_perc, 6.25);
+    assert_eq!(table.sub_forest[0].value.entries, 1);
+}
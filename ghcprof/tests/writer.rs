@@ -0,0 +1,53 @@
+
+extern crate ghcprof;
+
+mod common;
+
+use common::{parse_profile, read_profile};
+use ghcprof::parser::parse_prof_file;
+use ghcprof::writer::write_prof;
+use std::str;
+
+fn round_trip(path: &str) {
+    let input = read_profile(path);
+    let prof = parse_profile(&input);
+    let mut out = Vec::new();
+    write_prof(&mut out, &prof).unwrap();
+    match parse_prof_file(&out) {
+        Some(parsed) => assert_eq!(parsed, prof),
+        None => panic!("cannot parse back:\n{}", str::from_utf8(&out).unwrap()),
+    }
+}
+
+#[test]
+fn can_write_profiles_back() {
+    round_trip("../example_format/rncryptor-tests.prof");
+    round_trip("../example_format/hoogle.prof");
+}
+
+#[test]
+fn can_write_profiles_with_src() {
+    round_trip("../example_format/ghc8.prof");
+}
+
+#[test]
+fn can_write_ticks_and_bytes() {
+    round_trip("../example_format/ghc8-ticks.prof");
+}
+
+#[test]
+fn writes_the_header_like_ghc() {
+    let input = read_profile("../example_format/ghc8.prof");
+    let prof = parse_profile(&input);
+    let mut out = Vec::new();
+    write_prof(&mut out, &prof).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = text.lines().take(6).collect();
+    assert_eq!(lines,
+               vec!["\tMon Oct 16 10:02 2017 Time and Allocation Profiling Report  (Final)",
+                    "",
+                    "\t   wordfreq +RTS -p -RTS corpus.txt",
+                    "",
+                    "\ttotal time  =        0.45 secs   (449 ticks @ 1000 us, 1 processor)",
+                    "\ttotal alloc = 1,073,546,184 bytes  (excludes profiling overheads)"]);
+}
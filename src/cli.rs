@@ -1,41 +1,544 @@
 extern crate clap;
 
-use cli::clap::{Arg, App};
+use cli::clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
+use ghcprof::check::Budget;
+use ghcprof::dot::GraphOptions;
+use ghcprof::merge::Combine;
+use ghcprof::metric::Metric;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std;
 
 #[derive(Debug)]
 pub enum CliParseError {
     NotAProfilingFile(std::string::String),
+    InvalidValue(std::string::String),
+}
+
+impl fmt::Display for CliParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliParseError::NotAProfilingFile(ref msg) | CliParseError::InvalidValue(ref msg) => f.write_str(msg),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Command {
+    /// Opens each profile in its own tab.
+    Browse(Vec<PathBuf>, BrowseOptions),
+    Report(PathBuf, ReportOptions),
+    Merge(Vec<PathBuf>, MergeOptions),
+    /// Checks a profile against budget rules, a baseline or both.
+    Check(Option<PathBuf>, PathBuf, CheckOptions),
+    /// Lists the call paths selected by a query, see `ghcprof::query`.
+    Query(PathBuf, String),
+    /// Lists the calls between modules, see `ghcprof::rollup::module_calls`.
+    Modules(PathBuf, ModulesOptions),
+    /// Writes a profile in another format, compared with a baseline when
+    /// the format allows it.
+    Export(Option<PathBuf>, PathBuf, ExportOptions),
+    PrintDefaultConfig,
+}
+
+#[derive(Debug)]
+pub struct BrowseOptions {
+    /// Where to look for the sources of the profiled program.
+    pub src_root: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct MergeOptions {
+    pub combine: Combine,
+    /// Where to write the merged profile, stdout if `None`.
+    pub output: Option<PathBuf>,
+    /// Shows the merged profile in the TUI instead of writing it.
+    pub browse: bool,
+}
+
+#[derive(Debug)]
+pub struct CheckOptions {
+    /// How much worse than the baseline the profile may get.
+    pub budget: Budget,
+    /// A file of budget rules, see `rules::load`.
+    pub rules: Option<PathBuf>,
+    /// Where to write a JUnit XML report, if anywhere.
+    pub junit: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct ModulesOptions {
+    /// Writes a Graphviz graph instead of a table.
+    pub dot: bool,
+    pub metric: Metric,
+    /// Leaves out the calls costing less than this percentage.
+    pub threshold: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// A Graphviz call graph, see `ghcprof::dot::write_call_graph`.
+    Dot,
+    /// A standalone page, see `html::HtmlReport`.
+    Html,
+    /// Tables for pull requests, see `ghcprof::markdown`.
+    Markdown,
+    /// A pprof `profile.proto`, see `ghcprof::pprof::write_pprof`.
+    Pprof,
+    /// Chrome Trace Event JSON, see `ghcprof::trace::write_trace`.
+    Trace,
+    /// For KCachegrind, see `ghcprof::callgrind::write_callgrind`.
+    Callgrind,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s {
+            "dot" => Ok(ExportFormat::Dot),
+            "html" => Ok(ExportFormat::Html),
+            "markdown" => Ok(ExportFormat::Markdown),
+            "pprof" => Ok(ExportFormat::Pprof),
+            "trace" => Ok(ExportFormat::Trace),
+            "callgrind" => Ok(ExportFormat::Callgrind),
+            _ => Err(format!("Unknown format {:?}, expected one of: {}.", s, EXPORT_FORMATS.join(", "))),
+        }
+    }
+}
+
+const EXPORT_FORMATS: &'static [&'static str] = &["dot", "html", "markdown", "pprof", "trace", "callgrind"];
+
+#[derive(Debug)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Where to write the export, stdout if nowhere.
+    pub output: Option<PathBuf>,
+    /// What a call graph shows.
+    pub graph: GraphOptions,
+    /// How many rows a Markdown table has.
+    pub top: usize,
+}
+
+const DEFAULT_WIDTH: usize = 120;
+const DEFAULT_DEPTH: usize = 10;
+const DEFAULT_THRESHOLD: f32 = 1.0;
+const DEFAULT_METRIC: Metric = Metric::Time;
+const DEFAULT_TOP: usize = 10;
+
+#[derive(Debug)]
+pub struct ReportOptions {
+    pub width: usize,
+    pub depth: usize,
+    pub threshold: f32,
+    pub metric: Metric,
+    pub top: usize,
+    /// Whether CAFs are listed apart in the flat summary.
+    pub separate_cafs: bool,
+    /// Whether the costs of CAFs themselves are left out of the percentages.
+    pub exclude_cafs: bool,
+}
+
+impl Default for ReportOptions {
+    fn default() -> ReportOptions {
+        ReportOptions {
+            width: DEFAULT_WIDTH,
+            depth: DEFAULT_DEPTH,
+            threshold: DEFAULT_THRESHOLD,
+            metric: DEFAULT_METRIC,
+            top: DEFAULT_TOP,
+            separate_cafs: false,
+            exclude_cafs: false,
+        }
+    }
+}
+
+/// The defaults of the report options as command line values.
+struct ReportDefaults {
+    width: String,
+    depth: String,
+    threshold: String,
+    top: String,
+}
+
+impl ReportDefaults {
+    fn new() -> ReportDefaults {
+        ReportDefaults {
+            width: DEFAULT_WIDTH.to_string(),
+            depth: DEFAULT_DEPTH.to_string(),
+            threshold: format!("{:.1}", DEFAULT_THRESHOLD),
+            top: DEFAULT_TOP.to_string(),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Args {
-    pub file_path: PathBuf,
+    pub command: Command,
 }
 
 impl Args {
     pub fn parse() -> Result<Args, CliParseError> {
-        let matches = cli().get_matches();
-        let prof_file = try!(matches.value_of("input_file")
-            .ok_or(CliParseError::NotAProfilingFile(String::from("An input file is required.")))
-            .map(PathBuf::from));
-        let args = Args { file_path: prof_file };
-        Ok(args)
+        let defaults = ReportDefaults::new();
+        let matches = cli(&defaults).get_matches();
+        match matches.subcommand() {
+            ("report", Some(sub)) => {
+                let opts = ReportOptions {
+                    width: try!(parse_value(sub, "width")),
+                    depth: try!(parse_value(sub, "depth")),
+                    threshold: try!(parse_value(sub, "threshold")),
+                    metric: try!(parse_value(sub, "metric")),
+                    top: try!(parse_value(sub, "top")),
+                    separate_cafs: sub.is_present("separate_cafs"),
+                    exclude_cafs: sub.is_present("exclude_cafs"),
+                };
+                Ok(Args { command: Command::Report(try!(input_file(sub)), opts) })
+            }
+            ("merge", Some(sub)) => {
+                let files = sub.values_of("files").into_iter().flat_map(|v| v).map(PathBuf::from).collect();
+                let opts = MergeOptions {
+                    combine: if sub.is_present("average") { Combine::Average } else { Combine::Sum },
+                    output: sub.value_of("output").map(PathBuf::from),
+                    browse: sub.is_present("browse"),
+                };
+                Ok(Args { command: Command::Merge(files, opts) })
+            }
+            ("check", Some(sub)) => {
+                let budget = Budget {
+                    scope: sub.values_of("scope").into_iter().flat_map(|v| v).map(String::from).collect(),
+                    max_time_increase: try!(parse_perc(sub, "max-time-increase")),
+                    max_alloc_increase: try!(parse_perc(sub, "max-alloc-increase")),
+                    min_cost: try!(parse_value(sub, "min-cost")),
+                };
+                let has_increase = budget.max_time_increase.is_some() || budget.max_alloc_increase.is_some();
+                let opts = CheckOptions {
+                    budget: budget,
+                    rules: sub.value_of("rules").map(PathBuf::from),
+                    junit: sub.value_of("junit").map(PathBuf::from),
+                };
+                let mut files: Vec<PathBuf> = sub.values_of("files").unwrap().map(PathBuf::from).collect();
+                let current = files.pop().unwrap();
+                let baseline = files.pop();
+                match (&baseline, has_increase) {
+                    (&Some(_), false) if opts.rules.is_none() => {
+                        Err(CliParseError::InvalidValue(String::from("Comparing with a baseline needs \
+                                                                      --max-time-increase or --max-alloc-increase.")))
+                    }
+                    (&None, true) => {
+                        Err(CliParseError::InvalidValue(String::from("--max-time-increase and --max-alloc-increase \
+                                                                      need a baseline to compare with.")))
+                    }
+                    (&None, false) if opts.rules.is_none() => {
+                        Err(CliParseError::InvalidValue(String::from("Either --rules or a baseline is required.")))
+                    }
+                    _ => Ok(Args { command: Command::Check(baseline, current, opts) }),
+                }
+            }
+            ("query", Some(sub)) => {
+                let file = PathBuf::from(sub.value_of("file").unwrap());
+                Ok(Args { command: Command::Query(file, sub.value_of("query").unwrap().to_string()) })
+            }
+            ("modules", Some(sub)) => {
+                let opts = ModulesOptions {
+                    dot: sub.is_present("dot"),
+                    metric: try!(parse_value(sub, "metric")),
+                    threshold: try!(parse_perc(sub, "threshold")).unwrap_or(0.0),
+                };
+                Ok(Args { command: Command::Modules(PathBuf::from(sub.value_of("file").unwrap()), opts) })
+            }
+            ("export", Some(sub)) => {
+                let graph = GraphOptions {
+                    merge: !sub.is_present("tree"),
+                    metric: try!(parse_value(sub, "metric")),
+                    threshold: try!(parse_perc(sub, "threshold")).unwrap_or(0.0),
+                    max_nodes: match sub.value_of("max-nodes") {
+                        Some(_) => Some(try!(parse_value(sub, "max-nodes"))),
+                        None => None,
+                    },
+                };
+                let opts = ExportOptions {
+                    format: try!(parse_value(sub, "format")),
+                    output: sub.value_of("output").map(PathBuf::from),
+                    graph: graph,
+                    top: try!(parse_value(sub, "top")),
+                };
+                let mut files: Vec<PathBuf> = sub.values_of("files").unwrap().map(PathBuf::from).collect();
+                let current = files.pop().unwrap();
+                let baseline = files.pop();
+                if baseline.is_some() && opts.format != ExportFormat::Markdown {
+                    return Err(CliParseError::InvalidValue(String::from("Only the markdown format compares \
+                                                                          with a baseline.")));
+                }
+                Ok(Args { command: Command::Export(baseline, current, opts) })
+            }
+            ("config", Some(_)) => Ok(Args { command: Command::PrintDefaultConfig }),
+            _ => {
+                let opts = BrowseOptions { src_root: matches.value_of("src_root").map(PathBuf::from) };
+                let files: Vec<PathBuf> = matches.values_of("input_file")
+                    .into_iter()
+                    .chain(matches.values_of("files"))
+                    .flat_map(|values| values)
+                    .map(PathBuf::from)
+                    .collect();
+                if files.is_empty() {
+                    return Err(CliParseError::NotAProfilingFile(String::from("An input file is required.")));
+                }
+                Ok(Args { command: Command::Browse(files, opts) })
+            }
+        }
     }
 }
 
-pub fn cli() -> App<'static, 'static> {
-    let prof_file_arg = Arg::with_name("input_file")
+fn input_file(matches: &ArgMatches) -> Result<PathBuf, CliParseError> {
+    matches.value_of("input_file")
+        .ok_or(CliParseError::NotAProfilingFile(String::from("An input file is required.")))
+        .map(PathBuf::from)
+}
+
+fn parse_value<T>(matches: &ArgMatches, name: &str) -> Result<T, CliParseError>
+    where T: FromStr
+{
+    let raw = matches.value_of(name).unwrap_or("");
+    raw.parse::<T>()
+        .map_err(|_| CliParseError::InvalidValue(format!("Invalid value {:?} for --{}.", raw, name)))
+}
+
+/// A percentage such as `5%` or `5`, if given.
+fn parse_perc(matches: &ArgMatches, name: &str) -> Result<Option<f32>, CliParseError> {
+    match matches.value_of(name) {
+        None => Ok(None),
+        Some(raw) => {
+            let number = if raw.ends_with('%') { &raw[..raw.len() - 1] } else { raw };
+            number.parse::<f32>()
+                .map(Some)
+                .map_err(|_| CliParseError::InvalidValue(format!("Invalid value {:?} for --{}.", raw, name)))
+        }
+    }
+}
+
+fn prof_file_arg() -> Arg<'static, 'static> {
+    Arg::with_name("input_file")
         .long("file")
         .short("f")
         .value_name("PATH_TO_FILE")
         .help("The path to a valid .prof file.")
-        .required(true);
+        .required(true)
+}
+
+fn report_cmd<'a>(defaults: &'a ReportDefaults) -> App<'a, 'a> {
+    SubCommand::with_name("report")
+        .about("Prints a non-interactive summary of the profile.")
+        .arg(prof_file_arg())
+        .arg(Arg::with_name("width")
+            .long("width")
+            .value_name("COLUMNS")
+            .help("The maximum width of each line.")
+            .default_value(&defaults.width))
+        .arg(Arg::with_name("depth")
+            .long("depth")
+            .value_name("N")
+            .help("How deep the cost centre tree is printed.")
+            .default_value(&defaults.depth))
+        .arg(Arg::with_name("threshold")
+            .long("threshold")
+            .value_name("PERC")
+            .help("Hides the subtrees whose inherited cost is below this percentage.")
+            .default_value(&defaults.threshold))
+        .arg(Arg::with_name("metric")
+            .long("metric")
+            .value_name("METRIC")
+            .help("The metric used to rank and prune cost centres.")
+            .possible_values(&["time", "alloc"])
+            .default_value(DEFAULT_METRIC.name()))
+        .arg(Arg::with_name("top")
+            .long("top")
+            .value_name("N")
+            .help("How many cost centres to list in the summary.")
+            .default_value(&defaults.top))
+        .arg(Arg::with_name("separate_cafs")
+            .long("separate-cafs")
+            .help("Lists the CAFs apart, after the other cost centres of the summary."))
+        .arg(Arg::with_name("exclude_cafs")
+            .long("exclude-cafs")
+            .help("Leaves the costs of CAFs themselves out of the percentages."))
+}
+
+fn merge_cmd() -> App<'static, 'static> {
+    SubCommand::with_name("merge")
+        .about("Combines several profiles of the same program into one, by call path.")
+        .arg(Arg::with_name("files")
+            .value_name("FILE")
+            .help("The .prof files to merge.")
+            .required(true)
+            .multiple(true))
+        .arg(Arg::with_name("average")
+            .long("average")
+            .help("Averages the costs instead of summing them, e.g. for repeated runs."))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .value_name("FILE")
+            .help("Writes the merged profile to FILE instead of stdout.")
+            .conflicts_with("browse"))
+        .arg(Arg::with_name("browse")
+            .long("browse")
+            .help("Opens the merged profile in the interactive UI instead of writing it."))
+}
+
+fn check_cmd() -> App<'static, 'static> {
+    SubCommand::with_name("check")
+        .about("Fails when a profile breaks budget rules, or costs more than a baseline.")
+        .arg(Arg::with_name("files")
+            .value_name("FILE")
+            .help("The .prof file to check, preceded by a baseline to compare it with.")
+            .required(true)
+            .multiple(true)
+            .max_values(2))
+        .arg(Arg::with_name("rules")
+            .long("rules")
+            .value_name("FILE")
+            .help("Checks the profile against the budget rules in FILE, a TOML file."))
+        .arg(Arg::with_name("max-time-increase")
+            .long("max-time-increase")
+            .value_name("PERC")
+            .help("The largest increase in time allowed, e.g. 5%."))
+        .arg(Arg::with_name("max-alloc-increase")
+            .long("max-alloc-increase")
+            .value_name("PERC")
+            .help("The largest increase in allocation allowed, e.g. 10%."))
+        .arg(Arg::with_name("scope")
+            .long("scope")
+            .value_name("PATTERN")
+            .help("Only compares the cost centres matching PATTERN, e.g. 'Data.Map.*'. Can be repeated.")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("min-cost")
+            .long("min-cost")
+            .value_name("PERC")
+            .help("Skips the cost centres costing less than this percentage in both profiles.")
+            .default_value("1.0"))
+        .arg(Arg::with_name("junit")
+            .long("junit")
+            .value_name("FILE")
+            .help("Also writes the results to FILE as JUnit XML, for CI servers."))
+}
+
+fn query_cmd() -> App<'static, 'static> {
+    SubCommand::with_name("query")
+        .about("Lists the call paths matching a query, e.g. 'main/**/pretty[inherited-alloc > 5]'.")
+        .arg(Arg::with_name("file")
+            .value_name("FILE")
+            .help("The .prof file to search.")
+            .required(true))
+        .arg(Arg::with_name("query")
+            .value_name("QUERY")
+            .help("Cost centre patterns separated by /, with ** for any path in between and \
+                   conditions in brackets on no, entries, ticks, bytes and \
+                   {individual,inherited}-{time,alloc}.")
+            .required(true))
+}
+
+fn modules_cmd() -> App<'static, 'static> {
+    SubCommand::with_name("modules")
+        .about("Lists which modules call into which, and what the calls cost.")
+        .arg(Arg::with_name("file")
+            .value_name("FILE")
+            .help("The .prof file to read.")
+            .required(true))
+        .arg(Arg::with_name("dot")
+            .long("dot")
+            .help("Writes a Graphviz graph, for dot -Tsvg, instead of a table."))
+        .arg(Arg::with_name("metric")
+            .long("metric")
+            .value_name("METRIC")
+            .help("The metric the calls are sorted by, and drawn thicker for.")
+            .possible_values(&["time", "alloc"])
+            .default_value("time"))
+        .arg(Arg::with_name("threshold")
+            .long("threshold")
+            .value_name("PERC")
+            .help("Leaves out the calls costing less than this percentage of the metric."))
+}
+
+fn export_cmd() -> App<'static, 'static> {
+    SubCommand::with_name("export")
+        .about("Writes a profile in another format, e.g. a Graphviz call graph or an HTML page.")
+        .arg(Arg::with_name("files")
+            .value_name("FILE")
+            .help("The .prof file to export, preceded by a baseline to compare it with in Markdown.")
+            .required(true)
+            .multiple(true)
+            .max_values(2))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .help("The format to write.")
+            .possible_values(EXPORT_FORMATS)
+            .required(true))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .value_name("FILE")
+            .help("Writes the export to FILE instead of stdout."))
+        .arg(Arg::with_name("tree")
+            .long("tree")
+            .help("Keeps a node per call path, as in the cost centre tree, rather than merging the \
+                   calls to each cost centre."))
+        .arg(Arg::with_name("metric")
+            .long("metric")
+            .value_name("METRIC")
+            .help("The metric call graphs, flame graphs and Markdown tables are driven by.")
+            .possible_values(&["time", "alloc"])
+            .default_value("time"))
+        .arg(Arg::with_name("threshold")
+            .long("threshold")
+            .value_name("PERC")
+            .help("Leaves out the cost centres whose inherited cost is below this percentage.")
+            .default_value("1.0"))
+        .arg(Arg::with_name("max-nodes")
+            .long("max-nodes")
+            .value_name("N")
+            .help("Keeps only the N most expensive cost centres, by inherited cost."))
+        .arg(Arg::with_name("top")
+            .long("top")
+            .value_name("N")
+            .help("How many cost centres, call paths and changes the Markdown tables list.")
+            .default_value("10"))
+}
+
+fn config_cmd() -> App<'static, 'static> {
+    SubCommand::with_name("config")
+        .about("Inspects the configuration.")
+        .arg(Arg::with_name("print_default")
+            .long("print-default")
+            .help("Prints the default configuration, to be used as a starting point.")
+            .required(true))
+}
+
+fn cli<'a>(defaults: &'a ReportDefaults) -> App<'a, 'a> {
     let app = App::new("Provis")
         .version("0.0.1")
         .author("Alfredo Di Napoli")
         .about("Interactive Haskell .prof visualiser.")
-        .arg(prof_file_arg);
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(prof_file_arg().required(false))
+        .arg(Arg::with_name("files")
+            .value_name("FILE")
+            .help("More .prof files, each opened in its own tab.")
+            .multiple(true))
+        .arg(Arg::with_name("src_root")
+            .long("src-root")
+            .value_name("DIR")
+            .help("Where to look for the sources of the profiled program, to preview cost \
+                   centres or open them in $EDITOR. Profiles made by GHC 8 or later are \
+                   relative to it."))
+        .subcommand(report_cmd(defaults))
+        .subcommand(merge_cmd())
+        .subcommand(check_cmd())
+        .subcommand(query_cmd())
+        .subcommand(modules_cmd())
+        .subcommand(export_cmd())
+        .subcommand(config_cmd());
     app
 }
@@ -0,0 +1,608 @@
+extern crate rustbox;
+extern crate toml;
+
+use self::rustbox::Color;
+use self::toml::{Parser, Table, Value};
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use editor;
+use ui::keys::{Action, Keymap, KeySeq, KeyStyle, KeyTable, ACTIONS, key_seq_name, parse_key_seq};
+use ui::style::{ColourDepth, HeatMode, HeatScale, Palette, Theme, THEMES};
+use ui::types::{Bars, Column, Named, SortKey, View, ViewSettings, BARS, SORT_KEYS, VIEWS};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IOError(PathBuf, io::Error),
+    /// The file is not valid TOML.
+    ParseError(PathBuf, String),
+    /// The file is valid TOML, but doesn't describe a valid configuration.
+    InvalidConfig(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::IOError(ref path, ref e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::ParseError(ref path, ref e) |
+            ConfigError::InvalidConfig(ref path, ref e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub heat: HeatScale,
+    pub theme: Theme,
+    pub colours: ColourDepth,
+    pub keys: KeyTable,
+    pub view: ViewSettings,
+    /// The command opening a cost centre's source, see `editor::open`.
+    pub editor: String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            heat: HeatScale::default(),
+            theme: Theme::default(),
+            colours: ColourDepth::detect(),
+            keys: Keymap::default().lookup_table().unwrap(),
+            view: ViewSettings::default(),
+            editor: editor::DEFAULT_COMMAND.to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the user configuration, overridden by the project one, if any.
+    /// Missing files are not an error: the defaults are used instead.
+    pub fn load() -> Result<Config, ConfigError> {
+        let user = match user_config_path() {
+            Some(path) => if path.is_file() { Some(try!(read_table(path))) } else { None },
+            None => None,
+        };
+        let project = match project_config_path() {
+            Some(path) => Some(try!(read_table(path))),
+            None => None,
+        };
+        match (user, project) {
+            (None, None) => Ok(Config::default()),
+            (Some((path, table)), None) | (None, Some((path, table))) => {
+                Config::from_table(&table).map_err(|e| ConfigError::InvalidConfig(path, e))
+            }
+            (Some((user_path, user_table)), Some((project_path, project_table))) => {
+                // The user config must make sense on its own, as it applies
+                // to every project.
+                try!(Config::from_table(&user_table).map_err(|e| ConfigError::InvalidConfig(user_path, e)));
+                let mut merged = user_table;
+                merge_tables(&mut merged, project_table);
+                Config::from_table(&merged).map_err(|e| ConfigError::InvalidConfig(project_path, e))
+            }
+        }
+    }
+
+    fn from_table(table: &Table) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "theme" | "themes" | "keybindings" | "keys" => {}
+                "colours" => config.colours = try!(parse_colour_depth(value)),
+                "heat" => config.heat = try!(parse_heat(value)),
+                "view" => config.view = try!(parse_view(value)),
+                "editor" => config.editor = try!(parse_editor(value)),
+                _ => return Err(format!("unknown key `{}`", key)),
+            }
+        }
+        if let Some(name) = table.get("theme") {
+            let name = try!(name.as_str().ok_or("`theme` must be a string".to_string()));
+            config.theme = try!(find_theme(name, table.get("themes")));
+        }
+        config.keys = try!(parse_keymap(table.get("keybindings"), table.get("keys")));
+        Ok(config)
+    }
+
+    pub fn palette(&self) -> Palette {
+        Palette::new(self.heat.clone(), self.theme.clone(), self.colours)
+    }
+}
+
+/// The configuration with every setting at its default value, as TOML.
+pub fn default_config() -> String {
+    let defaults = Config::default();
+    let keymap = Keymap::default();
+    let bindings: Vec<String> = ACTIONS.iter().map(|a| {
+        let keys: Vec<String> = keymap.keys(*a).iter().map(|k| format!("{:?}", key_seq_name(k))).collect();
+        format!("# {} = [{}]", a.name(), keys.join(", "))
+    }).collect();
+    let thresholds: Vec<String> = defaults.heat.thresholds.iter().map(|t| format!("{:.1}", t)).collect();
+    format!(r#"# profiv reads ~/.config/profiv/config.toml, overridden by the first
+# .profiv.toml found in the current directory or its parents.
+
+# {themes}, or one defined under [themes].
+theme = "dark"
+
+# "8", "256" or "auto" (256 when $TERM ends in 256color).
+colours = "auto"
+
+# "vim" or "emacs". Single actions can be rebound under [keys].
+keybindings = "vim"
+
+# How to open the selected cost centre's source: {{file}}, {{line}} and
+# {{column}} are substituted, $EDITOR falls back on $VISUAL, then on vi.
+# e.g. "code --goto {{file}}:{{line}}:{{column}}" or "emacsclient -n +{{line}} {{file}}".
+editor = "{editor}"
+
+[heat]
+# "absolute": thresholds are percentages of the whole program.
+# "relative": thresholds are percentages of the most expensive cost centre.
+mode = "absolute"
+thresholds = [{thresholds}]
+
+[view]
+# {views}
+default = "{view}"
+# {sort_keys}
+sort = "{sort}"
+columns = [{columns}]
+# Bars next to the percentages of the tree, full at the largest percentage
+# of the column ("max") or at the inherited cost of the caller ("parent"):
+# {bars}
+bars = "{bar}"
+# Whether the source of the selected cost centre is shown below the profile.
+preview = {preview}
+# Whether recursive calls, such as go -> go -> go, are merged into the outermost one.
+collapse-recursion = {collapse_recursion}
+# Whether the cost centres matching `hide` are left out of the tree, their
+# costs going to their caller. Patterns are on Module.cost_centre, with *
+# and ? wildcards, and `keep` lists exceptions.
+hide-libraries = {hide_libraries}
+hide = [{hide}]
+keep = [{keep}]
+# Whether the costs of CAFs themselves are left out of the percentages, the
+# others being scaled up to add up to 100% again.
+exclude-cafs = {exclude_cafs}
+# Whether CAFs are listed apart at the end of the flat summary.
+separate-cafs = {separate_cafs}
+
+[keys]
+# Keys are single characters, "C-x" for Ctrl+x, F1 to F12 or one of: Up,
+# Down, Left, Right, PageUp, PageDown, Home, End, Enter, Tab, Space, Esc,
+# Backspace, Delete, Insert. Keys pressed one after the other are separated
+# by spaces, e.g. "g t". 1 to 9 switch to the matching tab when unbound.
+# The vim bindings are:
+{bindings}
+
+# [themes.sepia]
+# foreground = "black"
+# background = 230
+# basic = ["black", "yellow", "red"]
+# gradient = [94, 130, 166, 202]
+"#,
+            themes = THEMES.join(", "),
+            editor = defaults.editor,
+            thresholds = thresholds.join(", "),
+            views = names(VIEWS).join(", "),
+            view = defaults.view.view.name(),
+            sort_keys = names(SORT_KEYS).join(", "),
+            sort = defaults.view.sort.name(),
+            bars = names(BARS).join(", "),
+            bar = defaults.view.bars.name(),
+            preview = defaults.view.preview,
+            collapse_recursion = defaults.view.collapse_recursion,
+            hide_libraries = defaults.view.hide_libraries,
+            hide = quoted(&defaults.view.hidden.hide).join(", "),
+            keep = quoted(&defaults.view.hidden.keep).join(", "),
+            exclude_cafs = defaults.view.exclude_cafs,
+            separate_cafs = defaults.view.separate_cafs,
+            columns = names(&defaults.view.columns).iter().map(|c| format!("{:?}", c)).collect::<Vec<_>>().join(", "),
+            bindings = bindings.join("\n"))
+}
+
+fn quoted(values: &[String]) -> Vec<String> {
+    values.iter().map(|v| format!("{:?}", v)).collect()
+}
+
+fn names<T: Named>(values: &[T]) -> Vec<&'static str> {
+    values.iter().map(|v| v.name()).collect()
+}
+
+pub fn read_table(path: PathBuf) -> Result<(PathBuf, Table), ConfigError> {
+    let mut content = String::new();
+    match File::open(&path).and_then(|mut f| f.read_to_string(&mut content)) {
+        Ok(_) => {}
+        Err(e) => return Err(ConfigError::IOError(path, e)),
+    }
+    let table = try!(parse_toml(&path, &content));
+    Ok((path, table))
+}
+
+/// Recursively overrides the keys of `base` with the ones in `overrides`.
+fn merge_tables(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        let merged = match (base.get_mut(&key), value) {
+            (Some(&mut Value::Table(ref mut inner)), Value::Table(other)) => {
+                merge_tables(inner, other);
+                None
+            }
+            (_, value) => Some(value),
+        };
+        if let Some(value) = merged {
+            base.insert(key, value);
+        }
+    }
+}
+
+/// The first `.profiv.toml` in the current directory or its ancestors.
+fn project_config_path() -> Option<PathBuf> {
+    let mut dir = match env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return None,
+    };
+    loop {
+        let candidate = dir.join(".profiv.toml");
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if !dir.pop() {
+            return None;
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/profiv/config.toml`, or `~/.config/profiv/config.toml`.
+fn user_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or(env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .map(|d| d.join("profiv").join("config.toml"))
+}
+
+fn parse_toml(path: &Path, content: &str) -> Result<Table, ConfigError> {
+    let mut parser = Parser::new(content);
+    match parser.parse() {
+        Some(table) => Ok(table),
+        None => {
+            let errors: Vec<String> = parser.errors.iter().map(|e| {
+                let (line, col) = parser.to_linecol(e.lo);
+                format!("{}:{}: {}", line + 1, col + 1, e.desc)
+            }).collect();
+            Err(ConfigError::ParseError(path.to_path_buf(), errors.join("; ")))
+        }
+    }
+}
+
+fn parse_colour_depth(value: &Value) -> Result<ColourDepth, String> {
+    match value.as_str() {
+        Some("auto") => Ok(ColourDepth::detect()),
+        Some("8") => Ok(ColourDepth::Basic),
+        Some("256") => Ok(ColourDepth::Extended),
+        _ => Err("`colours` must be one of \"auto\", \"8\" or \"256\"".to_string()),
+    }
+}
+
+fn parse_heat(value: &Value) -> Result<HeatScale, String> {
+    let table = try!(value.as_table().ok_or("`heat` must be a table".to_string()));
+    let mut heat = HeatScale::default();
+    for (key, value) in table {
+        match key.as_str() {
+            "mode" => heat.mode = match value.as_str() {
+                Some("absolute") => HeatMode::Absolute,
+                Some("relative") => HeatMode::Relative,
+                _ => return Err("`heat.mode` must be either \"absolute\" or \"relative\"".to_string()),
+            },
+            "thresholds" => heat.thresholds = try!(parse_thresholds(value)),
+            _ => return Err(format!("unknown key `heat.{}`", key)),
+        }
+    }
+    Ok(heat)
+}
+
+fn parse_thresholds(value: &Value) -> Result<Vec<f32>, String> {
+    let err = "`heat.thresholds` must be a non-empty list of increasing, positive percentages";
+    let values = try!(value.as_slice().ok_or(err.to_string()));
+    let mut thresholds = Vec::new();
+    for v in values {
+        let t = try!(v.as_float().or(v.as_integer().map(|i| i as f64)).ok_or(err.to_string())) as f32;
+        if t < 0.0 || thresholds.last().map_or(false, |&prev| t <= prev) {
+            return Err(err.to_string());
+        }
+        thresholds.push(t);
+    }
+    if thresholds.is_empty() {
+        return Err(err.to_string());
+    }
+    Ok(thresholds)
+}
+
+fn parse_editor(value: &Value) -> Result<String, String> {
+    match value.as_str() {
+        Some(command) if command.contains("{file}") => Ok(command.to_string()),
+        _ => Err("`editor` must be a command containing `{file}`".to_string()),
+    }
+}
+
+fn parse_view(value: &Value) -> Result<ViewSettings, String> {
+    let table = try!(value.as_table().ok_or("`view` must be a table".to_string()));
+    let mut view = ViewSettings::default();
+    for (key, value) in table {
+        let field = format!("view.{}", key);
+        match key.as_str() {
+            "default" => view.view = try!(parse_named::<View>(&field, value)),
+            "sort" => view.sort = try!(parse_named::<SortKey>(&field, value)),
+            "columns" => {
+                let columns = try!(parse_list(&field, value, parse_named::<Column>));
+                for (i, c) in columns.iter().enumerate() {
+                    if columns[..i].contains(c) {
+                        return Err(format!("`{}`: column `{}` is listed twice", field, c.name()));
+                    }
+                }
+                view.columns = columns;
+            }
+            "bars" => view.bars = try!(parse_named::<Bars>(&field, value)),
+            "preview" => {
+                view.preview = try!(value.as_bool().ok_or(format!("`{}` must be true or false", field)));
+            }
+            "collapse-recursion" => {
+                view.collapse_recursion = try!(value.as_bool().ok_or(format!("`{}` must be true or false", field)));
+            }
+            "hide-libraries" => {
+                view.hide_libraries = try!(value.as_bool().ok_or(format!("`{}` must be true or false", field)));
+            }
+            "exclude-cafs" => {
+                view.exclude_cafs = try!(value.as_bool().ok_or(format!("`{}` must be true or false", field)));
+            }
+            "separate-cafs" => {
+                view.separate_cafs = try!(value.as_bool().ok_or(format!("`{}` must be true or false", field)));
+            }
+            "hide" => view.hidden.hide = try!(parse_patterns(&field, value)),
+            "keep" => view.hidden.keep = try!(parse_patterns(&field, value)),
+            _ => return Err(format!("unknown key `{}`", field)),
+        }
+    }
+    Ok(view)
+}
+
+/// A list of patterns, which may be empty.
+fn parse_patterns(field: &str, value: &Value) -> Result<Vec<String>, String> {
+    let err = format!("`{}` must be a list of patterns", field);
+    let values = try!(value.as_slice().ok_or(err.clone()));
+    let mut patterns = Vec::new();
+    for v in values {
+        patterns.push(try!(v.as_str().ok_or(err.clone())).to_string());
+    }
+    Ok(patterns)
+}
+
+fn parse_named<T: Named>(field: &str, value: &Value) -> Result<T, String> {
+    value.as_str().and_then(T::from_name).ok_or_else(|| {
+        format!("`{}` must be one of: {}", field, names(T::all()).join(", "))
+    })
+}
+
+fn parse_keymap(style: Option<&Value>, overrides: Option<&Value>) -> Result<KeyTable, String> {
+    let mut keymap = match style.map(|s| s.as_str()) {
+        None | Some(Some("vim")) => Keymap::preset(KeyStyle::Vim),
+        Some(Some("emacs")) => Keymap::preset(KeyStyle::Emacs),
+        _ => return Err("`keybindings` must be either \"vim\" or \"emacs\"".to_string()),
+    };
+    if let Some(value) = overrides {
+        let table = try!(value.as_table().ok_or("`keys` must be a table".to_string()));
+        for (name, keys) in table {
+            let field = format!("keys.{}", name);
+            let action = try!(Action::from_name(name).ok_or_else(|| {
+                let known: Vec<&str> = ACTIONS.iter().map(|a| a.name()).collect();
+                format!("unknown action `{}`, expected one of: {}", name, known.join(", "))
+            }));
+            let keys = match *keys {
+                Value::String(_) => vec![try!(parse_key_value(&field, keys))],
+                _ => try!(parse_list(&field, keys, parse_key_value)),
+            };
+            keymap.rebind(action, keys);
+        }
+    }
+    keymap.lookup_table()
+}
+
+fn parse_key_value(field: &str, value: &Value) -> Result<KeySeq, String> {
+    let name = try!(value.as_str().ok_or(format!("`{}` must be a key or a list of keys", field)));
+    parse_key_seq(name).map_err(|e| format!("`{}`: {}", field, e))
+}
+
+fn find_theme(name: &str, custom: Option<&Value>) -> Result<Theme, String> {
+    let custom = match custom {
+        None => None,
+        Some(v) => Some(try!(v.as_table().ok_or("`themes` must be a table".to_string()))),
+    };
+    match custom.and_then(|t| t.get(name)) {
+        Some(theme) => parse_theme(name, theme),
+        None => Theme::named(name).ok_or_else(|| {
+            let mut known: Vec<String> = THEMES.iter().map(|t| t.to_string()).collect();
+            known.extend(custom.iter().flat_map(|t| t.keys().cloned()));
+            format!("unknown theme `{}`, expected one of: {}", name, known.join(", "))
+        }),
+    }
+}
+
+fn parse_theme(name: &str, value: &Value) -> Result<Theme, String> {
+    let table = try!(value.as_table().ok_or(format!("`themes.{}` must be a table", name)));
+    let mut theme = Theme::default();
+    for (key, value) in table {
+        let field = format!("themes.{}.{}", name, key);
+        match key.as_str() {
+            "foreground" => theme.foreground = try!(parse_colour(&field, value)),
+            "background" => theme.background = try!(parse_colour_index(&field, value)),
+            "basic" => theme.basic = try!(parse_list(&field, value, parse_colour)),
+            "gradient" => theme.gradient = try!(parse_list(&field, value, parse_colour_index)),
+            _ => return Err(format!("unknown key `{}`", field)),
+        }
+    }
+    Ok(theme)
+}
+
+fn parse_list<T, F>(field: &str, value: &Value, parse_item: F) -> Result<Vec<T>, String>
+    where F: Fn(&str, &Value) -> Result<T, String>
+{
+    let values = try!(value.as_slice().ok_or(format!("`{}` must be a list", field)));
+    let mut res = Vec::new();
+    for v in values {
+        res.push(try!(parse_item(field, v)));
+    }
+    if res.is_empty() {
+        return Err(format!("`{}` must not be empty", field));
+    }
+    Ok(res)
+}
+
+/// The name of one of the 8 basic colours.
+fn parse_colour(field: &str, value: &Value) -> Result<Color, String> {
+    match value.as_str() {
+        Some("black") => Ok(Color::Black),
+        Some("red") => Ok(Color::Red),
+        Some("green") => Ok(Color::Green),
+        Some("yellow") => Ok(Color::Yellow),
+        Some("blue") => Ok(Color::Blue),
+        Some("magenta") => Ok(Color::Magenta),
+        Some("cyan") => Ok(Color::Cyan),
+        Some("white") => Ok(Color::White),
+        _ => Err(format!("`{}` must be one of: black, red, green, yellow, blue, magenta, cyan, white", field)),
+    }
+}
+
+fn parse_colour_index(field: &str, value: &Value) -> Result<u16, String> {
+    match value.as_integer() {
+        Some(i) if i >= 0 && i <= 255 => Ok(i as u16),
+        _ => Err(format!("`{}` must be a colour index between 0 and 255", field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::rustbox::Key;
+    use ui::keys::Lookup;
+    use ui::types::COLUMNS;
+
+    fn config(content: &str) -> Result<Config, String> {
+        let table = Parser::new(content).parse().expect("invalid TOML");
+        Config::from_table(&table)
+    }
+
+    fn error(content: &str) -> String {
+        config(content).err().expect("the configuration is valid")
+    }
+
+    #[test]
+    fn the_default_config_round_trips() {
+        let config = config(&default_config()).unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.heat.mode, defaults.heat.mode);
+        assert_eq!(config.heat.thresholds, defaults.heat.thresholds);
+        assert_eq!(config.theme.gradient, defaults.theme.gradient);
+        assert_eq!(config.colours, defaults.colours);
+        assert_eq!(config.editor, defaults.editor);
+        assert_eq!(config.view.view, defaults.view.view);
+        assert_eq!(config.view.sort, defaults.view.sort);
+        assert_eq!(config.view.columns, defaults.view.columns);
+        assert_eq!(config.view.bars, defaults.view.bars);
+        assert_eq!(config.view.hidden.hide, defaults.view.hidden.hide);
+        assert_eq!(config.view.hidden.keep, defaults.view.hidden.keep);
+        for action in ACTIONS {
+            for keys in Keymap::default().keys(*action) {
+                assert_eq!(config.keys.lookup(&keys), Lookup::Action(*action));
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_keys() {
+        assert_eq!(error("colors = \"8\""), "unknown key `colors`");
+        assert_eq!(error("[heat]\nmin = 1"), "unknown key `heat.min`");
+        assert_eq!(error("[view]\nsorting = \"time\""), "unknown key `view.sorting`");
+        assert_eq!(error("theme = \"mine\"\n[themes.mine]\ntext = \"red\""),
+                   "unknown key `themes.mine.text`");
+        assert!(error("[keys]\njump = \"J\"").starts_with("unknown action `jump`, expected one of: quit, down, "));
+    }
+
+    #[test]
+    fn thresholds_increase() {
+        assert_eq!(config("[heat]\nthresholds = [5, 20, 80]").unwrap().heat.thresholds, vec![5.0, 20.0, 80.0]);
+        assert_eq!(config("[heat]\nthresholds = [0.0, 2.5]").unwrap().heat.thresholds, vec![0.0, 2.5]);
+        let err = "`heat.thresholds` must be a non-empty list of increasing, positive percentages";
+        assert_eq!(error("[heat]\nthresholds = [50.0, 10.0]"), err);
+        assert_eq!(error("[heat]\nthresholds = [10.0, 10.0]"), err);
+        assert_eq!(error("[heat]\nthresholds = [-1.0]"), err);
+        assert_eq!(error("[heat]\nthresholds = []"), err);
+        assert_eq!(error("[heat]\nthresholds = [\"10\"]"), err);
+        assert_eq!(error("[heat]\nmode = \"log\""), "`heat.mode` must be either \"absolute\" or \"relative\"");
+    }
+
+    #[test]
+    fn themes_by_name() {
+        for name in THEMES {
+            assert!(config(&format!("theme = {:?}", name)).is_ok(), "theme {}", name);
+        }
+        let theme = config("theme = \"sepia\"\n\
+                            [themes.sepia]\n\
+                            foreground = \"black\"\n\
+                            background = 230\n\
+                            gradient = [94, 130]")
+            .unwrap()
+            .theme;
+        assert_eq!(theme.foreground, Color::Black);
+        assert_eq!(theme.background, 230);
+        assert_eq!(theme.gradient, vec![94, 130]);
+        assert_eq!(error("theme = \"solarized\"\n[themes.sepia]\nbackground = 230"),
+                   "unknown theme `solarized`, expected one of: dark, light, colour-blind, colour-blind-light, sepia");
+        assert_eq!(error("theme = 1"), "`theme` must be a string");
+        assert_eq!(error("theme = \"sepia\"\n[themes.sepia]\nbackground = 256"),
+                   "`themes.sepia.background` must be a colour index between 0 and 255");
+        assert_eq!(error("theme = \"sepia\"\n[themes.sepia]\nbasic = []"), "`themes.sepia.basic` must not be empty");
+    }
+
+    #[test]
+    fn columns_are_listed_once() {
+        let columns = config("[view]\ncolumns = [\"entries\"]").unwrap().view.columns;
+        assert_eq!(columns.len(), 1);
+        assert!(COLUMNS.contains(&columns[0]));
+        assert_eq!(error("[view]\ncolumns = [\"entries\", \"entries\"]"),
+                   "`view.columns`: column `entries` is listed twice");
+        assert!(error("[view]\ncolumns = [\"calls\"]").starts_with("`view.columns` must be one of: "));
+        assert_eq!(error("[view]\ncolumns = []"), "`view.columns` must not be empty");
+    }
+
+    #[test]
+    fn other_settings() {
+        let config = config("colours = \"8\"\n\
+                             keybindings = \"emacs\"\n\
+                             editor = \"code --goto {file}:{line}\"\n\
+                             [view]\n\
+                             preview = true\n\
+                             hide = []")
+            .unwrap();
+        assert_eq!(config.colours, ColourDepth::Basic);
+        assert_eq!(config.keys.lookup(&[Key::Ctrl('n')]), Lookup::Action(Action::Down));
+        assert_eq!(config.editor, "code --goto {file}:{line}");
+        assert!(config.view.preview);
+        assert!(config.view.hidden.hide.is_empty());
+        assert_eq!(error("colours = 16"), "`colours` must be one of \"auto\", \"8\" or \"256\"");
+        assert_eq!(error("keybindings = \"nano\""), "`keybindings` must be either \"vim\" or \"emacs\"");
+        assert_eq!(error("editor = \"vi\""), "`editor` must be a command containing `{file}`");
+        assert_eq!(error("[view]\npreview = \"yes\""), "`view.preview` must be true or false");
+        assert_eq!(error("[view]\nkeep = [1]"), "`view.keep` must be a list of patterns");
+    }
+
+    #[test]
+    fn project_settings_override_the_user_ones() {
+        let mut user = Parser::new("theme = \"light\"\n[view]\nsort = \"inherited-time\"\npreview = true").parse().unwrap();
+        let project = Parser::new("[view]\npreview = false").parse().unwrap();
+        merge_tables(&mut user, project);
+        let config = Config::from_table(&user).unwrap();
+        assert_eq!(config.theme.gradient, Theme::named("light").unwrap().gradient);
+        assert_eq!(config.view.sort.name(), "inherited-time");
+        assert!(!config.view.preview);
+    }
+}
@@ -0,0 +1,48 @@
+use ghcprof::source::Location;
+use std::env;
+use std::process::Command;
+
+pub const DEFAULT_COMMAND: &'static str = "$EDITOR +{line} {file}";
+
+/// Runs the editor `template` on `location`, waiting for it to exit.
+///
+/// The template is split on whitespace, then `{file}`, `{line}` and
+/// `{column}` are substituted in each word, so file names with spaces are
+/// passed as a single argument. Words such as `$EDITOR` are replaced with
+/// the (whitespace separated) value of the variable.
+pub fn open(template: &str, location: &Location) -> Result<(), String> {
+    let words = try!(expand(template, location));
+    let (program, args) = match words.split_first() {
+        Some(split) => split,
+        None => return Err("the editor command is empty".to_string()),
+    };
+    match Command::new(program).args(args).status() {
+        Ok(ref status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("`{}` failed ({})", program, status)),
+        Err(e) => Err(format!("could not run `{}`: {}", program, e)),
+    }
+}
+
+fn expand(template: &str, location: &Location) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    for word in template.split_whitespace() {
+        if word.starts_with('$') {
+            let value = try!(variable(&word[1..]));
+            words.extend(value.split_whitespace().map(String::from));
+        } else {
+            words.push(word.replace("{file}", &location.file.to_string_lossy())
+                .replace("{line}", &location.line.to_string())
+                .replace("{column}", &location.column.to_string()));
+        }
+    }
+    Ok(words)
+}
+
+/// `$EDITOR` falls back on `$VISUAL`, then on `vi`.
+fn variable(name: &str) -> Result<String, String> {
+    match env::var(name) {
+        Ok(ref value) if !value.trim().is_empty() => Ok(value.clone()),
+        _ if name == "EDITOR" => Ok(env::var("VISUAL").unwrap_or("vi".to_string())),
+        _ => Err(format!("${} is not set", name)),
+    }
+}
@@ -0,0 +1,243 @@
+use ghcprof::flame::layout;
+use ghcprof::metric::Metric;
+use ghcprof::parser::{Header, Summary, ExtendedSummary, ExtendedSummaryLine, RoseTree, GHCProf};
+use std::cmp::max;
+use std::io;
+use std::io::prelude::*;
+use report::truncate;
+use ui::style::{Palette, Temperature};
+
+// The flame graph is drawn this wide, and then scaled to the page.
+const FLAME_WIDTH: f64 = 1200.0;
+const FLAME_ROW_HEIGHT: f64 = 17.0;
+// Nodes narrower than this, as a percentage of the root, are left out of the flame graph.
+const FLAME_MIN_WIDTH: f64 = 0.1;
+// Nodes of the tree costing at least this much, in either metric, start unfolded.
+const OPEN_PERC: f32 = 5.0;
+
+const STYLE: &'static str = r#"body { font-family: monospace; margin: 1em 2em; }
+h2 { margin-top: 2em; }
+.hot { font-weight: bold; }
+.num { text-align: right; }
+table.sortable { border-collapse: collapse; }
+table.sortable th { cursor: pointer; text-align: left; user-select: none; }
+table.sortable th, table.sortable td { padding: 0 1.5em 0 0; white-space: nowrap; }
+table.sortable th.num { text-align: right; }
+table.sortable th[data-order=asc]::after { content: " \25b4"; }
+table.sortable th[data-order=desc]::after { content: " \25be"; }
+.tree summary, .tree .leaf, .tree .columns { white-space: pre; }
+.tree details details, .tree details .leaf { margin-left: 2ch; }
+.tree summary { list-style: none; cursor: pointer; }
+.tree summary::-webkit-details-marker { display: none; }
+.tree summary::before { content: "\25b8 "; }
+.tree details[open] > summary::before { content: "\25be "; }
+.tree .leaf::before, .tree .columns::before { content: "  "; }
+.tree span { display: inline-block; }
+.tree span.num { width: 8ch; }
+.tree span.entries { width: 11ch; }
+.tree span.inherited { margin-left: 2ch; }
+svg.flame { width: 100%; height: auto; }
+svg.flame rect { stroke-width: 0.5; }
+svg.flame rect.cold { fill-opacity: 0.2; }
+svg.flame text { font-size: 11px; pointer-events: none; }
+"#;
+
+const SCRIPT: &'static str = r#"function sortTable(table, col, th) {
+  var numeric = th.classList.contains("num");
+  var asc = th.dataset.order ? th.dataset.order === "desc" : !numeric;
+  Array.prototype.forEach.call(table.tHead.rows[0].cells, function (c) { delete c.dataset.order; });
+  th.dataset.order = asc ? "asc" : "desc";
+  var body = table.tBodies[0];
+  var rows = Array.prototype.slice.call(body.rows);
+  rows.sort(function (a, b) {
+    var x = a.cells[col].textContent, y = b.cells[col].textContent;
+    var c = numeric ? parseFloat(x) - parseFloat(y) : x.localeCompare(y);
+    return asc ? c : -c;
+  });
+  rows.forEach(function (r) { body.appendChild(r); });
+}
+Array.prototype.forEach.call(document.querySelectorAll("table.sortable"), function (table) {
+  Array.prototype.forEach.call(table.tHead.rows[0].cells, function (th, col) {
+    th.addEventListener("click", function () { sortTable(table, col, th); });
+  });
+});
+"#;
+
+/// Renders a profile as a single HTML page, with no external assets: the
+/// header, a sortable table of the cost centres, the cost centre tree,
+/// foldable, and a flame graph of `metric`. Costs are coloured as in the
+/// terminal, with the 256 colours of the theme.
+pub struct HtmlReport<'r> {
+    palette: &'r Palette,
+    metric: Metric,
+}
+
+impl<'r> HtmlReport<'r> {
+    pub fn new(palette: &'r Palette, metric: Metric) -> HtmlReport<'r> {
+        HtmlReport { palette: palette, metric: metric }
+    }
+
+    pub fn write<'a, W: Write>(&self, out: &mut W, prof: &GHCProf<'a>) -> io::Result<()> {
+        try!(writeln!(out, "<!DOCTYPE html>"));
+        try!(writeln!(out, "<html>"));
+        try!(writeln!(out, "<head>"));
+        try!(writeln!(out, "<meta charset=\"utf-8\">"));
+        try!(writeln!(out, "<title>{}</title>", escape(prof.header.program.trim())));
+        try!(writeln!(out, "<style>"));
+        try!(writeln!(out, "body {{ background: {}; color: {}; }}",
+                      self.palette.css_background(), self.palette.css_foreground()));
+        try!(writeln!(out, "svg.flame rect {{ stroke: {}; }}", self.palette.css_background()));
+        try!(writeln!(out, "svg.flame rect.cold, svg.flame text.cold {{ fill: {}; }}", self.palette.css_foreground()));
+        try!(writeln!(out, "svg.flame text {{ fill: {}; }}", self.palette.css_background()));
+        try!(write!(out, "{}", STYLE));
+        try!(writeln!(out, "</style>"));
+        try!(writeln!(out, "</head>"));
+        try!(writeln!(out, "<body>"));
+        try!(self.write_header(out, &prof.header));
+        try!(self.write_summary(out, &prof.summary));
+        try!(self.write_extended_summary(out, &prof.extended_summary));
+        try!(self.write_flame_graph(out, &prof.extended_summary));
+        try!(writeln!(out, "<script>"));
+        try!(write!(out, "{}", SCRIPT));
+        try!(writeln!(out, "</script>"));
+        try!(writeln!(out, "</body>"));
+        writeln!(out, "</html>")
+    }
+
+    fn write_header<'a, W: Write>(&self, out: &mut W, header: &Header<'a>) -> io::Result<()> {
+        let ref tt = header.total_time;
+        let ref ta = header.total_alloc;
+        try!(writeln!(out, "<pre class=\"header\">"));
+        try!(writeln!(out, "{}", escape(header.title.trim())));
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "{}", escape(header.program.trim())));
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "total time  = {} secs   ({} ticks @ {} us, {} processor)",
+                      tt.time, tt.ticks, tt.freq, tt.procs));
+        try!(writeln!(out, "total alloc = {} bytes  (excludes profiling overheads)", ta.bytes));
+        writeln!(out, "</pre>")
+    }
+
+    fn write_summary<'a, W: Write>(&self, out: &mut W, &Summary(ref lines): &Summary<'a>) -> io::Result<()> {
+        try!(writeln!(out, "<h2>Cost centres</h2>"));
+        try!(writeln!(out, "<table class=\"sortable\">"));
+        try!(writeln!(out, "<thead><tr><th>COST CENTRE</th><th>MODULE</th><th>SRC</th>\
+                            <th class=\"num\">%time</th><th class=\"num\">%alloc</th></tr></thead>"));
+        try!(writeln!(out, "<tbody>"));
+        for line in lines {
+            let time_temp = self.palette.temperature(line.time_perc);
+            let alloc_temp = self.palette.temperature(line.alloc_perc);
+            let combined_temp = Temperature::append(&time_temp, &alloc_temp);
+            let class = if combined_temp.is_hot() { " class=\"hot\"" } else { "" };
+            try!(writeln!(out, "<tr><td{}>{}</td><td{}>{}</td><td>{}</td>{}{}</tr>",
+                          class, escape(line.cost_centre),
+                          class, escape(line.module),
+                          escape(line.src.unwrap_or("")),
+                          self.heat("td", "num", line.time_perc),
+                          self.heat("td", "num", line.alloc_perc)));
+        }
+        try!(writeln!(out, "</tbody>"));
+        writeln!(out, "</table>")
+    }
+
+    fn write_extended_summary<'a, W: Write>(&self, out: &mut W, &ExtendedSummary(ref tree): &ExtendedSummary<'a>) -> io::Result<()> {
+        // Each level is indented by 2 characters, which the cost centre column makes up for.
+        let cc_w = tree_width(tree, &|t| 2 * t.depth + t.value.cost_centre.len()).max("COST CENTRE".len());
+        let mo_w = tree_width(tree, &|t| t.value.module.len()).max("MODULE".len());
+        try!(writeln!(out, "<h2>Call tree</h2>"));
+        try!(writeln!(out, "<div class=\"tree\">"));
+        try!(writeln!(out, "<div class=\"columns\"><span style=\"width: {}ch\"></span> <span style=\"width: {}ch\"></span>\
+                            <span class=\"num\"></span><span class=\"num entries\"></span>\
+                            <span class=\"num\" style=\"width: 16ch\">individual</span>\
+                            <span class=\"num inherited\" style=\"width: 16ch\">inherited</span></div>",
+                      cc_w, mo_w));
+        try!(writeln!(out, "<div class=\"columns\"><span style=\"width: {}ch\">COST CENTRE</span> \
+                            <span style=\"width: {}ch\">MODULE</span><span class=\"num\">no.</span>\
+                            <span class=\"num entries\">entries</span><span class=\"num\">%time</span>\
+                            <span class=\"num\">%alloc</span><span class=\"num inherited\">%time</span>\
+                            <span class=\"num\">%alloc</span></div>",
+                      cc_w, mo_w));
+        try!(self.write_node(out, tree, cc_w, mo_w));
+        writeln!(out, "</div>")
+    }
+
+    fn write_node<'a, W: Write>(&self,
+                                out: &mut W,
+                                tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                                cc_w: usize,
+                                mo_w: usize) -> io::Result<()> {
+        let ref line = tree.value;
+        let row = format!("<span style=\"width: {}ch\" title=\"{}\">{}</span> <span style=\"width: {}ch\">{}</span>\
+                           <span class=\"num\">{}</span><span class=\"num entries\">{}</span>{}{}{}{}",
+                          cc_w - 2 * tree.depth, escape(line.src.unwrap_or("")), escape(line.cost_centre),
+                          mo_w, escape(line.module),
+                          line.no, line.entries,
+                          self.heat("span", "num", line.individual_time_perc),
+                          self.heat("span", "num", line.individual_alloc_perc),
+                          self.heat("span", "num inherited", line.inherited_time_perc),
+                          self.heat("span", "num", line.inherited_alloc_perc));
+        if tree.sub_forest.is_empty() {
+            return writeln!(out, "<div class=\"leaf\">{}</div>", row);
+        }
+        let open = tree.depth == 0 || line.inherited_time_perc >= OPEN_PERC || line.inherited_alloc_perc >= OPEN_PERC;
+        try!(writeln!(out, "<details{}><summary>{}</summary>", if open { " open" } else { "" }, row));
+        for t in &tree.sub_forest {
+            try!(self.write_node(out, t, cc_w, mo_w));
+        }
+        writeln!(out, "</details>")
+    }
+
+    fn write_flame_graph<'a, W: Write>(&self, out: &mut W, &ExtendedSummary(ref tree): &ExtendedSummary<'a>) -> io::Result<()> {
+        let rects = layout(tree, self.metric, FLAME_MIN_WIDTH);
+        let rows = rects.iter().map(|r| r.depth + 1).fold(1, max);
+        let height = rows as f64 * FLAME_ROW_HEIGHT;
+        try!(writeln!(out, "<h2>Flame graph ({})</h2>", self.metric.name()));
+        try!(writeln!(out, "<svg class=\"flame\" xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">",
+                      FLAME_WIDTH, height));
+        for r in &rects {
+            let ref line = r.node.value;
+            let x = r.start * FLAME_WIDTH / 100.0;
+            let width = r.width * FLAME_WIDTH / 100.0;
+            // The root at the bottom, callees above their callers.
+            let y = height - (r.depth + 1) as f64 * FLAME_ROW_HEIGHT;
+            let (class, fill) = match self.palette.css(&self.palette.temperature(line.individual_perc(self.metric))) {
+                Some(colour) => ("", format!(" fill=\"{}\"", colour)),
+                None => (" class=\"cold\"", String::new()),
+            };
+            try!(writeln!(out, "<g><title>{} {}&#10;{:.1}% time, {:.1}% alloc (inherited)&#10;{} entries</title>",
+                          escape(line.cost_centre), escape(line.module),
+                          line.inherited_time_perc, line.inherited_alloc_perc, line.entries));
+            try!(writeln!(out, "<rect{} x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\"{}/>",
+                          class, x, y, width, FLAME_ROW_HEIGHT, fill));
+            // Roughly 7 pixels per character at this font size.
+            let chars = ((width - 6.0) / 7.0) as usize;
+            if chars >= 3 {
+                try!(writeln!(out, "<text{} x=\"{:.2}\" y=\"{:.2}\">{}</text>",
+                              class, x + 3.0, y + FLAME_ROW_HEIGHT - 5.0, escape(&truncate(line.cost_centre, chars))));
+            }
+            try!(writeln!(out, "</g>"));
+        }
+        writeln!(out, "</svg>")
+    }
+
+    /// A table cell or a span holding `perc`, coloured by its temperature.
+    fn heat(&self, tag: &str, class: &str, perc: f32) -> String {
+        let temp = self.palette.temperature(perc);
+        let hot = if temp.is_hot() { " hot" } else { "" };
+        match self.palette.css(&temp) {
+            Some(colour) => format!("<{} class=\"{}{}\" style=\"color: {}\">{:.1}</{}>", tag, class, hot, colour, perc, tag),
+            None => format!("<{} class=\"{}{}\">{:.1}</{}>", tag, class, hot, perc, tag),
+        }
+    }
+}
+
+/// The largest `width` of the nodes of `tree`.
+fn tree_width<'a, F>(tree: &RoseTree<ExtendedSummaryLine<'a>>, width: &F) -> usize
+    where F: Fn(&RoseTree<ExtendedSummaryLine<'a>>) -> usize
+{
+    tree.sub_forest.iter().map(|t| tree_width(t, width)).fold(width(tree), max)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -1,23 +1,64 @@
 
+extern crate atty;
 extern crate ghcprof;
 
 mod cli;
+mod config;
+mod editor;
+mod html;
+mod report;
+mod rules;
 mod ui;
 
+use config::Config;
+use html::HtmlReport;
+use report::Report;
 use ui::{UI};
-use ui::types::{UIError};
-use ghcprof::parser::parse_prof_file;
+use ui::style::Palette;
+use ui::types::{Tab, UIError};
+use ghcprof::check::{compare, evaluate, write_junit, write_text};
+use ghcprof::dot::{write_call_graph, write_module_calls};
+use ghcprof::markdown;
+use ghcprof::merge::{merge, summarise};
+use ghcprof::metric::Metric;
+use ghcprof::callgrind::write_callgrind;
+use ghcprof::callpath::{call_path, display};
+use ghcprof::parser::{parse_prof_file, ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree};
+use ghcprof::pprof::write_pprof;
+use ghcprof::query::Query;
+use ghcprof::rollup::{module_calls, ModuleCall};
+use ghcprof::trace::write_trace;
+use ghcprof::transform::exclude_cafs;
+use ghcprof::writer::write_prof;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::process;
 
 #[derive(Debug)]
 enum AppError {
     CliError(cli::CliParseError),
+    ConfigError(config::ConfigError),
     IOError(std::io::Error),
+    MergeError(String),
     UIError(UIError),
 }
 
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AppError::CliError(ref e) => e.fmt(f),
+            AppError::ConfigError(ref e) => e.fmt(f),
+            AppError::IOError(ref e) => e.fmt(f),
+            AppError::MergeError(ref e) => f.write_str(e),
+            AppError::UIError(UIError::UiInitialisationFailed(ref e)) => {
+                write!(f, "the terminal UI could not be initialised ({:?})", e)
+            }
+        }
+    }
+}
+
 impl From<std::io::Error> for AppError {
     fn from(err: std::io::Error) -> AppError {
         AppError::IOError(err)
@@ -34,26 +75,285 @@ fn main() {
    match cli::Args::parse().map_err(AppError::CliError).and_then(run) {
         Ok(()) => process::exit(0),
         Err(e) => {
-            println!("{:?}", e);
+            let _ = writeln!(std::io::stderr(), "error: {}", e);
             process::exit(1);
         }
    }
 }
 
 fn run(args: cli::Args) -> Result<(), AppError> {
-    let file_path = args.file_path;
-    let mut prof_file = try!(File::open(&file_path));
+    if let cli::Command::PrintDefaultConfig = args.command {
+        print!("{}", config::default_config());
+        return Ok(());
+    }
+    let config = try!(Config::load().map_err(AppError::ConfigError));
+    match args.command {
+        cli::Command::Browse(ref file_paths, ref opts) => {
+            let mut profiles = Vec::new();
+            for file_path in file_paths {
+                profiles.push(try!(read_profile(file_path)));
+            }
+            let tabs: Vec<Tab> = file_paths.iter().zip(profiles.iter()).map(|(file_path, profile)| {
+                let prof = parse_or_exit(file_path, profile);
+                let palette = calibrated_palette(&config, &prof);
+                Tab::new(file_path.clone(), prof, palette, config.view.clone())
+            }).collect();
+            try!(browse(tabs, &config, opts.src_root.as_ref().map(|p| p.as_path())));
+        }
+        cli::Command::Merge(ref file_paths, ref opts) => {
+            let mut profiles = Vec::new();
+            for file_path in file_paths {
+                profiles.push(try!(read_profile(file_path)));
+            }
+            let profs: Vec<GHCProf> = file_paths.iter()
+                .zip(profiles.iter())
+                .map(|(file_path, profile)| parse_or_exit(file_path, profile))
+                .collect();
+            let merged = try!(merge(&profs, opts.combine).map_err(AppError::MergeError));
+            if opts.browse {
+                let palette = calibrated_palette(&config, &merged);
+                let tab = Tab::new(PathBuf::from("merged"), merged, palette, config.view.clone());
+                try!(browse(vec![tab], &config, None));
+            } else if let Some(ref output) = opts.output {
+                let mut file = try!(File::create(output));
+                try!(write_prof(&mut file, &merged));
+            } else {
+                let stdout = std::io::stdout();
+                try!(ignore_broken_pipe(write_prof(&mut stdout.lock(), &merged)));
+            }
+        }
+        cli::Command::Check(ref baseline_path, ref current_path, ref opts) => {
+            let current = try!(read_profile(current_path));
+            let prof = parse_or_exit(current_path, &current);
+            let mut cases = Vec::new();
+            if let Some(ref baseline_path) = *baseline_path {
+                let baseline = try!(read_profile(baseline_path));
+                let comparisons = compare(&parse_or_exit(baseline_path, &baseline), &prof, &opts.budget);
+                cases.extend(comparisons.iter().map(|c| c.test_case()));
+            }
+            if let Some(ref rules_path) = opts.rules {
+                let rules = try!(rules::load(rules_path).map_err(AppError::ConfigError));
+                cases.extend(evaluate(&prof, &rules).iter().map(|v| v.test_case()));
+            }
+            if let Some(ref junit) = opts.junit {
+                let mut file = try!(File::create(junit));
+                try!(write_junit(&mut file, &cases));
+            }
+            let stdout = std::io::stdout();
+            try!(ignore_broken_pipe(write_text(&mut stdout.lock(), &cases)));
+            if cases.iter().any(|c| c.failure.is_some()) {
+                process::exit(1);
+            }
+        }
+        cli::Command::Query(ref file_path, ref query) => {
+            let query: Query = try!(query.parse().map_err(|e| AppError::CliError(cli::CliParseError::InvalidValue(e))));
+            let profile = try!(read_profile(file_path));
+            let prof = parse_or_exit(file_path, &profile);
+            let selected = {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                query.select(tree)
+            };
+            let stdout = std::io::stdout();
+            try!(ignore_broken_pipe(print_matches(&mut stdout.lock(), &prof, &selected)));
+            // Like grep, for scripts.
+            if selected.is_empty() {
+                process::exit(1);
+            }
+        }
+        cli::Command::Modules(ref file_path, ref opts) => {
+            let profile = try!(read_profile(file_path));
+            let prof = parse_or_exit(file_path, &profile);
+            let mut calls = {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                module_calls(tree)
+            };
+            let metric = opts.metric;
+            calls.retain(|c| perc(c, metric) >= opts.threshold);
+            calls.sort_by(|a, b| perc(b, metric).partial_cmp(&perc(a, metric)).unwrap());
+            let stdout = std::io::stdout();
+            let written = if opts.dot {
+                write_module_calls(&mut stdout.lock(), &calls, metric)
+            } else {
+                print_module_calls(&mut stdout.lock(), &calls)
+            };
+            try!(ignore_broken_pipe(written));
+        }
+        cli::Command::Export(ref baseline_path, ref file_path, ref opts) => {
+            let baseline_profile = match *baseline_path {
+                Some(ref path) => Some(try!(read_profile(path))),
+                None => None,
+            };
+            let baseline = match (baseline_path, &baseline_profile) {
+                (&Some(ref path), &Some(ref profile)) => Some(parse_or_exit(path, profile)),
+                _ => None,
+            };
+            let profile = try!(read_profile(file_path));
+            let prof = parse_or_exit(file_path, &profile);
+            let palette = calibrated_palette(&config, &prof);
+            if let Some(ref output) = opts.output {
+                let mut file = try!(File::create(output));
+                try!(export(&mut file, baseline.as_ref(), &prof, &palette, opts));
+            } else {
+                let stdout = std::io::stdout();
+                try!(ignore_broken_pipe(export(&mut stdout.lock(), baseline.as_ref(), &prof, &palette, opts)));
+            }
+        }
+        cli::Command::Report(ref file_path, ref opts) => {
+            let profile = try!(read_profile(file_path));
+            let mut prof = parse_or_exit(file_path, &profile);
+            if opts.exclude_cafs {
+                let excluded = {
+                    let ExtendedSummary(ref tree) = prof.extended_summary;
+                    exclude_cafs(tree)
+                };
+                prof.summary = summarise(&excluded);
+                prof.extended_summary = ExtendedSummary(excluded);
+            }
+            let palette = calibrated_palette(&config, &prof);
+            let colour = atty::is(atty::Stream::Stdout);
+            try!(print_report(&Report::new(opts, &palette, colour), &prof));
+        }
+        cli::Command::PrintDefaultConfig => {}
+    }
+    Ok(())
+}
+
+/// Opens the interactive UI, or prints plain-text reports when it can't.
+fn browse<'a>(tabs: Vec<Tab<'a>>, config: &Config, src_root: Option<&Path>) -> Result<(), AppError> {
+    if !atty::is(atty::Stream::Stdout) {
+        return fallback_report("stdout is not a terminal", &tabs);
+    }
+    match UI::new(config.colours) {
+        Ok(ui) => try!(ui.render_loop(tabs, config, src_root)),
+        Err(UIError::UiInitialisationFailed(e)) => {
+            let reason = format!("the terminal UI could not be initialised ({:?})", e);
+            return fallback_report(&reason, &tabs);
+        }
+    }
+    Ok(())
+}
+
+fn read_profile(file_path: &Path) -> Result<String, AppError> {
+    let mut prof_file = try!(File::open(file_path));
     let mut profile   = String::new();
     try!(prof_file.read_to_string(&mut profile));
+    Ok(profile)
+}
+
+fn parse_or_exit<'a>(file_path: &Path, profile: &'a str) -> GHCProf<'a> {
     match parse_prof_file(profile.as_bytes()) {
-        Some(prof) => {
-            let ui = try!(UI::new());
-            ui.render_loop(prof);
-            Ok(())
-        },
+        Some(prof) => prof,
         _ => {
             println!("Failed to parse {:?}. Report this as a bug.", file_path);
             process::exit(1)
         }
     }
 }
+
+fn calibrated_palette<'a>(config: &Config, prof: &GHCProf<'a>) -> Palette {
+    let mut palette = config.palette();
+    palette.scale.calibrate(prof);
+    palette
+}
+
+/// Prints the profiles as plain-text reports, explaining on stderr why we
+/// are not starting the interactive UI.
+fn fallback_report<'a>(reason: &str, tabs: &[Tab<'a>]) -> Result<(), AppError> {
+    let _ = writeln!(std::io::stderr(), "profiv: {}, printing a plain-text report instead.", reason);
+    let opts = cli::ReportOptions::default();
+    for (i, tab) in tabs.iter().enumerate() {
+        if tabs.len() > 1 {
+            let _ = writeln!(std::io::stdout(), "{}==> {} <==", if i > 0 { "\n" } else { "" }, tab.path.display());
+        }
+        try!(print_report(&Report::new(&opts, &tab.palette, false), &tab.prof));
+    }
+    Ok(())
+}
+
+/// Prints the costs and call path of each node of `selected`, if any.
+fn print_matches<'a, W: Write>(out: &mut W,
+                               prof: &GHCProf<'a>,
+                               selected: &[&RoseTree<ExtendedSummaryLine<'a>>])
+                               -> std::io::Result<()> {
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    if selected.is_empty() {
+        return Ok(());
+    }
+    try!(writeln!(out, "{:>30}individual      inherited", ""));
+    try!(writeln!(out, "    no.     entries  %time %alloc   %time %alloc  CALL PATH"));
+    for node in selected {
+        let ref line = node.value;
+        try!(writeln!(out,
+                      "{:>7} {:>11} {:>6.1} {:>6.1} {:>7.1} {:>6.1}  {}",
+                      line.no,
+                      line.entries,
+                      line.individual_time_perc,
+                      line.individual_alloc_perc,
+                      line.inherited_time_perc,
+                      line.inherited_alloc_perc,
+                      display(&call_path(tree, line.no).unwrap_or_default())));
+    }
+    Ok(())
+}
+
+/// Writes `prof` in the format of `opts`, followed by the changes since
+/// `baseline` in Markdown.
+fn export<'a, W: Write>(out: &mut W,
+                        baseline: Option<&GHCProf<'a>>,
+                        prof: &GHCProf<'a>,
+                        palette: &Palette,
+                        opts: &cli::ExportOptions) -> std::io::Result<()> {
+    let ExtendedSummary(ref tree) = prof.extended_summary;
+    let metric = opts.graph.metric;
+    match opts.format {
+        cli::ExportFormat::Dot => write_call_graph(out, tree, &opts.graph),
+        cli::ExportFormat::Html => HtmlReport::new(palette, metric).write(out, prof),
+        cli::ExportFormat::Markdown => {
+            try!(markdown::write_summary(out, prof, metric, opts.top));
+            match baseline {
+                Some(baseline) => {
+                    try!(writeln!(out, ""));
+                    markdown::write_diff(out, baseline, prof, metric, opts.top)
+                }
+                None => Ok(()),
+            }
+        }
+        cli::ExportFormat::Pprof => write_pprof(out, prof),
+        cli::ExportFormat::Trace => write_trace(out, prof),
+        cli::ExportFormat::Callgrind => write_callgrind(out, prof),
+    }
+}
+
+fn perc(call: &ModuleCall, metric: Metric) -> f32 {
+    match metric {
+        Metric::Time => call.time_perc,
+        Metric::Alloc => call.alloc_perc,
+    }
+}
+
+fn print_module_calls<'a, W: Write>(out: &mut W, calls: &[ModuleCall<'a>]) -> std::io::Result<()> {
+    let caller_w = calls.iter().map(|c| c.caller.len()).fold("CALLER".len(), std::cmp::max);
+    let callee_w = calls.iter().map(|c| c.callee.len()).fold("CALLEE".len(), std::cmp::max);
+    try!(writeln!(out, "{:cw$}  {:ew$}  {:>11}  {:>6} {:>6}", "CALLER", "CALLEE", "entries", "%time", "%alloc",
+                  cw = caller_w, ew = callee_w));
+    for call in calls {
+        try!(writeln!(out, "{:cw$}  {:ew$}  {:>11}  {:>6.1} {:>6.1}",
+                      call.caller, call.callee, call.entries, call.time_perc, call.alloc_perc,
+                      cw = caller_w, ew = callee_w));
+    }
+    Ok(())
+}
+
+fn print_report<'a>(report: &Report, prof: &GHCProf<'a>) -> Result<(), AppError> {
+    let stdout = std::io::stdout();
+    ignore_broken_pipe(report.write(&mut stdout.lock(), prof)).map_err(AppError::from)
+}
+
+/// Output to a reader that went away (e.g. `head` or `less`) is not an
+/// error: there is nothing left to do.
+fn ignore_broken_pipe(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(ref e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        r => r,
+    }
+}
@@ -0,0 +1,201 @@
+use cli::ReportOptions;
+use ghcprof::parser::{Header, Summary, ExtendedSummary, ExtendedSummaryLine, RoseTree, GHCProf};
+use ghcprof::transform::is_caf;
+use std::cmp::{max, min};
+use std::io;
+use std::io::prelude::*;
+use ui::style::{Palette, Temperature, ANSI_RESET};
+
+// The width of the `no.`, `entries` and the four percentage columns, separators included.
+const NUMERIC_COLUMNS_WIDTH: usize = 8 + 10 + 7 + 7 + 8 + 7;
+
+/// Plain-text renderer for a profile, used wherever rustbox can't run.
+pub struct Report<'r> {
+    opts: &'r ReportOptions,
+    palette: &'r Palette,
+    colour: bool,
+}
+
+impl<'r> Report<'r> {
+    pub fn new(opts: &'r ReportOptions, palette: &'r Palette, colour: bool) -> Report<'r> {
+        Report { opts: opts, palette: palette, colour: colour }
+    }
+
+    pub fn write<'a, W: Write>(&self, out: &mut W, prof: &GHCProf<'a>) -> io::Result<()> {
+        try!(self.write_header(out, &prof.header));
+        try!(self.write_summary(out, &prof.summary));
+        self.write_extended_summary(out, &prof.extended_summary)
+    }
+
+    fn write_header<'a, W: Write>(&self, out: &mut W, header: &Header<'a>) -> io::Result<()> {
+        let ref tt = header.total_time;
+        let ref ta = header.total_alloc;
+        try!(writeln!(out, "{}", self.fit(header.title)));
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "{}", self.fit(header.program)));
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "{}", self.fit(&format!("total time  = {} secs   ({} ticks @ {} us, {} processor)",
+                                                   tt.time, tt.ticks, tt.freq, tt.procs))));
+        try!(writeln!(out, "{}", self.fit(&format!("total alloc = {} bytes  (excludes profiling overheads)",
+                                                   ta.bytes))));
+        writeln!(out, "")
+    }
+
+    fn write_summary<'a, W: Write>(&self, out: &mut W, &Summary(ref lines): &Summary<'a>) -> io::Result<()> {
+        let metric = self.opts.metric;
+        let mut top = lines.clone();
+        top.sort_by(|a, b| b.perc(metric).partial_cmp(&a.perc(metric)).unwrap());
+        let (mut cafs, mut top): (Vec<_>, Vec<_>) = if self.opts.separate_cafs {
+            top.into_iter().partition(|l| is_caf(l.cost_centre))
+        } else {
+            (Vec::new(), top)
+        };
+        top.truncate(self.opts.top);
+        cafs.truncate(self.opts.top);
+
+        let cc_w = top.iter().map(|l| l.cost_centre.len()).fold("COST CENTRE".len(), max);
+        let mo_w = top.iter().map(|l| l.module.len()).fold("MODULE".len(), max);
+        let (cc_w, mo_w) = self.shrink(cc_w, mo_w, 14);
+
+        try!(writeln!(out, "{}", self.fit(&format!("{:cc$} {:mo$} {:>6} {:>6}",
+                                                   "COST CENTRE", "MODULE", "%time", "%alloc",
+                                                   cc = cc_w, mo = mo_w))));
+        for (i, line) in top.iter().chain(cafs.iter()).enumerate() {
+            if i == top.len() {
+                try!(writeln!(out, ""));
+                try!(writeln!(out, "CAFs"));
+            }
+            let time_temp = self.palette.temperature(line.time_perc);
+            let alloc_temp = self.palette.temperature(line.alloc_perc);
+            let combined_temp = Temperature::append(&time_temp, &alloc_temp);
+            let row = format!("{} {} {} {}",
+                              self.bold_if_hot(&combined_temp, &pad(line.cost_centre, cc_w)),
+                              self.bold_if_hot(&combined_temp, &pad(line.module, mo_w)),
+                              self.heat(&time_temp, &format!("{:>6.1}", line.time_perc)),
+                              self.heat(&alloc_temp, &format!("{:>6.1}", line.alloc_perc)));
+            try!(writeln!(out, "{}", row));
+        }
+        writeln!(out, "")
+    }
+
+    fn write_extended_summary<'a, W: Write>(&self, out: &mut W, &ExtendedSummary(ref tree): &ExtendedSummary<'a>) -> io::Result<()> {
+        let mut rows = Vec::new();
+        self.visible_nodes(tree, &mut rows);
+
+        let cc_w = rows.iter().map(|t| t.depth + t.value.cost_centre.len()).fold("COST CENTRE".len(), max);
+        let mo_w = rows.iter().map(|t| t.value.module.len()).fold("MODULE".len(), max);
+        let (cc_w, mo_w) = self.shrink(cc_w, mo_w, NUMERIC_COLUMNS_WIDTH);
+
+        try!(writeln!(out, "{}", self.fit(&format!("{:w$}{:>13}  {:>13}",
+                                                   "", "individual", "inherited",
+                                                   w = cc_w + mo_w + 20))));
+        try!(writeln!(out, "{}", self.fit(&format!("{:cc$} {:mo$} {:>7} {:>9} {:>6} {:>6}  {:>6} {:>6}",
+                                                   "COST CENTRE", "MODULE", "no.", "entries",
+                                                   "%time", "%alloc", "%time", "%alloc",
+                                                   cc = cc_w, mo = mo_w))));
+        for node in rows {
+            try!(self.write_extended_summary_line(out, node.depth, &node.value, cc_w, mo_w));
+        }
+        Ok(())
+    }
+
+    fn write_extended_summary_line<'a, W: Write>(&self,
+                                                  out: &mut W,
+                                                  depth: usize,
+                                                  line: &ExtendedSummaryLine<'a>,
+                                                  cc_w: usize,
+                                                  mo_w: usize) -> io::Result<()> {
+        let indented = format!("{:d$}{}", "", line.cost_centre, d = depth);
+        let heat = |perc: f32| self.heat(&self.palette.temperature(perc), &format!("{:>6.1}", perc));
+        writeln!(out, "{} {} {:>7} {:>9} {} {}  {} {}",
+                 pad(&indented, cc_w),
+                 pad(line.module, mo_w),
+                 line.no,
+                 line.entries,
+                 heat(line.individual_time_perc),
+                 heat(line.individual_alloc_perc),
+                 heat(line.inherited_time_perc),
+                 heat(line.inherited_alloc_perc))
+    }
+
+    /// Collects the nodes within the requested depth whose inherited cost
+    /// reaches the threshold. The root is always kept.
+    fn visible_nodes<'t, 'a>(&self, tree: &'t RoseTree<ExtendedSummaryLine<'a>>, acc: &mut Vec<&'t RoseTree<ExtendedSummaryLine<'a>>>) {
+        acc.push(tree);
+        if tree.depth >= self.opts.depth {
+            return;
+        }
+        for t in &tree.sub_forest {
+            if t.value.inherited_perc(self.opts.metric) >= self.opts.threshold {
+                self.visible_nodes(t, acc)
+            }
+        }
+    }
+
+    /// Shrinks the cost centre and then the module column so that a row
+    /// fits in the requested width, without going below a readable minimum.
+    fn shrink(&self, cc_w: usize, mo_w: usize, rest: usize) -> (usize, usize) {
+        let available = self.opts.width.saturating_sub(rest + 2);
+        if cc_w + mo_w <= available {
+            return (cc_w, mo_w);
+        }
+        let cc_w = max(min(cc_w, available.saturating_sub(mo_w)), min(cc_w, 12));
+        let mo_w = max(min(mo_w, available.saturating_sub(cc_w)), min(mo_w, 8));
+        (cc_w, mo_w)
+    }
+
+    fn fit(&self, s: &str) -> String {
+        truncate(s, self.opts.width)
+    }
+
+    fn heat(&self, temp: &Temperature, s: &str) -> String {
+        let escape = self.palette.ansi(temp);
+        if self.colour && !escape.is_empty() {
+            format!("{}{}{}", escape, s, ANSI_RESET)
+        } else {
+            s.to_string()
+        }
+    }
+
+    fn bold_if_hot(&self, temp: &Temperature, s: &str) -> String {
+        if self.colour && temp.is_hot() {
+            format!("\x1b[1m{}{}", s, ANSI_RESET)
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+/// Pads or truncates `s` to exactly `width` characters.
+fn pad(s: &str, width: usize) -> String {
+    format!("{:w$}", truncate(s, width), w = width)
+}
+
+/// Cuts `s` to `width` characters, its last one replaced by `~` when it
+/// was too long.
+pub fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut res: String = s.chars().take(width.saturating_sub(1)).collect();
+    res.push('~');
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_fits_the_width() {
+        assert_eq!(truncate("countWords", 20), "countWords");
+        assert_eq!(truncate("countWords", 10), "countWords");
+        assert_eq!(truncate("countWords", 6), "count~");
+        assert_eq!(truncate("countWords", 1), "~");
+        assert_eq!(truncate("countWords", 0), "");
+        assert_eq!(pad("main", 6), "main  ");
+    }
+}
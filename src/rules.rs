@@ -0,0 +1,151 @@
+extern crate toml;
+
+use self::toml::{Table, Value};
+use config::{read_table, ConfigError};
+use ghcprof::check::{Measure, Rule, MEASURES};
+use std::path::Path;
+
+/// Reads the budget rules in `path`, a TOML file with a `[[rule]]` table for
+/// each rule:
+///
+/// ```toml
+/// [[rule]]
+/// name = "parser"
+/// module = "Parser.*"
+/// max-inherited-alloc = 20
+/// ```
+pub fn load(path: &Path) -> Result<Vec<Rule>, ConfigError> {
+    let (path, table) = try!(read_table(path.to_path_buf()));
+    parse_rules(&table).map_err(|e| ConfigError::InvalidConfig(path, e))
+}
+
+fn parse_rules(table: &Table) -> Result<Vec<Rule>, String> {
+    for key in table.keys() {
+        if key != "rule" {
+            return Err(format!("unknown key `{}`", key));
+        }
+    }
+    let rules = match table.get("rule") {
+        Some(&Value::Array(ref rules)) => rules,
+        Some(_) => return Err("`rule` must be a list of tables, written `[[rule]]`".to_string()),
+        None => return Err("no `[[rule]]` found".to_string()),
+    };
+    let mut res = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        let table = try!(rule.as_table().ok_or("`rule` must be a list of tables, written `[[rule]]`".to_string()));
+        res.push(try!(parse_rule(i + 1, table)));
+    }
+    Ok(res)
+}
+
+fn parse_rule(n: usize, table: &Table) -> Result<Rule, String> {
+    let mut name = None;
+    let mut cost_centre = "*".to_string();
+    let mut module = "*".to_string();
+    let mut limits = Vec::new();
+    for (key, value) in table {
+        let field = format!("rule {}: `{}`", n, key);
+        match key.as_str() {
+            "name" => name = Some(try!(parse_string(&field, value))),
+            "cost-centre" => cost_centre = try!(parse_string(&field, value)),
+            "module" => module = try!(parse_string(&field, value)),
+            _ => {
+                let measure = match MEASURES.iter().find(|m| m.key() == key) {
+                    Some(&measure) => measure,
+                    None => return Err(format!("rule {}: unknown key `{}`", n, key)),
+                };
+                limits.push((measure, try!(parse_limit(&field, measure, value))));
+            }
+        }
+    }
+    if limits.is_empty() {
+        let keys: Vec<&str> = MEASURES.iter().map(|m| m.key()).collect();
+        return Err(format!("rule {} has no limit, expected one of: {}", n, keys.join(", ")));
+    }
+    let name = name.unwrap_or(format!("{}.{}", module, cost_centre));
+    Ok(Rule { name: name, cost_centre: cost_centre, module: module, limits: limits })
+}
+
+fn parse_string(field: &str, value: &Value) -> Result<String, String> {
+    value.as_str().map(String::from).ok_or(format!("{} must be a string", field))
+}
+
+/// Percentages can be fractional, bytes and entries are whole numbers.
+fn parse_limit(field: &str, measure: Measure, value: &Value) -> Result<f64, String> {
+    let limit = match (measure, value) {
+        (Measure::IndividualBytes, &Value::Integer(i)) |
+        (Measure::InheritedBytes, &Value::Integer(i)) |
+        (Measure::Entries, &Value::Integer(i)) => i as f64,
+        (Measure::IndividualBytes, _) | (Measure::InheritedBytes, _) | (Measure::Entries, _) => {
+            return Err(format!("{} must be a whole number", field))
+        }
+        (_, &Value::Integer(i)) => i as f64,
+        (_, &Value::Float(f)) => f,
+        _ => return Err(format!("{} must be a percentage", field)),
+    };
+    if limit < 0.0 {
+        return Err(format!("{} must not be negative", field));
+    }
+    Ok(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::toml::Parser;
+
+    fn rules(content: &str) -> Result<Vec<Rule>, String> {
+        parse_rules(&Parser::new(content).parse().expect("invalid TOML"))
+    }
+
+    fn error(content: &str) -> String {
+        rules(content).err().expect("the rules are valid")
+    }
+
+    #[test]
+    fn rules_with_their_limits() {
+        let rules = rules("[[rule]]\n\
+                           name = \"parser\"\n\
+                           module = \"Parser.*\"\n\
+                           max-inherited-alloc = 20\n\
+                           max-individual-time = 2.5\n\
+                           [[rule]]\n\
+                           cost-centre = \"bump\"\n\
+                           max-inherited-bytes = 1000000\n\
+                           max-entries = 10")
+            .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name, "parser");
+        assert_eq!(rules[0].cost_centre, "*");
+        assert_eq!(rules[0].module, "Parser.*");
+        assert_eq!(rules[0].limits,
+                   vec![(Measure::IndividualTime, 2.5), (Measure::InheritedAlloc, 20.0)]);
+        // Named after the patterns by default.
+        assert_eq!(rules[1].name, "*.bump");
+        assert_eq!(rules[1].limits, vec![(Measure::Entries, 10.0), (Measure::InheritedBytes, 1000000.0)]);
+    }
+
+    #[test]
+    fn rules_must_be_tables_of_rule() {
+        assert_eq!(error(""), "no `[[rule]]` found");
+        assert_eq!(error("[rule]\nmax-entries = 1"), "`rule` must be a list of tables, written `[[rule]]`");
+        assert_eq!(error("rule = [1]"), "`rule` must be a list of tables, written `[[rule]]`");
+        assert_eq!(error("[[rules]]\nmax-entries = 1"), "unknown key `rules`");
+    }
+
+    #[test]
+    fn rule_fields_are_checked() {
+        assert_eq!(error("[[rule]]\nmax-entries = 1\n[[rule]]\nmax-time = 1"), "rule 2: unknown key `max-time`");
+        assert_eq!(error("[[rule]]\nmodule = \"Main\""),
+                   "rule 1 has no limit, expected one of: max-individual-time, max-individual-alloc, \
+                    max-inherited-time, max-inherited-alloc, max-individual-bytes, max-inherited-bytes, \
+                    max-entries");
+        assert_eq!(error("[[rule]]\nname = 1\nmax-entries = 1"), "rule 1: `name` must be a string");
+        assert_eq!(error("[[rule]]\nmax-entries = 1.5"), "rule 1: `max-entries` must be a whole number");
+        assert_eq!(error("[[rule]]\nmax-individual-bytes = 1e6"),
+                   "rule 1: `max-individual-bytes` must be a whole number");
+        assert_eq!(error("[[rule]]\nmax-inherited-time = \"5%\""),
+                   "rule 1: `max-inherited-time` must be a percentage");
+        assert_eq!(error("[[rule]]\nmax-inherited-time = -1"), "rule 1: `max-inherited-time` must not be negative");
+    }
+}
@@ -0,0 +1,375 @@
+extern crate rustbox;
+
+use self::rustbox::Key;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    CycleSort,
+    CycleView,
+    ToggleFold,
+    OpenSource,
+    TogglePreview,
+    NextTab,
+    PreviousTab,
+    Filter,
+    CollapseRecursion,
+    HideLibraries,
+    ExcludeCafs,
+    Left,
+    Right,
+    CycleBars,
+}
+
+pub const ACTIONS: &'static [Action] = &[
+    Action::Quit,
+    Action::Down,
+    Action::Up,
+    Action::PageDown,
+    Action::PageUp,
+    Action::Top,
+    Action::Bottom,
+    Action::CycleSort,
+    Action::CycleView,
+    Action::ToggleFold,
+    Action::OpenSource,
+    Action::TogglePreview,
+    Action::NextTab,
+    Action::PreviousTab,
+    Action::Filter,
+    Action::CollapseRecursion,
+    Action::HideLibraries,
+    Action::ExcludeCafs,
+    Action::Left,
+    Action::Right,
+    Action::CycleBars,
+];
+
+impl Action {
+    /// The name used in the config file.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::Quit => "quit",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::PageDown => "page-down",
+            Action::PageUp => "page-up",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::CycleSort => "sort",
+            Action::CycleView => "view",
+            Action::ToggleFold => "fold",
+            Action::OpenSource => "edit",
+            Action::TogglePreview => "preview",
+            Action::NextTab => "next-tab",
+            Action::PreviousTab => "previous-tab",
+            Action::Filter => "filter",
+            Action::CollapseRecursion => "recursion",
+            Action::HideLibraries => "hide",
+            Action::ExcludeCafs => "exclude-cafs",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::CycleBars => "bars",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|a| a.name() == name).cloned()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyStyle {
+    Vim,
+    Emacs,
+}
+
+/// A key, or keys pressed one after the other such as `g t`.
+pub type KeySeq = Vec<Key>;
+
+/// Which action each key sequence triggers. Built from a `KeyStyle` preset,
+/// on top of which single actions can be rebound.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<KeySeq>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::preset(KeyStyle::Vim)
+    }
+}
+
+impl Keymap {
+    pub fn preset(style: KeyStyle) -> Keymap {
+        let bindings = match style {
+            KeyStyle::Vim => vec![
+                (Action::Quit, vec![vec![Key::Char('q')]]),
+                (Action::Down, vec![vec![Key::Char('j')], vec![Key::Down]]),
+                (Action::Up, vec![vec![Key::Char('k')], vec![Key::Up]]),
+                (Action::PageDown, vec![vec![Key::Ctrl('d')], vec![Key::PageDown]]),
+                (Action::PageUp, vec![vec![Key::Ctrl('u')], vec![Key::PageUp]]),
+                (Action::Top, vec![vec![Key::Char('g'), Key::Char('g')], vec![Key::Home]]),
+                (Action::Bottom, vec![vec![Key::Char('G')], vec![Key::End]]),
+                (Action::CycleSort, vec![vec![Key::Char('s')]]),
+                (Action::CycleView, vec![vec![Key::Char('v')]]),
+                (Action::ToggleFold, vec![vec![Key::Char(' ')], vec![Key::Enter]]),
+                (Action::OpenSource, vec![vec![Key::Char('e')]]),
+                (Action::TogglePreview, vec![vec![Key::Char('p')]]),
+                (Action::NextTab, vec![vec![Key::Char('g'), Key::Char('t')]]),
+                (Action::PreviousTab, vec![vec![Key::Char('g'), Key::Char('T')]]),
+                (Action::Filter, vec![vec![Key::Char('/')]]),
+                (Action::CollapseRecursion, vec![vec![Key::Char('r')]]),
+                (Action::HideLibraries, vec![vec![Key::Char('h')]]),
+                (Action::ExcludeCafs, vec![vec![Key::Char('c')]]),
+                (Action::Left, vec![vec![Key::Left]]),
+                (Action::Right, vec![vec![Key::Right]]),
+                (Action::CycleBars, vec![vec![Key::Char('b')]]),
+            ],
+            // termbox doesn't report Meta, so M-v, M-< and M-> are left out.
+            KeyStyle::Emacs => vec![
+                (Action::Quit, vec![vec![Key::Char('q')], vec![Key::Ctrl('g')]]),
+                (Action::Down, vec![vec![Key::Ctrl('n')], vec![Key::Down]]),
+                (Action::Up, vec![vec![Key::Ctrl('p')], vec![Key::Up]]),
+                (Action::PageDown, vec![vec![Key::Ctrl('v')], vec![Key::PageDown]]),
+                (Action::PageUp, vec![vec![Key::PageUp]]),
+                (Action::Top, vec![vec![Key::Home]]),
+                (Action::Bottom, vec![vec![Key::End]]),
+                (Action::CycleSort, vec![vec![Key::Ctrl('s')]]),
+                (Action::CycleView, vec![vec![Key::Ctrl('o')]]),
+                (Action::ToggleFold, vec![vec![Key::Tab], vec![Key::Enter]]),
+                (Action::OpenSource, vec![vec![Key::Char('e')]]),
+                (Action::TogglePreview, vec![vec![Key::Char('p')]]),
+                (Action::NextTab, vec![vec![Key::Ctrl('x'), Key::Char('o')]]),
+                (Action::PreviousTab, vec![vec![Key::Ctrl('x'), Key::Char('O')]]),
+                (Action::Filter, vec![vec![Key::Char('/')]]),
+                (Action::CollapseRecursion, vec![vec![Key::Char('r')]]),
+                (Action::HideLibraries, vec![vec![Key::Char('h')]]),
+                (Action::ExcludeCafs, vec![vec![Key::Char('c')]]),
+                (Action::Left, vec![vec![Key::Ctrl('b')], vec![Key::Left]]),
+                (Action::Right, vec![vec![Key::Ctrl('f')], vec![Key::Right]]),
+                (Action::CycleBars, vec![vec![Key::Char('b')]]),
+            ],
+        };
+        Keymap { bindings: bindings }
+    }
+
+    /// Replaces the keys bound to `action`.
+    pub fn rebind(&mut self, action: Action, keys: Vec<KeySeq>) {
+        self.bindings.retain(|&(a, _)| a != action);
+        self.bindings.push((action, keys));
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<KeySeq> {
+        self.bindings.iter()
+            .filter(|&&(a, _)| a == action)
+            .flat_map(|&(_, ref keys)| keys.iter().cloned())
+            .collect()
+    }
+
+    /// Resolves the bindings into a lookup table, failing if the same keys
+    /// are bound to more than one action, or if a binding starts with the
+    /// keys of another one, which would then never trigger.
+    pub fn lookup_table(&self) -> Result<KeyTable, String> {
+        let mut table = HashMap::new();
+        let mut seen: Vec<(&KeySeq, Action)> = Vec::new();
+        for &(action, ref keys) in &self.bindings {
+            for seq in keys {
+                for &(other_seq, other) in &seen {
+                    if other_seq == seq && other != action {
+                        return Err(format!("key `{}` is bound to both `{}` and `{}`",
+                                           key_seq_name(seq), other.name(), action.name()));
+                    }
+                    let (short, long) = if other_seq.len() < seq.len() { (other_seq, seq) } else { (seq, other_seq) };
+                    if short.len() < long.len() && long.starts_with(short) {
+                        return Err(format!("key `{}` hides `{}`, so `{}` and `{}` can't both be bound to them",
+                                           key_seq_name(short), key_seq_name(long), other.name(), action.name()));
+                    }
+                }
+                seen.push((seq, action));
+                table.insert(seq.clone(), action);
+            }
+        }
+        Ok(KeyTable { table: table })
+    }
+}
+
+/// What the keys pressed so far amount to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Action(Action),
+    /// More keys are needed to complete a binding.
+    Prefix,
+    Unbound,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyTable {
+    table: HashMap<KeySeq, Action>,
+}
+
+impl KeyTable {
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(&action) = self.table.get(keys) {
+            return Lookup::Action(action);
+        }
+        if self.table.keys().any(|seq| seq.len() > keys.len() && seq.starts_with(keys)) {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+}
+
+/// Parses a key as written in the config file: a single character, `C-x`
+/// for Ctrl+x, or the name of a special key.
+pub fn parse_key(s: &str) -> Result<Key, String> {
+    let special = match s {
+        "Up" => Some(Key::Up),
+        "Down" => Some(Key::Down),
+        "Left" => Some(Key::Left),
+        "Right" => Some(Key::Right),
+        "PageUp" => Some(Key::PageUp),
+        "PageDown" => Some(Key::PageDown),
+        "Home" => Some(Key::Home),
+        "End" => Some(Key::End),
+        "Enter" => Some(Key::Enter),
+        "Tab" => Some(Key::Tab),
+        "Space" => Some(Key::Char(' ')),
+        "Esc" => Some(Key::Esc),
+        "Backspace" => Some(Key::Backspace),
+        "Delete" => Some(Key::Delete),
+        "Insert" => Some(Key::Insert),
+        _ => None,
+    };
+    if let Some(key) = special {
+        return Ok(key);
+    }
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() == 1 {
+        return Ok(Key::Char(chars[0]));
+    }
+    if chars.len() == 3 && s.starts_with("C-") && chars[2] >= 'a' && chars[2] <= 'z' {
+        return Ok(Key::Ctrl(chars[2]));
+    }
+    if s.starts_with('F') {
+        if let Ok(n) = s[1..].parse::<u32>() {
+            if n >= 1 && n <= 12 {
+                return Ok(Key::F(n));
+            }
+        }
+    }
+    Err(format!("unknown key `{}`", s))
+}
+
+/// Parses keys separated by spaces, e.g. `g t` or `C-x o`.
+pub fn parse_key_seq(s: &str) -> Result<KeySeq, String> {
+    let mut seq = Vec::new();
+    for key in s.split_whitespace() {
+        seq.push(try!(parse_key(key)));
+    }
+    if seq.is_empty() {
+        return Err("empty key".to_string());
+    }
+    Ok(seq)
+}
+
+pub fn key_name(key: &Key) -> String {
+    match *key {
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("C-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Unknown(n) => format!("<{}>", n),
+        other => format!("{:?}", other),
+    }
+}
+
+pub fn key_seq_name(seq: &[Key]) -> String {
+    let names: Vec<String> = seq.iter().map(key_name).collect();
+    names.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seq(s: &str) -> KeySeq {
+        parse_key_seq(s).unwrap()
+    }
+
+    #[test]
+    fn presets_have_no_conflicts() {
+        for style in &[KeyStyle::Vim, KeyStyle::Emacs] {
+            let keymap = Keymap::preset(*style);
+            let table = keymap.lookup_table().unwrap();
+            for action in ACTIONS {
+                assert!(!keymap.keys(*action).is_empty(), "{:?} {:?}", style, action);
+            }
+            assert_eq!(table.lookup(&[Key::Char('q')]), Lookup::Action(Action::Quit));
+        }
+    }
+
+    #[test]
+    fn sequences_are_looked_up_key_by_key() {
+        let table = Keymap::default().lookup_table().unwrap();
+        assert_eq!(table.lookup(&seq("g")), Lookup::Prefix);
+        assert_eq!(table.lookup(&seq("g t")), Lookup::Action(Action::NextTab));
+        assert_eq!(table.lookup(&seq("g g")), Lookup::Action(Action::Top));
+        assert_eq!(table.lookup(&seq("g x")), Lookup::Unbound);
+        assert_eq!(table.lookup(&seq("x")), Lookup::Unbound);
+    }
+
+    #[test]
+    fn rebinding_replaces_the_keys_of_an_action() {
+        let mut keymap = Keymap::default();
+        keymap.rebind(Action::Quit, vec![seq("C-c"), seq("Esc")]);
+        assert_eq!(keymap.keys(Action::Quit), vec![vec![Key::Ctrl('c')], vec![Key::Esc]]);
+        let table = keymap.lookup_table().unwrap();
+        assert_eq!(table.lookup(&seq("q")), Lookup::Unbound);
+        assert_eq!(table.lookup(&seq("Esc")), Lookup::Action(Action::Quit));
+    }
+
+    #[test]
+    fn the_same_keys_cannot_trigger_two_actions() {
+        let mut keymap = Keymap::default();
+        keymap.rebind(Action::Quit, vec![seq("j")]);
+        assert_eq!(keymap.lookup_table().unwrap_err(), "key `j` is bound to both `down` and `quit`");
+        // Binding the same keys twice to one action is fine.
+        keymap.rebind(Action::Quit, vec![seq("q"), seq("q")]);
+        assert!(keymap.lookup_table().is_ok());
+    }
+
+    #[test]
+    fn keys_cannot_be_the_prefix_of_other_keys() {
+        let mut keymap = Keymap::default();
+        keymap.rebind(Action::Quit, vec![seq("g")]);
+        assert_eq!(keymap.lookup_table().unwrap_err(),
+                   "key `g` hides `g g`, so `top` and `quit` can't both be bound to them");
+        let mut keymap = Keymap::default();
+        keymap.rebind(Action::Quit, vec![seq("s q")]);
+        assert_eq!(keymap.lookup_table().unwrap_err(),
+                   "key `s` hides `s q`, so `sort` and `quit` can't both be bound to them");
+    }
+
+    #[test]
+    fn key_names() {
+        for name in &["a", "G", "/", "C-x", "F1", "F12", "Up", "PageDown", "Enter", "Space", "Esc"] {
+            let key = parse_key(name).unwrap();
+            assert_eq!(key_name(&key), *name);
+        }
+        assert_eq!(parse_key("Space"), Ok(Key::Char(' ')));
+        assert_eq!(key_seq_name(&seq("C-x  o")), "C-x o");
+        assert_eq!(parse_key("F13"), Err("unknown key `F13`".to_string()));
+        assert_eq!(parse_key("C-X"), Err("unknown key `C-X`".to_string()));
+        assert_eq!(parse_key("Return"), Err("unknown key `Return`".to_string()));
+        assert_eq!(parse_key_seq(" "), Err("empty key".to_string()));
+    }
+}
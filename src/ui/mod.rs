@@ -1,95 +1,516 @@
 extern crate rustbox;
 extern crate ghcprof;
 
-use self::rustbox::{RustBox, Style, Color, Key};
-use std::cmp::{max};
+use self::rustbox::{RustBox, Style, Color, Key, InitOptions, InputMode, OutputMode, Mouse};
+use std::cmp::{max, min, Ordering};
+use std::path::Path;
+use self::keys::{Action, KeyTable, Lookup};
 use self::types::*;
 use self::style::*;
+use self::preview::{render_preview, Sources};
+use config::Config;
+use editor;
+use ghcprof::callpath::{call_path, display, follow, CallPath};
+use ghcprof::flame::layout;
+use ghcprof::metric::Metric;
 use ghcprof::parser::{Header, Summary, ExtendedSummary, ExtendedSummaryLine, RoseTree, SummaryLine, GHCProf};
+use ghcprof::rollup::{module_calls, ModuleCall};
+use ghcprof::transform::is_caf;
+use report::truncate;
 
+pub mod keys;
 pub mod types;
 pub mod style;
+pub mod preview;
 
 pub struct UI {
     ui: RustBox,
+    options: InitOptions,
 }
 
 impl UI {
-    pub fn new() -> Result<UI, UIError> {
-        match RustBox::init(Default::default()) {
-            Result::Ok(v) => Ok(UI { ui: v }),
-            Result::Err(_) => Err(UIError::UiInitialisationFailed),
+    pub fn new(depth: ColourDepth) -> Result<UI, UIError> {
+        let output_mode = match depth {
+            ColourDepth::Basic => OutputMode::Normal,
+            ColourDepth::Extended => OutputMode::EightBit,
+        };
+        let options = InitOptions {
+            input_mode: InputMode::EscMouse,
+            output_mode: output_mode,
+            ..Default::default()
+        };
+        match RustBox::init(options) {
+            Result::Ok(v) => Ok(UI { ui: v, options: options }),
+            Result::Err(e) => Err(UIError::UiInitialisationFailed(e)),
         }
     }
 
-    pub fn render_loop<'a>(&self, prof: GHCProf<'a>) {
-        let ref rustbox = self.ui;
-        let ref mut user_cursor = UserCursor::new(0,1);
-        let ref mut draw_cursor = Cursor::new(1,1);
-
-        let ctx = TuiContext {
-            ui: rustbox,
-            user_cursor: user_cursor,
-            draw_cursor: draw_cursor,
-        };
+    pub fn render_loop<'a>(self,
+                           mut tabs: Vec<Tab<'a>>,
+                           config: &Config,
+                           src_root: Option<&Path>)
+                           -> Result<(), UIError> {
+        // Only one RustBox can exist at a time, so it is dropped while an
+        // editor runs, then created again.
+        let mut rustbox = Some(self.ui);
+        let mut sources = Sources::new(src_root);
+        let mut message: Option<String> = None;
+        let mut current = 0;
+        let mut pending_keys: Vec<Key> = Vec::new();
+        let mut sync: Option<SyncTarget<'a>> = None;
+        // The query being typed, while the filter prompt is open.
+        let mut prompt: Option<String> = None;
+        let titles: Vec<String> = tabs.iter().map(|t| t.title()).collect();
 
         loop {
-            render(&ctx, &prof);
+            let outcome = {
+                let rustbox = rustbox.as_ref().unwrap();
+                let tab = &mut tabs[current];
+                tab.refresh();
+                let prof = tab.transformed.as_ref().map_or(&tab.prof, |t| &t.prof);
+                let mut ctx = TuiContext {
+                    ui: rustbox,
+                    user_cursor: &mut tab.user_cursor,
+                    draw_cursor: &mut tab.draw_cursor,
+                    palette: &tab.palette,
+                    viewport: &mut tab.viewport,
+                    settings: &mut tab.settings,
+                    folded: &mut tab.folded,
+                    filter: tab.filter.as_ref(),
+                    recursion: tab.transformed.as_ref().map(|t| &t.recursion),
+                    icicle: &mut tab.icicle,
+                };
 
-            let status_bar_position = ctx.ui.height() - 1;
-            let viewport = format!("({}, {})", ctx.ui.width(), ctx.ui.height());
+                let preview_height = if ctx.settings.preview { (ctx.ui.height() - 1) / 2 } else { 0 };
+                ctx.viewport.height = ctx.ui.height() - 1 - preview_height;
+                ctx.viewport.follow(ctx.user_cursor.y);
+                ctx.ui.clear();
+                let mut frame = render(&ctx, prof);
+                if let Some(y) = sync.take().and_then(|target| target.row(prof, &frame)) {
+                    // Draws again, now that we know where the cursor goes.
+                    ctx.user_cursor.y = y;
+                    ctx.viewport.follow(y);
+                    ctx.ui.clear();
+                    frame = render(&ctx, prof);
+                }
+                if preview_height > 1 {
+                    let top = ctx.viewport.height;
+                    render_preview(&ctx, &frame, prof, &mut sources, top, preview_height);
+                }
 
-            // Render the status-bar and the viewport
-            ctx.ui.print(0, status_bar_position, Style::empty(), Color::Black, Color::Green, viewport.as_str());
-            for i in viewport.len() .. ctx.ui.width() {
-                ctx.ui.print(i, status_bar_position, Style::empty(), Color::Black, Color::Green, " ");
-            }
+                let status_bar_position = ctx.ui.height() - 1;
+                let viewport = match (prompt.as_ref(), message.take()) {
+                    (Some(query), _) => format!("/{}", query),
+                    (None, Some(msg)) => msg,
+                    (None, None) => format!("{}({}, {})  view: {}  sort: {}{}{}{}{}{}",
+                                            tab_list(&titles, current),
+                                            ctx.ui.width(),
+                                            ctx.ui.height(),
+                                            ctx.settings.view.name(),
+                                            ctx.settings.sort.name(),
+                                            if ctx.settings.bars == Bars::Off {
+                                                String::new()
+                                            } else {
+                                                format!("  bars: {}", ctx.settings.bars.name())
+                                            },
+                                            if ctx.settings.collapse_recursion { "  recursion: collapsed" } else { "" },
+                                            if ctx.settings.hide_libraries { "  libraries: hidden" } else { "" },
+                                            if ctx.settings.exclude_cafs { "  cafs: excluded" } else { "" },
+                                            ctx.filter.map_or(String::new(), |f| format!("  filter: {}", f.query))),
+                };
 
-            // Render the current line number.
-            ctx.ui.print(ctx.ui.width() - 5
-                         , status_bar_position
-                         , Style::empty()
-                         , Color::Black
-                         , Color::Green
-                         , format!("{}", ctx.user_cursor.y).as_str()
-            );
-
-            rustbox.present();
-            match rustbox.poll_event(false) {
-                Ok(rustbox::Event::KeyEvent(key)) => {
-                    match key {
-                        Key::Ctrl('d') => {
-                            println!("scroll down");
-                        }
-                        Key::Ctrl('u') => {
-                            println!("scroll up");
+                // Render the status-bar and the viewport
+                ctx.ui.print(0, status_bar_position, Style::empty(), Color::Black, Color::Green, viewport.as_str());
+                for i in viewport.len() .. ctx.ui.width() {
+                    ctx.ui.print(i, status_bar_position, Style::empty(), Color::Black, Color::Green, " ");
+                }
+
+                // Render the current line number.
+                ctx.ui.print(ctx.ui.width() - 5
+                             , status_bar_position
+                             , Style::empty()
+                             , Color::Black
+                             , Color::Green
+                             , format!("{}", ctx.user_cursor.y).as_str()
+                );
+
+                rustbox.present();
+                let outcome = match rustbox.poll_event(false) {
+                    Ok(rustbox::Event::KeyEvent(key)) if prompt.is_some() => edit_prompt(&mut prompt, key),
+                    Ok(rustbox::Event::KeyEvent(key)) => {
+                        match resolve(&config.keys, &mut pending_keys, key) {
+                            Lookup::Action(Action::Quit) => break,
+                            Lookup::Action(Action::OpenSource) => {
+                                match frame.cost_centres.get(&ctx.user_cursor.y) {
+                                    Some(cc) => Outcome::Edit(*cc),
+                                    None => Outcome::Continue,
+                                }
+                            }
+                            Lookup::Action(Action::Filter) => {
+                                prompt = Some(ctx.filter.map_or(String::new(), |f| f.query.clone()));
+                                Outcome::Continue
+                            }
+                            Lookup::Action(Action::CollapseRecursion) => {
+                                // Keeps the cursor on the same call path, when it is still
+                                // there.
+                                sync = SyncTarget::of(prof, &frame, ctx.user_cursor.y);
+                                perform(&mut ctx, Action::CollapseRecursion, &frame);
+                                Outcome::Continue
+                            }
+                            Lookup::Action(action @ Action::HideLibraries) |
+                            Lookup::Action(action @ Action::ExcludeCafs) => {
+                                // These change the call paths, or the percentages, but not
+                                // the `no` of the cost centres left.
+                                sync = frame.nodes.get(&ctx.user_cursor.y).map(|row| SyncTarget::Node(row.no));
+                                perform(&mut ctx, action, &frame);
+                                Outcome::Continue
+                            }
+                            Lookup::Action(Action::NextTab) => Outcome::SwitchTab((current + 1) % titles.len()),
+                            Lookup::Action(Action::PreviousTab) => {
+                                Outcome::SwitchTab((current + titles.len() - 1) % titles.len())
+                            }
+                            Lookup::Action(action) => {
+                                perform(&mut ctx, action, &frame);
+                                Outcome::Continue
+                            }
+                            Lookup::Unbound => {
+                                match key {
+                                    Key::Char(c) if c >= '1' && c <= '9' => {
+                                        let n = c as usize - '1' as usize;
+                                        if n < titles.len() { Outcome::SwitchTab(n) } else { Outcome::Continue }
+                                    }
+                                    _ => Outcome::Continue,
+                                }
+                            }
+                            Lookup::Prefix => Outcome::Continue,
                         }
-                        Key::Char('q') => {
-                            break;
+                    }
+                    Ok(rustbox::Event::MouseEvent(mouse, x, y)) => {
+                        click(&mut ctx, &frame, mouse, x as usize, y as usize);
+                        Outcome::Continue
+                    }
+                    Err(e) => panic!("{}", e),
+                    _ => Outcome::Continue,
+                };
+                if let Outcome::SwitchTab(_) = outcome {
+                    sync = SyncTarget::of(prof, &frame, ctx.user_cursor.y);
+                }
+                outcome
+            };
+
+            match outcome {
+                Outcome::SwitchTab(n) => current = n,
+                Outcome::Filter(query) => {
+                    let tab = &mut tabs[current];
+                    if query.trim().is_empty() {
+                        tab.filter = None;
+                    } else {
+                        match Filter::new(&query, tab.shown()) {
+                            Ok(filter) => {
+                                tab.filter = Some(filter);
+                                tab.user_cursor.y = 1;
+                            }
+                            Err(e) => message = Some(format!("Invalid filter: {}", e)),
                         }
-                        Key::Down => {
-                            ctx.user_cursor.y += 1;
+                    }
+                }
+                Outcome::Edit(cc) => {
+                    match sources.locate(&cc) {
+                        Some(location) => {
+                            drop(rustbox.take());
+                            if let Err(e) = editor::open(&config.editor, &location) {
+                                message = Some(e);
+                            }
+                            rustbox = Some(try!(RustBox::init(self.options).map_err(UIError::UiInitialisationFailed)));
                         }
-                        Key::Up => {
-                            ctx.user_cursor.y = max(1, ctx.user_cursor.y - 1);
+                        None if src_root.is_none() => {
+                            message = Some(format!("No source location for {}, try --src-root", cc.cost_centre));
                         }
-                        _ => {}
+                        None => message = Some(format!("No source found for {} ({})", cc.cost_centre, cc.module)),
+                    }
+                }
+                Outcome::Continue => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the render loop does after handling an event.
+enum Outcome<'a> {
+    Continue,
+    Edit(CostCentreRef<'a>),
+    SwitchTab(usize),
+    /// Filters the current tab with the query, or clears the filter if
+    /// empty.
+    Filter(String),
+}
+
+/// Edits the query in the filter prompt: Enter applies it and Esc, or
+/// deleting past its start, closes the prompt.
+fn edit_prompt<'a>(prompt: &mut Option<String>, key: Key) -> Outcome<'a> {
+    match key {
+        Key::Enter => return Outcome::Filter(prompt.take().unwrap_or_default()),
+        Key::Esc => *prompt = None,
+        Key::Backspace => {
+            if prompt.as_mut().and_then(|query| query.pop()).is_none() {
+                *prompt = None;
+            }
+        }
+        Key::Char(c) => {
+            if let Some(ref mut query) = *prompt {
+                query.push(c);
+            }
+        }
+        _ => {}
+    }
+    Outcome::Continue
+}
+
+/// Adds `key` to the keys typed so far, clearing them once they complete a
+/// binding or can't lead to one.
+fn resolve(keys: &KeyTable, pending: &mut Vec<Key>, key: Key) -> Lookup {
+    pending.push(key);
+    let mut lookup = keys.lookup(pending);
+    if lookup == Lookup::Unbound && pending.len() > 1 {
+        // The sequence went nowhere: the last key may start a new one.
+        pending.clear();
+        pending.push(key);
+        lookup = keys.lookup(pending);
+    }
+    if lookup != Lookup::Prefix {
+        pending.clear();
+    }
+    lookup
+}
+
+/// The tabs for the status bar, the current one in brackets. Nothing when
+/// there is a single profile.
+fn tab_list(titles: &[String], current: usize) -> String {
+    if titles.len() < 2 {
+        return String::new();
+    }
+    let mut res = String::new();
+    for (i, title) in titles.iter().enumerate() {
+        if i == current {
+            res.push_str(&format!("[{}:{}] ", i + 1, title));
+        } else {
+            res.push_str(&format!(" {}:{}  ", i + 1, title));
+        }
+    }
+    res.push(' ');
+    res
+}
+
+/// Where the cursor was before switching tab, to put it on the same cost
+/// centre in the next one.
+enum SyncTarget<'a> {
+    CallPath(CallPath<'a>),
+    /// A row of the flat summary.
+    CostCentre(&'a str, &'a str),
+    /// A node of the tree, by `no`.
+    Node(u32),
+}
+
+impl<'a> SyncTarget<'a> {
+    fn of(prof: &GHCProf<'a>, frame: &Frame<'a>, y: usize) -> Option<SyncTarget<'a>> {
+        let ExtendedSummary(ref tree) = prof.extended_summary;
+        if let Some(row) = frame.nodes.get(&y) {
+            return call_path(tree, row.no).map(SyncTarget::CallPath);
+        }
+        frame.cost_centres.get(&y).map(|cc| SyncTarget::CostCentre(cc.cost_centre, cc.module))
+    }
+
+    /// The row of the target in `frame`, if the same call path exists in
+    /// `prof`. When part of it is folded, the closest visible ancestor.
+    fn row(&self, prof: &GHCProf<'a>, frame: &Frame<'a>) -> Option<usize> {
+        match *self {
+            SyncTarget::CallPath(ref path) => {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                let nodes = follow(tree, path);
+                if nodes.len() < path.len() {
+                    return None;
+                }
+                nodes.iter().rev().filter_map(|node| frame.row_of(node.value.no)).next()
+            }
+            SyncTarget::Node(no) => frame.row_of(no),
+            SyncTarget::CostCentre(cost_centre, module) => {
+                frame.cost_centres.iter()
+                    .filter(|&(y, cc)| {
+                        !frame.nodes.contains_key(y) && cc.cost_centre == cost_centre && cc.module == module
+                    })
+                    .map(|(&y, _)| y)
+                    .min()
+            }
+        }
+    }
+}
+
+fn perform(ctx: &mut TuiContext<RustBox>, action: Action, frame: &Frame) {
+    if ctx.settings.view == View::Icicle && navigate_icicle(ctx, action, frame) {
+        return;
+    }
+    let page = max(1, ctx.viewport.height / 2);
+    let y = ctx.user_cursor.y;
+    let last_row = frame.last_row;
+    ctx.user_cursor.y = match action {
+        Action::Down => min(last_row, y + 1),
+        Action::Up => max(1, y - 1),
+        Action::PageDown => min(last_row, y + page),
+        Action::PageUp => max(1, y.saturating_sub(page)),
+        Action::Top => 1,
+        Action::Bottom => last_row,
+        Action::CycleSort => {
+            ctx.settings.sort = ctx.settings.sort.next();
+            y
+        }
+        Action::CycleView => {
+            ctx.settings.view = ctx.settings.view.next();
+            // The icicle view starts on the node zoomed into, on the first row.
+            ctx.icicle.selected = None;
+            1
+        }
+        Action::ToggleFold => {
+            if let Some(row) = frame.nodes.get(&y) {
+                toggle_fold(ctx, row);
+            }
+            y
+        }
+        Action::TogglePreview => {
+            ctx.settings.preview = !ctx.settings.preview;
+            y
+        }
+        Action::CollapseRecursion => {
+            ctx.settings.collapse_recursion = !ctx.settings.collapse_recursion;
+            y
+        }
+        Action::HideLibraries => {
+            ctx.settings.hide_libraries = !ctx.settings.hide_libraries;
+            y
+        }
+        Action::ExcludeCafs => {
+            ctx.settings.exclude_cafs = !ctx.settings.exclude_cafs;
+            y
+        }
+        Action::Quit | Action::OpenSource | Action::NextTab | Action::PreviousTab | Action::Filter => y,
+        Action::CycleBars => {
+            ctx.settings.bars = ctx.settings.bars.next();
+            y
+        }
+        Action::Left | Action::Right => y,
+    };
+}
+
+/// Moves around the icicle view, returning whether `action` did: up to the
+/// caller, down to the most expensive callee, left and right along the row.
+/// Folding zooms into the selected node, or out of it when already zoomed
+/// into, and going to the top zooms out all the way.
+fn navigate_icicle(ctx: &mut TuiContext<RustBox>, action: Action, frame: &Frame) -> bool {
+    let current = match ctx.icicle.selected.and_then(|no| frame.rect_of(no)).or(frame.rects.first()) {
+        Some(r) => r,
+        None => return false,
+    };
+    let next = match action {
+        Action::Up => current.parent.and_then(|no| frame.rect_of(no)),
+        Action::Down => frame.rects.iter().filter(|r| r.parent == Some(current.no)).max_by_key(|r| r.width),
+        Action::Left => frame.rects.iter().filter(|r| r.y == current.y && r.x < current.x).max_by_key(|r| r.x),
+        Action::Right => frame.rects.iter().filter(|r| r.y == current.y && r.x > current.x).min_by_key(|r| r.x),
+        Action::ToggleFold => {
+            let zoomed = frame.rects.first().map(|r| r.no) == Some(current.no);
+            ctx.icicle.zoom = if zoomed { frame.zoom_parent } else { Some(current.no) };
+            ctx.icicle.selected = Some(current.no);
+            // The row of the selected node, once zoomed.
+            ctx.user_cursor.y = if zoomed && frame.zoom_parent.is_some() { 2 } else { 1 };
+            return true;
+        }
+        Action::Top => {
+            ctx.icicle.zoom = None;
+            ctx.icicle.selected = None;
+            ctx.user_cursor.y = 1;
+            return true;
+        }
+        // Rows are depths, there are no pages to move across.
+        Action::PageDown | Action::PageUp | Action::Bottom => return true,
+        _ => return false,
+    };
+    if let Some(r) = next {
+        ctx.icicle.selected = Some(r.no);
+        ctx.user_cursor.y = r.y;
+    }
+    true
+}
+
+const WHEEL_STEP: usize = 3;
+
+/// Left clicks select the row under the pointer, and also fold it when on
+/// its marker or sort when on a column header. The wheel scrolls the view,
+/// dragging the cursor along when it would go off screen.
+fn click(ctx: &mut TuiContext<RustBox>, frame: &Frame, mouse: Mouse, x: usize, y: usize) {
+    if y >= ctx.viewport.height {
+        return;
+    }
+    let y = y + ctx.viewport.scroll;
+    match mouse {
+        Mouse::Left if ctx.settings.view == View::Icicle => {
+            if let Some(r) = frame.rect_at(x, y) {
+                if ctx.icicle.selected == Some(r.no) {
+                    navigate_icicle(ctx, Action::ToggleFold, frame);
+                } else {
+                    ctx.icicle.selected = Some(r.no);
+                    ctx.user_cursor.y = r.y;
+                }
+            }
+        }
+        Mouse::Left => {
+            if let Some(sort) = frame.header_at(x, y) {
+                ctx.settings.sort = if ctx.settings.sort == sort { SortKey::Profile } else { sort };
+            } else if y >= 1 && y <= frame.last_row {
+                ctx.user_cursor.y = y;
+                if let Some(row) = frame.nodes.get(&y) {
+                    if row.fold_marker == Some(x) {
+                        toggle_fold(ctx, row);
                     }
                 }
-                Err(e) => panic!("{}", e),
-                _ => {}
             }
         }
+        Mouse::WheelUp => {
+            ctx.viewport.scroll = ctx.viewport.scroll.saturating_sub(WHEEL_STEP);
+            let bottom = ctx.viewport.scroll + ctx.viewport.height - 1;
+            ctx.user_cursor.y = min(ctx.user_cursor.y, bottom);
+        }
+        Mouse::WheelDown => {
+            let end = (frame.last_row + 1).saturating_sub(ctx.viewport.height);
+            ctx.viewport.scroll = min(end, ctx.viewport.scroll + WHEEL_STEP);
+            ctx.user_cursor.y = max(ctx.user_cursor.y, max(1, ctx.viewport.scroll));
+        }
+        Mouse::Right | Mouse::Middle | Mouse::Release => {}
+    }
+}
+
+fn toggle_fold(ctx: &mut TuiContext<RustBox>, row: &TreeRow) {
+    if row.fold_marker.is_some() && !ctx.folded.remove(&row.no) {
+        ctx.folded.insert(row.no);
     }
 }
 
-fn render<'a>(ctx: &TuiContext<RustBox>, prof: &GHCProf<'a>) {
-        render_header(&ctx, &prof.header);
-        let cursor = render_summary(&ctx, &prof.summary);
-        render_extended_summary(&ctx, cursor, &prof.extended_summary);
+/// Renders the current view, returning where things were drawn.
+fn render<'a>(ctx: &TuiContext<RustBox>, prof: &GHCProf<'a>) -> Frame<'a> {
+    let mut frame = Frame::default();
+    frame.last_row = match ctx.settings.view {
+        View::Full => {
+            let cursor = render_header(&ctx, &prof.header);
+            let cursor = render_summary(&ctx, &mut frame, cursor + 2, &prof.summary);
+            render_extended_summary(&ctx, &mut frame, cursor, &prof.extended_summary)
+        }
+        View::Tree | View::Cafs | View::Modules => render_extended_summary(&ctx, &mut frame, 0, &prof.extended_summary),
+        View::Summary => render_summary(&ctx, &mut frame, 1, &prof.summary) - 1,
+        View::ModuleCalls => render_module_calls(&ctx, &mut frame, 1, prof),
+        View::Icicle => render_icicle(&ctx, &mut frame, 1, &prof.extended_summary),
+    };
+    frame
 }
 
-fn render_header<'a>(ctx: &TuiContext<RustBox>, header: &Header<'a>) {
+fn render_header<'a>(ctx: &TuiContext<RustBox>, header: &Header<'a>) -> usize {
     normal_line(ctx, 1, 1, header.title);
     normal_line(ctx, header.title.len() / 4, 3, header.program);
     let ref tt = header.total_time;
@@ -102,11 +523,16 @@ fn render_header<'a>(ctx: &TuiContext<RustBox>, header: &Header<'a>) {
     let total_alloc = format!("total alloc = {} bytes  (excludes profiling overheads)",
                               ta.bytes);
     normal_line(ctx, 1, 5, &total_time);
-    normal_line(ctx, 1, 6, &total_alloc)
+    normal_line(ctx, 1, 6, &total_alloc);
+    6
 }
 
-fn render_summary<'a>(ctx: &TuiContext<RustBox>, &Summary(ref lines): &Summary<'a>) -> usize {
-    normal_line(ctx, 1, 8, "COST CENTRE");
+fn render_summary<'a>(ctx: &TuiContext<RustBox>,
+                      frame: &mut Frame<'a>,
+                      top: usize,
+                      &Summary(ref lines): &Summary<'a>)
+                      -> usize {
+    normal_line(ctx, 1, top, "COST CENTRE");
 
     // Computes all the slacks to render the summary in a tabulated style.
     let mut lines_mut = lines.clone();
@@ -118,19 +544,41 @@ fn render_summary<'a>(ctx: &TuiContext<RustBox>, &Summary(ref lines): &Summary<'
 
     lines_mut.sort_by(|a, b| (format!("{}", b.time_perc).len())
             .cmp(&format!("{}", a.time_perc).len()));
-    let longest_tm = lines_mut.get(0).map_or(1, |v| (format!("{}", v.time_perc).len()));
+    let longest_tm = lines_mut.get(0).map_or(1, |v| format!("{}", v.time_perc).len());
 
     // Render the rest of the summary header
-    normal_line(ctx, longest_cc + 2, 8, "MODULE");
-    normal_line(ctx, longest_cc + longest_mo + 4, 8, "%time");
+    normal_line(ctx, longest_cc + 2, top, "MODULE");
+    normal_line(ctx, longest_cc + longest_mo + 4, top, "%time");
     normal_line(ctx,
                 longest_cc + longest_mo + longest_tm + 6,
-                8,
+                top,
                 "%alloc");
+    let headers = [(1, "COST CENTRE", SortKey::Name),
+                   (longest_cc + longest_mo + 4, "%time", SortKey::IndividualTime),
+                   (longest_cc + longest_mo + longest_tm + 6, "%alloc", SortKey::IndividualAlloc)];
+    for &(x, title, sort) in headers.iter() {
+        frame.headers.push(HeaderCell { x: x, y: top, width: title.len(), sort: sort });
+    }
+
+    let mut idx = top + 2;
 
-    let mut idx = 10;
+    let mut sorted: Vec<&SummaryLine<'a>> = lines.iter()
+        .filter(|l| ctx.filter.map_or(true, |f| f.cost_centres.contains(&(l.cost_centre, l.module))))
+        .collect();
+    sort_summary_lines(&mut sorted, ctx.settings.sort);
+    let (cafs, sorted): (Vec<&SummaryLine<'a>>, Vec<&SummaryLine<'a>>) = if ctx.settings.separate_cafs {
+        sorted.into_iter().partition(|l| is_caf(l.cost_centre))
+    } else {
+        (Vec::new(), sorted)
+    };
+    // CAFs come last, under a title of their own, when listed apart.
+    let cafs_title = if cafs.is_empty() { None } else { Some(sorted.len()) };
 
-    for line in lines {
+    for (i, &line) in sorted.iter().chain(cafs.iter()).enumerate() {
+        if cafs_title == Some(i) {
+            normal_line(ctx, 1, idx + 1, "CAFs");
+            idx += 2;
+        }
         let &SummaryLine { time_perc: time, alloc_perc: memory, .. } = line;
         let tm_str = format!("{}", line.time_perc);
         let cc_len = line.cost_centre.len();
@@ -140,11 +588,12 @@ fn render_summary<'a>(ctx: &TuiContext<RustBox>, &Summary(ref lines): &Summary<'
         let tm_len = tm_str.len();
         let tm_slack = longest_tm - tm_len;
 
-        let time_temp = Temperature::from(time);
-        let memory_temp = Temperature::from(memory);
+        let time_temp = ctx.palette.temperature(time);
+        let memory_temp = ctx.palette.temperature(memory);
         let combined_temp = Temperature::append(&time_temp, &memory_temp);
 
         styled_line(ctx, 1, idx, &combined_temp, line.cost_centre);
+        frame.cost_centres.insert(idx, CostCentreRef::summary(line));
         styled_line(ctx,
                     cc_len + cc_slack + 2,
                     idx,
@@ -166,41 +615,405 @@ fn render_summary<'a>(ctx: &TuiContext<RustBox>, &Summary(ref lines): &Summary<'
     idx
 }
 
-fn render_extended_summary<'a>(ctx: &TuiContext<RustBox>, idx: usize, &ExtendedSummary(ref tree): &ExtendedSummary<'a>) {
-    // TODO: This needs to scale according to the size of the longest cost centre. Ditto for module etc.
-    normal_line(ctx, 1, idx + 2, "                                                                                                                          individual     inherited");
-    normal_line(ctx, 1, idx + 3, "COST CENTRE                                                    MODULE                                   no.     entries  %time %alloc   %time %alloc
-");
-    let cursor = idx + 4;
-    render_rose_tree(ctx, &mut Cursor::new(1, cursor), &tree)
+/// Which modules call into which, one call per row, see
+/// `ghcprof::rollup::module_calls`.
+fn render_module_calls<'a>(ctx: &TuiContext<RustBox>, frame: &mut Frame<'a>, top: usize, prof: &GHCProf<'a>) -> usize {
+    let mut calls = {
+        let ExtendedSummary(ref tree) = prof.extended_summary;
+        module_calls(tree)
+    };
+    sort_module_calls(&mut calls, ctx.settings.sort);
+    let caller_w = calls.iter().map(|c| c.caller.len()).fold("CALLER".len(), max);
+    let callee_w = calls.iter().map(|c| c.callee.len()).fold("CALLEE".len(), max);
+    let entries_x = caller_w + callee_w + 5;
+    let time_x = entries_x + 13;
+    let alloc_x = time_x + 7;
+
+    let headers = [(1, "CALLER", SortKey::Name),
+                   (caller_w + 3, "CALLEE", SortKey::Name),
+                   (entries_x + 4, "entries", SortKey::Entries),
+                   (time_x + 1, "%time", SortKey::InheritedTime),
+                   (alloc_x, "%alloc", SortKey::InheritedAlloc)];
+    for &(x, title, sort) in headers.iter() {
+        normal_line(ctx, x, top, title);
+        frame.headers.push(HeaderCell { x: x, y: top, width: title.len(), sort: sort });
+    }
+
+    let mut idx = top + 2;
+    for call in &calls {
+        let time_temp = ctx.palette.temperature(call.time_perc);
+        let alloc_temp = ctx.palette.temperature(call.alloc_perc);
+        let combined_temp = Temperature::append(&time_temp, &alloc_temp);
+        styled_line(ctx, 1, idx, &combined_temp, call.caller);
+        styled_line(ctx, caller_w + 3, idx, &combined_temp, call.callee);
+        normal_line(ctx, entries_x, idx, &format!("{:>11}", call.entries));
+        heat_line(ctx, time_x, idx, &time_temp, &format!("{:>6.1}", call.time_perc));
+        heat_line(ctx, alloc_x, idx, &alloc_temp, &format!("{:>6.1}", call.alloc_perc));
+        idx += 1;
+    }
+    idx - 1
+}
+
+/// Calls only have inherited costs, so individual sort keys fall back on
+/// them.
+fn sort_module_calls<'a>(calls: &mut Vec<ModuleCall<'a>>, key: SortKey) {
+    match key {
+        SortKey::Profile => {}
+        SortKey::Name => calls.sort_by(|a, b| (a.caller, a.callee).cmp(&(b.caller, b.callee))),
+        SortKey::Entries => calls.sort_by(|a, b| b.entries.cmp(&a.entries)),
+        SortKey::IndividualTime | SortKey::InheritedTime => calls.sort_by(|a, b| desc(a.time_perc, b.time_perc)),
+        SortKey::IndividualAlloc | SortKey::InheritedAlloc => {
+            calls.sort_by(|a, b| desc(a.alloc_perc, b.alloc_perc))
+        }
+    }
+}
+
+/// The flat summary only has individual costs, so inherited sort keys fall
+/// back on them.
+fn sort_summary_lines<'a>(lines: &mut Vec<&SummaryLine<'a>>, key: SortKey) {
+    match key {
+        SortKey::Profile | SortKey::Entries => {}
+        SortKey::Name => lines.sort_by(|a, b| a.cost_centre.cmp(b.cost_centre)),
+        SortKey::IndividualTime | SortKey::InheritedTime => {
+            lines.sort_by(|a, b| desc(a.time_perc, b.time_perc))
+        }
+        SortKey::IndividualAlloc | SortKey::InheritedAlloc => {
+            lines.sort_by(|a, b| desc(a.alloc_perc, b.alloc_perc))
+        }
+    }
+}
+
+fn sort_sub_forest<'t, 'a>(forest: &mut Vec<&'t RoseTree<ExtendedSummaryLine<'a>>>, key: SortKey) {
+    match key {
+        SortKey::Profile => {}
+        SortKey::Name => forest.sort_by(|a, b| a.value.cost_centre.cmp(b.value.cost_centre)),
+        SortKey::Entries => forest.sort_by(|a, b| b.value.entries.cmp(&a.value.entries)),
+        SortKey::IndividualTime => {
+            forest.sort_by(|a, b| desc(a.value.individual_time_perc, b.value.individual_time_perc))
+        }
+        SortKey::IndividualAlloc => {
+            forest.sort_by(|a, b| desc(a.value.individual_alloc_perc, b.value.individual_alloc_perc))
+        }
+        SortKey::InheritedTime => {
+            forest.sort_by(|a, b| desc(a.value.inherited_time_perc, b.value.inherited_time_perc))
+        }
+        SortKey::InheritedAlloc => {
+            forest.sort_by(|a, b| desc(a.value.inherited_alloc_perc, b.value.inherited_alloc_perc))
+        }
+    }
+}
+
+fn desc(a: f32, b: f32) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+/// Where each column of the cost centre tree starts.
+struct Layout {
+    cost_centre: usize,
+    columns: Vec<(Column, usize)>,
+    bars: Bars,
+    /// The largest percentage of each percentage column, for bars.
+    maxima: Vec<(Column, f32)>,
+}
+
+impl Layout {
+    fn new<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, columns: &[Column], bars: Bars, note_width: usize) -> Layout {
+        let cc_width = max("COST CENTRE".len(), widest_cost_centre(tree) + FOLD_MARKER_WIDTH + note_width) + 2;
+        let mo_width = max("MODULE".len(), widest_module(tree)) + 2;
+        let bar_width = if bars == Bars::Off { 0 } else { BAR_WIDTH + 1 };
+        let mut x = 1 + cc_width;
+        let mut res = Vec::new();
+        for col in columns {
+            res.push((*col, x));
+            x += match *col {
+                Column::Module => mo_width,
+                Column::No => 8,
+                Column::Entries => 12,
+                _ => 7 + bar_width,
+            };
+        }
+        let maxima = columns.iter()
+            .filter(|&&col| column_perc(col, &tree.value).is_some())
+            .map(|&col| (col, largest_perc(tree, col)))
+            .collect();
+        Layout { cost_centre: 1, columns: res, bars: bars, maxima: maxima }
+    }
+
+    /// What a full bar stands for in column `col`, on the row of `line`,
+    /// called by `parent`.
+    fn bar_scale<'a>(&self, col: Column, line: &ExtendedSummaryLine<'a>, parent: Option<&ExtendedSummaryLine<'a>>) -> f32 {
+        match self.bars {
+            Bars::Off => 0.0,
+            Bars::Max => self.maxima.iter().find(|&&(c, _)| c == col).map_or(0.0, |&(_, m)| m),
+            Bars::Parent => column_perc(inherited_column(col), parent.unwrap_or(line)).unwrap_or(0.0),
+        }
+    }
+}
+
+/// How many cells bars take, each split in eighths.
+const BAR_WIDTH: usize = 8;
+
+/// `fraction` of a bar, as blocks rising in eighths of a cell at its end.
+fn bar(fraction: f32) -> String {
+    const BLOCKS: [char; 8] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+    let eighths = (fraction.max(0.0).min(1.0) * (BAR_WIDTH * 8) as f32).round() as usize;
+    let mut res: String = ::std::iter::repeat('▇').take(eighths / 8).collect();
+    if eighths % 8 > 0 {
+        res.push(BLOCKS[eighths % 8]);
+    }
+    res
+}
+
+/// The percentage of `line` in column `col`, if it has one.
+fn column_perc<'a>(col: Column, line: &ExtendedSummaryLine<'a>) -> Option<f32> {
+    match col {
+        Column::Module | Column::No | Column::Entries => None,
+        Column::IndividualTime => Some(line.individual_time_perc),
+        Column::IndividualAlloc => Some(line.individual_alloc_perc),
+        Column::InheritedTime => Some(line.inherited_time_perc),
+        Column::InheritedAlloc => Some(line.inherited_alloc_perc),
+    }
+}
+
+/// The inherited column of the same metric as `col`.
+fn inherited_column(col: Column) -> Column {
+    match col {
+        Column::IndividualTime => Column::InheritedTime,
+        Column::IndividualAlloc => Column::InheritedAlloc,
+        other => other,
+    }
+}
+
+fn largest_perc<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, col: Column) -> f32 {
+    let own = column_perc(col, &tree.value).unwrap_or(0.0);
+    tree.sub_forest.iter().map(|t| largest_perc(t, col)).fold(own, f32::max)
+}
+
+fn widest_cost_centre<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> usize {
+    tree.sub_forest.iter().map(widest_cost_centre).fold(tree.depth + tree.value.cost_centre.len(), max)
+}
+
+fn widest_module<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> usize {
+    tree.sub_forest.iter().map(widest_module).fold(tree.value.module.len(), max)
+}
+
+/// The sort key matching a column of the tree, if any.
+fn column_sort_key(col: Column) -> Option<SortKey> {
+    match col {
+        Column::Module => None,
+        Column::No => Some(SortKey::Profile),
+        Column::Entries => Some(SortKey::Entries),
+        Column::IndividualTime => Some(SortKey::IndividualTime),
+        Column::IndividualAlloc => Some(SortKey::IndividualAlloc),
+        Column::InheritedTime => Some(SortKey::InheritedTime),
+        Column::InheritedAlloc => Some(SortKey::InheritedAlloc),
+    }
+}
+
+fn column_header(col: Column) -> &'static str {
+    match col {
+        Column::Module => "MODULE",
+        Column::No => "     no.",
+        Column::Entries => "     entries",
+        Column::IndividualTime | Column::InheritedTime => "  %time",
+        Column::IndividualAlloc | Column::InheritedAlloc => " %alloc",
+    }
+}
+
+fn render_extended_summary<'a>(ctx: &TuiContext<RustBox>,
+                               frame: &mut Frame<'a>,
+                               idx: usize,
+                               &ExtendedSummary(ref tree): &ExtendedSummary<'a>)
+                               -> usize {
+    let note_width = ctx.recursion
+        .and_then(|r| r.values().map(|&d| recursion_note(d).chars().count()).max())
+        .unwrap_or(0);
+    let layout = Layout::new(tree, &ctx.settings.columns, ctx.settings.bars, note_width);
+
+    let individual = layout.columns.iter().find(|&&(c, _)| c == Column::IndividualTime || c == Column::IndividualAlloc);
+    let inherited = layout.columns.iter().find(|&&(c, _)| c == Column::InheritedTime || c == Column::InheritedAlloc);
+    if let Some(&(_, x)) = individual {
+        normal_line(ctx, x + 2, idx + 2, "individual");
+    }
+    if let Some(&(_, x)) = inherited {
+        normal_line(ctx, x + 2, idx + 2, "inherited");
+    }
+    normal_line(ctx, layout.cost_centre, idx + 3, "COST CENTRE");
+    frame.headers.push(HeaderCell { x: layout.cost_centre, y: idx + 3, width: 11, sort: SortKey::Name });
+    for &(col, x) in &layout.columns {
+        let title = column_header(col);
+        normal_line(ctx, x, idx + 3, title);
+        if let Some(sort) = column_sort_key(col) {
+            frame.headers.push(HeaderCell { x: x, y: idx + 3, width: title.len(), sort: sort });
+        }
+    }
+
+    let mut cursor = Cursor::new(1, idx + 4);
+    render_rose_tree(ctx, frame, &layout, &mut cursor, &tree, None);
+    cursor.y
 }
 
+/// Room for the fold marker drawn before each cost centre.
+const FOLD_MARKER_WIDTH: usize = 2;
 
-fn render_rose_tree<'a>(ctx: &TuiContext<RustBox>, cursor: &mut Cursor, tree: &RoseTree<ExtendedSummaryLine<'a>>) {
+
+fn render_rose_tree<'a>(ctx: &TuiContext<RustBox>,
+                        frame: &mut Frame<'a>,
+                        layout: &Layout,
+                        cursor: &mut Cursor,
+                        tree: &RoseTree<ExtendedSummaryLine<'a>>,
+                        parent: Option<&ExtendedSummaryLine<'a>>) {
+    if ctx.filter.map_or(false, |f| !f.shown.contains(&tree.value.no)) {
+        return;
+    }
     cursor.y += 1;
     cursor.x = tree.depth + 1;
-    render_extended_summary_line(ctx, cursor, &tree.value);
-    for t in &tree.sub_forest {
-        render_rose_tree(ctx, cursor, &t)
+    let folded = ctx.folded.contains(&tree.value.no);
+    let fold_marker = if tree.sub_forest.is_empty() {
+        None
+    } else {
+        normal_line(ctx, cursor.x, cursor.y, if folded { "+" } else { "-" });
+        Some(cursor.x)
+    };
+    frame.nodes.insert(cursor.y, TreeRow { no: tree.value.no, fold_marker: fold_marker });
+    frame.cost_centres.insert(cursor.y, CostCentreRef::tree(&tree.value));
+    cursor.x += FOLD_MARKER_WIDTH;
+    render_extended_summary_line(ctx, layout, cursor, &tree.value, parent);
+    if ctx.filter.map_or(false, |f| f.selected.contains(&tree.value.no)) {
+        match_line(ctx, cursor.x, cursor.y, tree.value.cost_centre);
+    }
+    if let Some(&depth) = ctx.recursion.and_then(|r| r.get(&tree.value.no)) {
+        normal_line(ctx, cursor.x + tree.value.cost_centre.len(), cursor.y, &recursion_note(depth));
+    }
+    if folded {
+        return;
+    }
+    let mut sub_forest: Vec<&RoseTree<ExtendedSummaryLine<'a>>> = tree.sub_forest.iter().collect();
+    sort_sub_forest(&mut sub_forest, ctx.settings.sort);
+    for t in sub_forest {
+        render_rose_tree(ctx, frame, layout, cursor, &t, Some(&tree.value))
   }
 }
 
-fn render_extended_summary_line<'a>(ctx: &TuiContext<RustBox>, cursor: &Cursor, line: &ExtendedSummaryLine<'a>) {
-    let cost_centre_len = line.cost_centre.len();
-    let module_len      = line.module.len();
-    let no              = format!("{}", line.no);
-    let entries         = format!("{}", line.entries);
-    let ind_time        = format!("{}", line.individual_time_perc);
-    let ind_alloc       = format!("{}", line.individual_alloc_perc);
-    let inh_time        = format!("{}", line.inherited_time_perc);
-    let inh_alloc       = format!("{}", line.inherited_alloc_perc);
+/// Follows a cost centre into which `depth` nested recursive calls were
+/// collapsed.
+fn recursion_note(depth: usize) -> String {
+    format!(" ×{}", depth)
+}
+
+fn render_extended_summary_line<'a>(ctx: &TuiContext<RustBox>,
+                                    layout: &Layout,
+                                    cursor: &Cursor,
+                                    line: &ExtendedSummaryLine<'a>,
+                                    parent: Option<&ExtendedSummaryLine<'a>>) {
     normal_line(ctx, cursor.x, cursor.y, line.cost_centre);
-    // TODO: All hardcoded for now
-    normal_line(ctx, 64, cursor.y, line.module);
-    normal_line(ctx, 105, cursor.y, &no);
-    normal_line(ctx, 113, cursor.y, &entries);
-    heat_line(ctx, 122, cursor.y, &Temperature::from(line.individual_time_perc), &ind_time);
-    heat_line(ctx, 128, cursor.y, &Temperature::from(line.individual_alloc_perc), &ind_alloc);
-    heat_line(ctx, 137, cursor.y, &Temperature::from(line.inherited_time_perc), &inh_time);
-    heat_line(ctx, 143, cursor.y, &Temperature::from(line.inherited_alloc_perc), &inh_alloc);
+    for &(col, x) in &layout.columns {
+        match col {
+            Column::Module => normal_line(ctx, x, cursor.y, line.module),
+            Column::No => normal_line(ctx, x, cursor.y, &format!("{:>8}", line.no)),
+            Column::Entries => normal_line(ctx, x, cursor.y, &format!("{:>12}", line.entries)),
+            _ => {
+                let perc = column_perc(col, line).unwrap_or(0.0);
+                perc_cell(ctx, x, cursor.y, perc);
+                let scale = layout.bar_scale(col, line, parent);
+                if scale > 0.0 {
+                    let temp = ctx.palette.temperature(perc);
+                    heat_line(ctx, x + 8, cursor.y, &temp, &bar(perc / scale));
+                }
+            }
+        }
+    }
+}
+
+fn perc_cell(ctx: &TuiContext<RustBox>, x: usize, y: usize, perc: f32) {
+    heat_line(ctx, x, y, &ctx.palette.temperature(perc), &format!("{:>7.1}", perc));
+}
+
+/// The tree as an icicle chart, a flame graph upside down filling the width
+/// of the terminal: each node is as wide as its inherited cost, coloured by
+/// its individual one, with its callees on the row below. Sizes are in
+/// alloc when sorting by alloc, in time otherwise. The call path and the
+/// costs of the selected node follow.
+fn render_icicle<'a>(ctx: &TuiContext<RustBox>,
+                     frame: &mut Frame<'a>,
+                     top: usize,
+                     &ExtendedSummary(ref tree): &ExtendedSummary<'a>)
+                     -> usize {
+    let metric = match ctx.settings.sort {
+        SortKey::IndividualAlloc | SortKey::InheritedAlloc => Metric::Alloc,
+        _ => Metric::Time,
+    };
+    let root = ctx.icicle.zoom.and_then(|no| find_node(tree, no)).unwrap_or(tree);
+    frame.zoom_parent = parent_of(tree, root.value.no);
+    let width = max(1, ctx.ui.width().saturating_sub(2));
+    let columns = |perc: f64| (perc * width as f64 / 100.0).round() as usize;
+    let rects = layout(root, metric, 100.0 / width as f64);
+    let selected = ctx.icicle.selected
+        .and_then(|no| rects.iter().find(|r| r.node.value.no == no))
+        .unwrap_or(&rects[0])
+        .node;
+
+    // The `no` of the nodes drawn above the current one.
+    let mut parents: Vec<u32> = Vec::new();
+    let mut last_row = top;
+    for r in &rects {
+        let ref line = r.node.value;
+        parents.truncate(r.depth);
+        let (x, end) = (columns(r.start), columns(r.start + r.width));
+        if end > x {
+            let y = top + r.depth;
+            // A single column only has room for the border.
+            let label = match end - x {
+                1 => "▕".to_string(),
+                w => format!("{:<w$}▕", truncate(line.cost_centre, w - 1), w = w - 1),
+            };
+            let temp = ctx.palette.temperature(line.individual_perc(metric));
+            block_line(ctx, 1 + x, y, &temp, line.no == selected.value.no, &label);
+            frame.rects.push(IcicleRect {
+                no: line.no,
+                parent: parents.last().cloned(),
+                x: 1 + x,
+                y: y,
+                width: end - x,
+            });
+            if line.no == selected.value.no {
+                frame.nodes.insert(y, TreeRow { no: line.no, fold_marker: None });
+                frame.cost_centres.insert(y, CostCentreRef::tree(line));
+            }
+            last_row = max(last_row, y);
+        }
+        parents.push(line.no);
+    }
+
+    let ref line = selected.value;
+    let path = call_path(tree, line.no).map_or(String::new(), |p| display(&p));
+    text_line(ctx, 1, last_row + 2, &path);
+    text_line(ctx,
+              1,
+              last_row + 3,
+              &format!("entries: {}  individual: {:.1}% time {:.1}% alloc  \
+                        inherited: {:.1}% time {:.1}% alloc  sized by {}{}",
+                       line.entries,
+                       line.individual_time_perc,
+                       line.individual_alloc_perc,
+                       line.inherited_time_perc,
+                       line.inherited_alloc_perc,
+                       metric.name(),
+                       if root.value.no == tree.value.no { "" } else { ", zoomed in" }));
+    last_row + 3
+}
+
+fn find_node<'t, 'a>(tree: &'t RoseTree<ExtendedSummaryLine<'a>>, no: u32) -> Option<&'t RoseTree<ExtendedSummaryLine<'a>>> {
+    if tree.value.no == no {
+        return Some(tree);
+    }
+    tree.sub_forest.iter().filter_map(|t| find_node(t, no)).next()
+}
+
+/// The `no` of the caller of node `no`, none for the root.
+fn parent_of<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>, no: u32) -> Option<u32> {
+    if tree.sub_forest.iter().any(|t| t.value.no == no) {
+        return Some(tree.value.no);
+    }
+    tree.sub_forest.iter().filter_map(|t| parent_of(t, no)).next()
 }
@@ -0,0 +1,132 @@
+extern crate rustbox;
+extern crate ghcprof;
+
+use self::rustbox::{RustBox, Color};
+use ghcprof::parser::{ExtendedSummary, GHCProf};
+use ghcprof::source::{binding_costs, definitions, locate, Location};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use ui::types::{CostCentreRef, Frame, TuiContext};
+
+/// The source files of the profiled program, each read the first time it
+/// is needed.
+pub struct Sources<'r> {
+    pub root: Option<&'r Path>,
+    files: HashMap<PathBuf, Option<String>>,
+}
+
+impl<'r> Sources<'r> {
+    pub fn new(root: Option<&'r Path>) -> Sources<'r> {
+        Sources { root: root, files: HashMap::new() }
+    }
+
+    pub fn locate(&self, cc: &CostCentreRef) -> Option<Location> {
+        locate(self.root, cc.cost_centre, cc.module, cc.src)
+    }
+
+    pub fn contents(&mut self, file: &Path) -> Option<&str> {
+        self.files.entry(file.to_path_buf())
+            .or_insert_with(|| {
+                let mut contents = String::new();
+                File::open(file).and_then(|mut f| f.read_to_string(&mut contents)).ok().map(|_| contents)
+            })
+            .as_ref()
+            .map(|c| c.as_str())
+    }
+}
+
+/// Room for the costs of a definition, e.g. ` 12.3% 45.6% `.
+const COSTS_WIDTH: usize = 14;
+const LINE_NO_WIDTH: usize = 5;
+
+/// Shows the source of the cost centre under the cursor in the `height`
+/// rows of the screen starting at `top`, the first of which is a title bar.
+/// The cost centre's span is highlighted and each top-level definition of
+/// the module is annotated with its individual %time and %alloc.
+pub fn render_preview<'a>(ctx: &TuiContext<RustBox>,
+                          frame: &Frame<'a>,
+                          prof: &GHCProf<'a>,
+                          sources: &mut Sources,
+                          top: usize,
+                          height: usize) {
+    let width = ctx.ui.width();
+    let cc = frame.cost_centres.get(&ctx.user_cursor.y);
+    let location = cc.and_then(|cc| sources.locate(cc));
+    let title = match (cc, location.as_ref()) {
+        (None, _) => " no cost centre selected".to_string(),
+        (Some(cc), None) => format!(" {}: no source location{}",
+                                    cc.cost_centre,
+                                    if sources.root.is_none() { ", try --src-root" } else { "" }),
+        (Some(cc), Some(loc)) => format!(" {}  {}:{}", cc.cost_centre, loc.file.display(), loc.line),
+    };
+    title_bar(ctx, top, width, &title);
+
+    let (cc, location) = match (cc, location) {
+        (Some(cc), Some(location)) => (cc, location),
+        _ => return,
+    };
+    let costs = {
+        let ExtendedSummary(ref tree) = prof.extended_summary;
+        binding_costs(tree, cc.module)
+    };
+    let contents = match sources.contents(&location.file) {
+        Some(contents) => contents,
+        None => {
+            print(ctx, 1, top + 1, Color::Red, &format!("cannot read {}", location.file.display()));
+            return;
+        }
+    };
+    let annotations: HashMap<usize, (f32, f32)> = definitions(contents)
+        .into_iter()
+        .filter_map(|(line, name)| costs.get(name).map(|&c| (line, c)))
+        .collect();
+
+    // Keeps a third of the pane above the span, for context.
+    let rows = height - 1;
+    let first = location.line.saturating_sub(rows / 3).max(1);
+    let (end_line, end_col) = location.end.unwrap_or((location.line, usize::max_value()));
+    let code_x = COSTS_WIDTH + LINE_NO_WIDTH + 2;
+    for (row, (idx, text)) in contents.lines().enumerate().skip(first - 1).take(rows).enumerate() {
+        let y = top + 1 + row;
+        let line_no = idx + 1;
+        if let Some(&(time, alloc)) = annotations.get(&line_no) {
+            let time_temp = ctx.palette.temperature(time);
+            let alloc_temp = ctx.palette.temperature(alloc);
+            print(ctx, 1, y, ctx.palette.colour(&time_temp), &format!("{:>5.1}%", time));
+            print(ctx, 8, y, ctx.palette.colour(&alloc_temp), &format!("{:>5.1}%", alloc));
+        }
+        print(ctx, COSTS_WIDTH, y, ctx.palette.theme.foreground, &format!("{:>5} ", line_no));
+
+        let text = text.replace('\t', "        ");
+        let in_span = line_no >= location.line && line_no <= end_line;
+        if !in_span {
+            print(ctx, code_x, y, ctx.palette.theme.foreground, &text);
+            continue;
+        }
+        // Columns count from 1 and the span includes its last column.
+        let from = if line_no == location.line { location.column.saturating_sub(1) } else { 0 };
+        let to = if line_no == end_line { end_col } else { usize::max_value() };
+        for (col, c) in text.chars().enumerate() {
+            let highlighted = col >= from && col < to;
+            let (fg, bg) = if highlighted {
+                (ctx.palette.contrast(), ctx.palette.highlight())
+            } else {
+                (ctx.palette.theme.foreground, ctx.palette.background())
+            };
+            if code_x + col < width {
+                ctx.ui.print_char(code_x + col, y, rustbox::RB_NORMAL, fg, bg, c);
+            }
+        }
+    }
+}
+
+fn title_bar(ctx: &TuiContext<RustBox>, y: usize, width: usize, title: &str) {
+    let bar: String = title.chars().chain(::std::iter::repeat(' ')).take(width).collect();
+    ctx.ui.print(0, y, rustbox::RB_BOLD, Color::Black, Color::Cyan, &bar);
+}
+
+fn print(ctx: &TuiContext<RustBox>, x: usize, y: usize, fg: Color, text: &str) {
+    ctx.ui.print(x, y, rustbox::RB_NORMAL, fg, ctx.palette.background(), text);
+}
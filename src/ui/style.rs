@@ -1,74 +1,352 @@
 extern crate rustbox;
+extern crate ghcprof;
 
 use self::rustbox::{Style, RustBox, Color};
+use ghcprof::parser::{ExtendedSummary, ExtendedSummaryLine, RoseTree, GHCProf};
 use ui::types::TuiContext;
 
 pub fn normal_line(ctx: &TuiContext<RustBox>, x: usize, y: usize, str: &str) {
-    ctx.ui.print(x,
-                 y,
-                 rustbox::RB_NORMAL,
-                 ctx.user_cursor.fg(x, y),
-                 ctx.user_cursor.bg(x, y),
-                 str);
+    print(ctx,
+          x,
+          y,
+          rustbox::RB_NORMAL,
+          ctx.user_cursor.fg_or(ctx.palette.theme.foreground, x, y),
+          ctx.user_cursor.bg_or(ctx.palette.background(), x, y),
+          str);
 }
 
 pub fn heat_line(ctx: &TuiContext<RustBox>, x: usize, y: usize, temp: &Temperature, str: &str) {
-    ctx.ui.print(x,
-                 y,
-                 temp.to_style(),
-                 ctx.user_cursor.fg_or(temp.to_colour(), x, y),
-                 ctx.user_cursor.bg(x, y),
-                 str);
+    print(ctx,
+          x,
+          y,
+          ctx.palette.style(temp),
+          ctx.user_cursor.fg_or(ctx.palette.colour(temp), x, y),
+          ctx.user_cursor.bg_or(ctx.palette.background(), x, y),
+          str);
 }
 
 pub fn styled_line(ctx: &TuiContext<RustBox>, x: usize, y: usize, temp: &Temperature, str: &str) {
-    ctx.ui.print(x,
-                 y,
-                 temp.to_style(),
-                 ctx.user_cursor.fg(x, y),
-                 ctx.user_cursor.bg(x, y),
-                 str);
+    print(ctx,
+          x,
+          y,
+          ctx.palette.style(temp),
+          ctx.user_cursor.fg_or(ctx.palette.theme.foreground, x, y),
+          ctx.user_cursor.bg_or(ctx.palette.background(), x, y),
+          str);
 }
 
-pub enum Temperature {
-    Hot,
-    Warm,
-    Cold,
+/// A cost centre selected by the filter, among the ancestors shown to
+/// place it in the tree.
+pub fn match_line(ctx: &TuiContext<RustBox>, x: usize, y: usize, str: &str) {
+    print(ctx,
+          x,
+          y,
+          rustbox::RB_BOLD | rustbox::RB_UNDERLINE,
+          ctx.user_cursor.fg_or(ctx.palette.theme.foreground, x, y),
+          ctx.user_cursor.bg_or(ctx.palette.background(), x, y),
+          str);
 }
 
-impl Temperature {
-    pub fn from(temp_c: f32) -> Temperature {
-        match temp_c {
-            0.0...10.0 => Temperature::Cold,
-            10.1...50.0 => Temperature::Warm,
-            _ => Temperature::Hot,
-        }
+/// Text of the icicle view, which has no row cursor.
+pub fn text_line(ctx: &TuiContext<RustBox>, x: usize, y: usize, str: &str) {
+    print(ctx, x, y, rustbox::RB_NORMAL, ctx.palette.theme.foreground, ctx.palette.background(), str);
+}
+
+/// A node of the icicle view, on the colour of its temperature, reversed
+/// when selected.
+pub fn block_line(ctx: &TuiContext<RustBox>, x: usize, y: usize, temp: &Temperature, selected: bool, str: &str) {
+    let style = if selected { rustbox::RB_REVERSE | rustbox::RB_BOLD } else { ctx.palette.style(temp) };
+    print(ctx, x, y, style, ctx.palette.contrast(), ctx.palette.colour(temp), str);
+}
+
+/// Prints at row `y` of the rendered profile, if it is scrolled into view.
+fn print(ctx: &TuiContext<RustBox>, x: usize, y: usize, style: Style, fg: Color, bg: Color, str: &str) {
+    if ctx.viewport.contains(y) {
+        ctx.ui.print(x, y - ctx.viewport.scroll, style, fg, bg, str);
     }
+}
+
+/// How hot a cost is, as a level in `0 .. levels`, where the last level is
+/// the hottest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Temperature {
+    pub level: usize,
+    pub levels: usize,
+}
 
+impl Temperature {
     // Monoidal append.
     pub fn append(t1: &Temperature, t2: &Temperature) -> Temperature {
-        match (t1, t2) {
-            (&Temperature::Hot, _) => Temperature::Hot,
-            (_, &Temperature::Hot) => Temperature::Hot,
-            (&Temperature::Warm, _) => Temperature::Warm,
-            (_, &Temperature::Warm) => Temperature::Warm,
-            _ => Temperature::Cold,
+        if t1.level >= t2.level { *t1 } else { *t2 }
+    }
+
+    pub fn is_cold(&self) -> bool {
+        self.level == 0
+    }
+
+    pub fn is_hot(&self) -> bool {
+        self.levels > 1 && self.level == self.levels - 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeatMode {
+    /// Thresholds are percentages of the whole program.
+    Absolute,
+    /// Thresholds are percentages of the most expensive cost centre.
+    Relative,
+}
+
+/// Maps a cost percentage to a `Temperature`. A cost lands on the level
+/// matching the number of thresholds it exceeds, so the thresholds leave
+/// no gaps between levels.
+#[derive(Debug, Clone)]
+pub struct HeatScale {
+    pub mode: HeatMode,
+    pub thresholds: Vec<f32>,
+    reference: f32,
+}
+
+impl Default for HeatScale {
+    fn default() -> HeatScale {
+        HeatScale::new(HeatMode::Absolute, vec![10.0, 50.0])
+    }
+}
+
+impl HeatScale {
+    pub fn new(mode: HeatMode, thresholds: Vec<f32>) -> HeatScale {
+        HeatScale { mode: mode, thresholds: thresholds, reference: 100.0 }
+    }
+
+    /// Uses the most expensive individual cost in `prof` as the reference
+    /// for relative thresholds.
+    pub fn calibrate<'a>(&mut self, prof: &GHCProf<'a>) {
+        let ExtendedSummary(ref tree) = prof.extended_summary;
+        let max = max_individual(tree);
+        self.reference = if max > 0.0 { max } else { 100.0 };
+    }
+
+    pub fn levels(&self) -> usize {
+        self.thresholds.len() + 1
+    }
+
+    pub fn temperature(&self, perc: f32) -> Temperature {
+        let value = match self.mode {
+            HeatMode::Absolute => perc,
+            HeatMode::Relative => perc * 100.0 / self.reference,
+        };
+        Temperature {
+            level: self.thresholds.iter().filter(|&&t| value > t).count(),
+            levels: self.levels(),
+        }
+    }
+}
+
+fn max_individual<'a>(tree: &RoseTree<ExtendedSummaryLine<'a>>) -> f32 {
+    let own = tree.value.individual_time_perc.max(tree.value.individual_alloc_perc);
+    tree.sub_forest.iter().map(max_individual).fold(own, f32::max)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColourDepth {
+    /// The 8 colours every terminal supports.
+    Basic,
+    /// The xterm 256 colours palette.
+    Extended,
+}
+
+impl ColourDepth {
+    pub fn detect() -> ColourDepth {
+        match ::std::env::var("TERM") {
+            Ok(ref term) if term.contains("256color") => ColourDepth::Extended,
+            _ => ColourDepth::Basic,
+        }
+    }
+}
+
+/// The colours used to paint temperatures, coldest first. `basic` is used
+/// on 8 colours terminals, `gradient` (xterm colour indices) on 256 colours
+/// ones. Levels are spread evenly across whichever list is in use.
+///
+/// termbox has no "default" colour in 256 colours mode, hence the explicit
+/// `background` for it.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub foreground: Color,
+    pub background: u16,
+    pub basic: Vec<Color>,
+    pub gradient: Vec<u16>,
+}
+
+pub const THEMES: &'static [&'static str] = &["dark", "light", "colour-blind", "colour-blind-light"];
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::named("dark").unwrap()
+    }
+}
+
+impl Theme {
+    pub fn named(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme {
+                foreground: Color::White,
+                background: 0,
+                basic: vec![Color::White, Color::Yellow, Color::Red],
+                gradient: vec![252, 229, 226, 220, 214, 208, 202, 196],
+            }),
+            "light" => Some(Theme {
+                foreground: Color::Black,
+                background: 15,
+                basic: vec![Color::Black, Color::Blue, Color::Red],
+                gradient: vec![240, 25, 91, 127, 161, 160],
+            }),
+            // Blue to orange, distinguishable with the common forms of colour blindness.
+            "colour-blind" => Some(Theme {
+                foreground: Color::White,
+                background: 0,
+                basic: vec![Color::White, Color::Cyan, Color::Yellow],
+                gradient: vec![252, 117, 75, 33, 214, 208, 202],
+            }),
+            "colour-blind-light" => Some(Theme {
+                foreground: Color::Black,
+                background: 15,
+                basic: vec![Color::Black, Color::Blue, Color::Magenta],
+                gradient: vec![240, 25, 19, 94, 130, 166],
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Everything needed to paint a cost: how to bucket it and how to colour it.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub scale: HeatScale,
+    pub theme: Theme,
+    pub depth: ColourDepth,
+}
+
+impl Palette {
+    pub fn new(scale: HeatScale, theme: Theme, depth: ColourDepth) -> Palette {
+        Palette { scale: scale, theme: theme, depth: depth }
+    }
+
+    pub fn temperature(&self, perc: f32) -> Temperature {
+        self.scale.temperature(perc)
+    }
+
+    pub fn colour(&self, temp: &Temperature) -> Color {
+        match self.depth {
+            ColourDepth::Basic => pick(&self.theme.basic, temp).map_or(self.theme.foreground, |&c| c),
+            ColourDepth::Extended => {
+                pick(&self.theme.gradient, temp).map_or(self.theme.foreground, |&b| Color::Byte(b))
+            }
+        }
+    }
+
+    pub fn background(&self) -> Color {
+        match self.depth {
+            ColourDepth::Basic => Color::Default,
+            ColourDepth::Extended => Color::Byte(self.theme.background),
+        }
+    }
+
+    /// The colour of text drawn on that of a temperature.
+    pub fn contrast(&self) -> Color {
+        match self.depth {
+            ColourDepth::Basic => {
+                match self.theme.foreground {
+                    Color::Black => Color::White,
+                    _ => Color::Black,
+                }
+            }
+            ColourDepth::Extended => Color::Byte(self.theme.background),
         }
     }
 
-    fn to_style(&self) -> Style {
-        match *self {
-            Temperature::Cold => rustbox::RB_NORMAL,
-            Temperature::Warm => rustbox::RB_NORMAL,
-            Temperature::Hot => rustbox::RB_BOLD,
+    /// The background of highlighted text, drawn in `contrast`: the middle
+    /// of the theme's basic colours, which stands out without reading as hot.
+    pub fn highlight(&self) -> Color {
+        self.theme.basic[self.theme.basic.len() / 2]
+    }
+
+    pub fn style(&self, temp: &Temperature) -> Style {
+        if temp.is_hot() { rustbox::RB_BOLD } else { rustbox::RB_NORMAL }
+    }
+
+    /// The ANSI escape sequence matching `colour` and `style`, for output
+    /// which doesn't go through rustbox. Cold costs are left uncoloured.
+    pub fn ansi(&self, temp: &Temperature) -> String {
+        if temp.is_cold() {
+            return String::new();
+        }
+        let bold = if temp.is_hot() { "1;" } else { "" };
+        match self.colour(temp) {
+            Color::Byte(b) => format!("\x1b[{}38;5;{}m", bold, b),
+            Color::Default => format!("\x1b[{}39m", bold),
+            c => format!("\x1b[{}{}m", bold, 30 + c.as_256color()),
         }
     }
 
-    fn to_colour(&self) -> Color {
-        match *self {
-            Temperature::Cold => Color::White,
-            Temperature::Warm => Color::Yellow,
-            Temperature::Hot => Color::Red,
+    /// The CSS colour of `temp`, as it looks in a 256 colours terminal, for
+    /// output which isn't a terminal at all. Cold costs are left uncoloured.
+    pub fn css(&self, temp: &Temperature) -> Option<String> {
+        if temp.is_cold() {
+            return None;
         }
+        pick(&self.theme.gradient, temp).map(|&b| css_colour(b))
+    }
+
+    pub fn css_foreground(&self) -> String {
+        match self.theme.foreground {
+            Color::Default => "inherit".to_string(),
+            c => css_colour(c.as_256color()),
+        }
+    }
+
+    pub fn css_background(&self) -> String {
+        css_colour(self.theme.background)
     }
 }
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new(HeatScale::default(), Theme::default(), ColourDepth::detect())
+    }
+}
+
+fn pick<'t, T>(colours: &'t [T], temp: &Temperature) -> Option<&'t T> {
+    if colours.is_empty() {
+        return None;
+    }
+    if temp.levels <= 1 {
+        return colours.first();
+    }
+    colours.get(temp.level * (colours.len() - 1) / (temp.levels - 1))
+}
+
+pub const ANSI_RESET: &'static str = "\x1b[0m";
+
+/// The standard RGB values of the xterm 256 colours.
+fn css_colour(colour: u16) -> String {
+    const SYSTEM: [(u8, u8, u8); 16] = [
+        (0, 0, 0), (128, 0, 0), (0, 128, 0), (128, 128, 0),
+        (0, 0, 128), (128, 0, 128), (0, 128, 128), (192, 192, 192),
+        (128, 128, 128), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+        (0, 0, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+    ];
+    let cube = |c: u16| if c == 0 { 0 } else { (55 + 40 * c) as u8 };
+    let (r, g, b) = if colour < 16 {
+        SYSTEM[colour as usize]
+    } else if colour < 232 {
+        let c = colour - 16;
+        (cube(c / 36), cube(c / 6 % 6), cube(c % 6))
+    } else if colour < 256 {
+        let grey = (8 + 10 * (colour - 232)) as u8;
+        (grey, grey, grey)
+    } else {
+        (255, 255, 255)
+    };
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
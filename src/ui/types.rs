@@ -1,10 +1,18 @@
 
 extern crate rustbox;
-use self::rustbox::Color;
+use self::rustbox::{Color, InitError};
+use ghcprof::parser::{ExtendedSummary, ExtendedSummaryLine, GHCProf, RoseTree, SummaryLine};
+use ghcprof::query::Query;
+use ghcprof::rollup::by_module;
+use ghcprof::merge::summarise;
+use ghcprof::transform::{collapse_recursion, exclude_cafs, group_cafs, hide, HideRules};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use ui::style::Palette;
 
 #[derive(Debug)]
 pub enum UIError {
-    UiInitialisationFailed,
+    UiInitialisationFailed(InitError),
 }
 
 pub struct Cursor {
@@ -28,10 +36,6 @@ impl UserCursor {
         UserCursor { x: x, y: y }
     }
 
-    pub fn bg(&self, x: usize, y: usize) -> Color {
-        self.bg_or(Color::Default, x, y)
-    }
-
     pub fn bg_or(&self, default: Color, _: usize, y: usize) -> Color {
         let UserCursor { x: _, y: uy } = *self;
         if y == uy {
@@ -49,14 +53,533 @@ impl UserCursor {
             return default;
         }
     }
-
-    pub fn fg(&self, x: usize, y: usize) -> Color {
-        self.fg_or(Color::White, x, y)
-    }
 }
 
 pub struct TuiContext<'a, A: 'a> {
     pub ui: &'a A,
     pub user_cursor: &'a mut UserCursor,
     pub draw_cursor: &'a mut Cursor,
+    pub palette: &'a Palette,
+    pub viewport: &'a mut Viewport,
+    pub settings: &'a mut ViewSettings,
+    /// The `no` of the cost centres whose children are hidden.
+    pub folded: &'a mut HashSet<u32>,
+    pub filter: Option<&'a Filter<'a>>,
+    /// How deep the recursion collapsed into each node went, by `no`.
+    pub recursion: Option<&'a HashMap<u32, usize>>,
+    pub icicle: &'a mut Icicle,
+}
+
+/// A profile open in the TUI, with its own cursor, folding and settings.
+pub struct Tab<'a> {
+    pub path: PathBuf,
+    pub prof: GHCProf<'a>,
+    pub palette: Palette,
+    pub user_cursor: UserCursor,
+    pub draw_cursor: Cursor,
+    pub viewport: Viewport,
+    pub folded: HashSet<u32>,
+    pub settings: ViewSettings,
+    pub filter: Option<Filter<'a>>,
+    pub icicle: Icicle,
+    /// The profile with the transforms enabled in `settings` applied, if
+    /// any are.
+    pub transformed: Option<Transformed<'a>>,
+}
+
+impl<'a> Tab<'a> {
+    pub fn new(path: PathBuf, prof: GHCProf<'a>, palette: Palette, settings: ViewSettings) -> Tab<'a> {
+        Tab {
+            path: path,
+            prof: prof,
+            palette: palette,
+            user_cursor: UserCursor::new(0, 1),
+            draw_cursor: Cursor::new(1, 1),
+            viewport: Viewport::new(0),
+            folded: HashSet::new(),
+            settings: settings,
+            filter: None,
+            icicle: Icicle::default(),
+            transformed: None,
+        }
+    }
+
+    /// The profile as shown.
+    pub fn shown(&self) -> &GHCProf<'a> {
+        self.transformed.as_ref().map_or(&self.prof, |t| &t.prof)
+    }
+
+    /// Applies the transforms enabled in the settings, when they changed,
+    /// and the filter again to the result.
+    pub fn refresh(&mut self) {
+        let enabled = Transforms::of(&self.settings);
+        if self.transformed.as_ref().map_or(Transforms::default(), |t| t.enabled) == enabled {
+            return;
+        }
+        self.transformed = if enabled == Transforms::default() {
+            None
+        } else {
+            Some(Transformed::new(&self.prof, enabled, &self.settings.hidden))
+        };
+        let query = self.filter.take().map(|f| f.query);
+        if let Some(query) = query {
+            self.filter = Filter::new(&query, self.shown()).ok();
+        }
+    }
+
+    /// The file name, as shown in the status bar.
+    pub fn title(&self) -> String {
+        self.path.file_name().unwrap_or(self.path.as_os_str()).to_string_lossy().into_owned()
+    }
+}
+
+/// The transforms of the profile enabled in the settings.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Transforms {
+    caf_view: bool,
+    module_view: bool,
+    exclude_cafs: bool,
+    hide_libraries: bool,
+    collapse_recursion: bool,
+}
+
+impl Transforms {
+    /// The CAF view shows the costs of CAFs, even from libraries, and the
+    /// module view has no recursion.
+    fn of(settings: &ViewSettings) -> Transforms {
+        let caf_view = settings.view == View::Cafs;
+        let module_view = settings.view == View::Modules;
+        Transforms {
+            caf_view: caf_view,
+            module_view: module_view,
+            exclude_cafs: settings.exclude_cafs && !caf_view,
+            hide_libraries: settings.hide_libraries && !caf_view,
+            collapse_recursion: settings.collapse_recursion && !module_view,
+        }
+    }
+}
+
+/// A profile with the transforms of `ghcprof::transform` enabled in the
+/// settings applied: CAFs grouped by module or left out of the
+/// percentages, the cost centres of libraries hidden, recursive calls
+/// collapsed and costs rolled up by module.
+pub struct Transformed<'a> {
+    pub prof: GHCProf<'a>,
+    pub recursion: HashMap<u32, usize>,
+    enabled: Transforms,
+}
+
+impl<'a> Transformed<'a> {
+    pub fn new(prof: &GHCProf<'a>, enabled: Transforms, hidden: &HideRules) -> Transformed<'a> {
+        let mut prof = prof.clone();
+        if enabled.caf_view {
+            let cafs = {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                group_cafs(tree)
+            };
+            prof.extended_summary = ExtendedSummary(cafs);
+        }
+        if enabled.exclude_cafs {
+            let excluded = {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                exclude_cafs(tree)
+            };
+            prof.extended_summary = ExtendedSummary(excluded);
+        }
+        if enabled.hide_libraries {
+            let hidden = {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                hide(tree, hidden)
+            };
+            prof.extended_summary = ExtendedSummary(hidden);
+        }
+        if enabled.exclude_cafs || enabled.hide_libraries {
+            // The costs moved between cost centres.
+            let ExtendedSummary(ref tree) = prof.extended_summary;
+            prof.summary = summarise(tree);
+        }
+        if enabled.module_view {
+            let modules = {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                by_module(tree)
+            };
+            prof.extended_summary = ExtendedSummary(modules);
+        }
+        let mut recursion = HashMap::new();
+        if enabled.collapse_recursion {
+            let collapsed = {
+                let ExtendedSummary(ref tree) = prof.extended_summary;
+                collapse_recursion(tree)
+            };
+            prof.extended_summary = ExtendedSummary(collapsed.tree);
+            recursion = collapsed.recursion;
+        }
+        Transformed {
+            prof: prof,
+            recursion: recursion,
+            enabled: enabled,
+        }
+    }
+}
+
+/// The nodes of the tree selected by a query, see `ghcprof::query`. Only
+/// they and their ancestors are shown, and only their cost centres in the
+/// flat summary.
+pub struct Filter<'a> {
+    pub query: String,
+    pub selected: HashSet<u32>,
+    pub shown: HashSet<u32>,
+    pub cost_centres: HashSet<(&'a str, &'a str)>,
+}
+
+impl<'a> Filter<'a> {
+    pub fn new(query: &str, prof: &GHCProf<'a>) -> Result<Filter<'a>, String> {
+        let parsed: Query = try!(query.parse());
+        let ExtendedSummary(ref tree) = prof.extended_summary;
+        let nodes = parsed.select(tree);
+        let mut filter = Filter {
+            query: query.trim().to_string(),
+            selected: nodes.iter().map(|t| t.value.no).collect(),
+            shown: HashSet::new(),
+            cost_centres: nodes.iter().map(|t| (t.value.cost_centre, t.value.module)).collect(),
+        };
+        filter.add_shown(tree);
+        Ok(filter)
+    }
+
+    /// Adds the selected nodes of `tree` and their ancestors to `shown`,
+    /// returning whether there were any.
+    fn add_shown(&mut self, tree: &RoseTree<ExtendedSummaryLine<'a>>) -> bool {
+        let mut show = self.selected.contains(&tree.value.no);
+        for t in &tree.sub_forest {
+            show = self.add_shown(t) || show;
+        }
+        if show {
+            self.shown.insert(tree.value.no);
+        }
+        show
+    }
+}
+
+/// Which parts of the profile are on screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    /// Header, flat summary and cost centre tree, as in the .prof file.
+    Full,
+    Tree,
+    Summary,
+    /// The CAFs of the tree grouped by module, see
+    /// `ghcprof::transform::group_cafs`.
+    Cafs,
+    /// The costs rolled up by module, see `ghcprof::rollup::by_module`.
+    Modules,
+    /// Which modules call into which.
+    ModuleCalls,
+    /// The tree as an icicle chart, one row per depth.
+    Icicle,
+}
+
+pub const VIEWS: &'static [View] = &[
+    View::Full,
+    View::Tree,
+    View::Summary,
+    View::Cafs,
+    View::Modules,
+    View::ModuleCalls,
+    View::Icicle,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    /// The order of the .prof file.
+    Profile,
+    Name,
+    Entries,
+    IndividualTime,
+    IndividualAlloc,
+    InheritedTime,
+    InheritedAlloc,
+}
+
+pub const SORT_KEYS: &'static [SortKey] = &[
+    SortKey::Profile,
+    SortKey::Name,
+    SortKey::Entries,
+    SortKey::IndividualTime,
+    SortKey::IndividualAlloc,
+    SortKey::InheritedTime,
+    SortKey::InheritedAlloc,
+];
+
+/// The columns of the cost centre tree, besides the cost centre itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Module,
+    No,
+    Entries,
+    IndividualTime,
+    IndividualAlloc,
+    InheritedTime,
+    InheritedAlloc,
+}
+
+pub const COLUMNS: &'static [Column] = &[
+    Column::Module,
+    Column::No,
+    Column::Entries,
+    Column::IndividualTime,
+    Column::IndividualAlloc,
+    Column::InheritedTime,
+    Column::InheritedAlloc,
+];
+
+/// The bars drawn next to the percentages of the tree, and what a full
+/// bar stands for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bars {
+    Off,
+    /// The largest percentage of the column.
+    Max,
+    /// The inherited cost of the caller.
+    Parent,
+}
+
+pub const BARS: &'static [Bars] = &[Bars::Off, Bars::Max, Bars::Parent];
+
+/// Gives each variant of an enum the name used for it in the config file.
+pub trait Named: Copy + 'static {
+    fn name(&self) -> &'static str;
+    fn all() -> &'static [Self];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::all().iter().find(|v| v.name() == name).cloned()
+    }
+
+    /// The variant after this one, wrapping around.
+    fn next(&self) -> Self {
+        let all = Self::all();
+        let idx = all.iter().position(|v| v.name() == self.name()).unwrap_or(0);
+        all[(idx + 1) % all.len()]
+    }
+}
+
+impl Named for View {
+    fn name(&self) -> &'static str {
+        match *self {
+            View::Full => "full",
+            View::Tree => "tree",
+            View::Summary => "summary",
+            View::Cafs => "cafs",
+            View::Modules => "modules",
+            View::ModuleCalls => "calls",
+            View::Icicle => "icicle",
+        }
+    }
+
+    fn all() -> &'static [View] {
+        VIEWS
+    }
+}
+
+impl Named for SortKey {
+    fn name(&self) -> &'static str {
+        match *self {
+            SortKey::Profile => "profile",
+            SortKey::Name => "name",
+            SortKey::Entries => "entries",
+            SortKey::IndividualTime => "individual-time",
+            SortKey::IndividualAlloc => "individual-alloc",
+            SortKey::InheritedTime => "inherited-time",
+            SortKey::InheritedAlloc => "inherited-alloc",
+        }
+    }
+
+    fn all() -> &'static [SortKey] {
+        SORT_KEYS
+    }
+}
+
+impl Named for Column {
+    fn name(&self) -> &'static str {
+        match *self {
+            Column::Module => "module",
+            Column::No => "no",
+            Column::Entries => "entries",
+            Column::IndividualTime => "individual-time",
+            Column::IndividualAlloc => "individual-alloc",
+            Column::InheritedTime => "inherited-time",
+            Column::InheritedAlloc => "inherited-alloc",
+        }
+    }
+
+    fn all() -> &'static [Column] {
+        COLUMNS
+    }
+}
+
+impl Named for Bars {
+    fn name(&self) -> &'static str {
+        match *self {
+            Bars::Off => "off",
+            Bars::Max => "max",
+            Bars::Parent => "parent",
+        }
+    }
+
+    fn all() -> &'static [Bars] {
+        BARS
+    }
+}
+
+/// What the user chose to look at, and how.
+#[derive(Debug, Clone)]
+pub struct ViewSettings {
+    pub view: View,
+    pub sort: SortKey,
+    pub columns: Vec<Column>,
+    pub bars: Bars,
+    /// Whether the source of the selected cost centre is shown below.
+    pub preview: bool,
+    /// Whether recursive calls are merged into the outermost one.
+    pub collapse_recursion: bool,
+    /// Whether the cost centres `hidden` matches are left out of the tree.
+    pub hide_libraries: bool,
+    pub hidden: HideRules,
+    /// Whether the costs of CAFs themselves are left out of the percentages.
+    pub exclude_cafs: bool,
+    /// Whether CAFs are listed apart in the flat summary.
+    pub separate_cafs: bool,
+}
+
+impl Default for ViewSettings {
+    fn default() -> ViewSettings {
+        ViewSettings {
+            view: View::Full,
+            sort: SortKey::Profile,
+            columns: COLUMNS.to_vec(),
+            bars: Bars::Off,
+            preview: false,
+            collapse_recursion: false,
+            hide_libraries: false,
+            hidden: HideRules {
+                hide: ["GHC.*", "Data.*", "Control.*", "*.CAF", "*.CAF:*", "*\\*"].iter().map(|p| p.to_string()).collect(),
+                keep: Vec::new(),
+            },
+            exclude_cafs: false,
+            separate_cafs: false,
+        }
+    }
+}
+
+/// The rows of the rendered profile which fit on screen, the last row of
+/// the terminal being taken by the status bar.
+pub struct Viewport {
+    pub scroll: usize,
+    pub height: usize,
+}
+
+impl Viewport {
+    pub fn new(height: usize) -> Viewport {
+        Viewport { scroll: 0, height: height }
+    }
+
+    pub fn contains(&self, y: usize) -> bool {
+        y >= self.scroll && y < self.scroll + self.height
+    }
+
+    /// Scrolls just enough for `y` to be visible.
+    pub fn follow(&mut self, y: usize) {
+        if y < self.scroll {
+            self.scroll = y;
+        } else if y >= self.scroll + self.height {
+            self.scroll = y + 1 - self.height;
+        }
+    }
+}
+
+/// Which node of the icicle view is zoomed into, filling the width of the
+/// terminal, and which one is selected, by `no`. The root of the tree when
+/// unset, or when no longer drawn.
+#[derive(Debug, Default)]
+pub struct Icicle {
+    pub zoom: Option<u32>,
+    pub selected: Option<u32>,
+}
+
+/// Where things ended up in the last rendered frame, in rows of the
+/// rendered profile, so that clicks and the cursor can be mapped back to it.
+#[derive(Default)]
+pub struct Frame<'p> {
+    pub last_row: usize,
+    pub nodes: HashMap<usize, TreeRow>,
+    pub cost_centres: HashMap<usize, CostCentreRef<'p>>,
+    pub headers: Vec<HeaderCell>,
+    /// The nodes of the icicle view, the one zoomed into first.
+    pub rects: Vec<IcicleRect>,
+    /// The caller of the node zoomed into, to zoom out to.
+    pub zoom_parent: Option<u32>,
+}
+
+/// A cost centre of the tree, with the column of its fold marker if it has
+/// children.
+pub struct TreeRow {
+    pub no: u32,
+    pub fold_marker: Option<usize>,
+}
+
+/// What is needed to find the source of the cost centre on a row, be it
+/// from the flat summary or the tree.
+#[derive(Debug, Clone, Copy)]
+pub struct CostCentreRef<'p> {
+    pub cost_centre: &'p str,
+    pub module: &'p str,
+    pub src: Option<&'p str>,
+}
+
+impl<'p> CostCentreRef<'p> {
+    pub fn summary(line: &SummaryLine<'p>) -> CostCentreRef<'p> {
+        CostCentreRef { cost_centre: line.cost_centre, module: line.module, src: line.src }
+    }
+
+    pub fn tree(line: &ExtendedSummaryLine<'p>) -> CostCentreRef<'p> {
+        CostCentreRef { cost_centre: line.cost_centre, module: line.module, src: line.src }
+    }
+}
+
+/// A node drawn in the icicle view, from column `x` to `x + width`.
+pub struct IcicleRect {
+    pub no: u32,
+    /// The node drawn above, if any.
+    pub parent: Option<u32>,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+}
+
+/// A column header which sorts by `sort` when clicked.
+pub struct HeaderCell {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub sort: SortKey,
+}
+
+impl<'p> Frame<'p> {
+    pub fn row_of(&self, no: u32) -> Option<usize> {
+        self.nodes.iter().find(|&(_, row)| row.no == no).map(|(&y, _)| y)
+    }
+
+    pub fn rect_of(&self, no: u32) -> Option<&IcicleRect> {
+        self.rects.iter().find(|r| r.no == no)
+    }
+
+    pub fn rect_at(&self, x: usize, y: usize) -> Option<&IcicleRect> {
+        self.rects.iter().find(|r| r.y == y && x >= r.x && x < r.x + r.width)
+    }
+
+    pub fn header_at(&self, x: usize, y: usize) -> Option<SortKey> {
+        self.headers.iter()
+            .find(|h| h.y == y && x >= h.x && x < h.x + h.width)
+            .map(|h| h.sort)
+    }
 }